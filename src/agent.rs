@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Instructions added to the initial prompt in agent mode
+pub const AGENT_INSTRUCTIONS: &str = "You are working towards a goal given by the user, one step at a time. In \
+    each step, first say in a sentence or two what you will do next and why, then call one of the functions. Look \
+    at its result before planning the next step. Once the goal is reached, or if it can't be reached with the \
+    available functions, answer with the result without calling a function. If the user declines to run a \
+    function, don't try it again.";
+
+/// Settings of agent mode, started with /agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentConfig {
+    // Most tools the agent can run towards a goal, after which it has to answer with what it has
+    pub max_steps: u32,
+    // Ask before continuing after every this many steps, even for auto-approved tools. 0 never asks for tools that
+    // are auto-approved.
+    pub checkpoint_every: u32,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            max_steps: 10,
+            checkpoint_every: 3,
+        }
+    }
+}
+
+impl AgentConfig {
+    /// Whether to ask the user before taking a step, counting from 1
+    pub fn is_checkpoint(&self, step: usize) -> bool {
+        let every = self.checkpoint_every as usize;
+        every > 0 && step > 1 && (step - 1).is_multiple_of(every)
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::api::{ChatGPTClient, ChatLogEntry, Message, Rating, SessionFile};
+use crate::sampling::SamplingParams;
+
+const SUGGESTION_REQUEST: &str = "Below is the system prompt of an assistant, followed by exchanges with it that \
+    the user rated and annotated. Suggest concrete improvements to the system prompt that would get more answers \
+    like the ones rated well and fewer like the ones rated badly, paying attention to the notes. Finish with the \
+    improved system prompt in full.\n\n";
+
+// Annotated exchanges sent to the model for each prompt, from the first sessions given
+const MAX_EXCHANGES: usize = 20;
+// Messages and responses are cut to this many characters when sent to the model
+const MAX_EXCERPT_CHARS: usize = 600;
+
+// Exchanges of the sessions started with one persona, or with the initial prompt for None
+#[derive(Default)]
+struct PromptUse<'a> {
+    sessions: usize,
+    exchanges: usize,
+    annotated: Vec<&'a ChatLogEntry>,
+}
+
+// The start of a text, cut to a number of characters
+fn excerpt(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Gather the ratings and notes of the exchanges in saved sessions by the persona they were started with, and
+/// have the model suggest improvements to the prompts of the personas that were rated, most used first. Sessions
+/// are given newest first, so that the latest ratings are sent if there are too many.
+pub fn analyze(
+    client: &ChatGPTClient,
+    files: &[SessionFile],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut uses: BTreeMap<Option<&str>, PromptUse> = BTreeMap::new();
+    for file in files {
+        let prompt_use = uses.entry(file.metadata.persona.as_deref()).or_default();
+        prompt_use.sessions += 1;
+        prompt_use.exchanges += file.entries.len();
+        prompt_use.annotated.extend(
+            file.entries
+                .iter()
+                .filter(|entry| entry.annotation().is_some()),
+        );
+    }
+    let mut uses: Vec<(Option<&str>, PromptUse)> = uses.into_iter().collect();
+    uses.sort_by_key(|(_, prompt_use)| std::cmp::Reverse(prompt_use.exchanges));
+
+    let mut report = String::new();
+    let mut num_analyzed = 0;
+    for (persona, prompt_use) in &uses {
+        let prompt = match persona {
+            Some(name) => client
+                .config
+                .personas
+                .iter()
+                .find(|persona| persona.name == *name)
+                .map(|persona| persona.prompt.as_str()),
+            None => Some(client.config.initial_prompt.as_str()),
+        };
+        let count = |rating| {
+            prompt_use
+                .annotated
+                .iter()
+                .filter(|entry| entry.rating == Some(rating))
+                .count()
+        };
+        let num_notes = prompt_use
+            .annotated
+            .iter()
+            .filter(|entry| entry.note.is_some())
+            .count();
+        writeln!(
+            report,
+            "## {}",
+            persona.map_or(String::from("Initial prompt"), |name| format!(
+                "Persona {}",
+                name
+            ))
+        )?;
+        writeln!(report)?;
+        writeln!(
+            report,
+            "{} sessions, {} exchanges: {} {}, {} {}, {} notes",
+            prompt_use.sessions,
+            prompt_use.exchanges,
+            count(Rating::Up),
+            Rating::Up,
+            count(Rating::Down),
+            Rating::Down,
+            num_notes
+        )?;
+        writeln!(report)?;
+        let Some(prompt) = prompt else {
+            writeln!(report, "The persona is no longer in the config")?;
+            writeln!(report)?;
+            continue;
+        };
+        if prompt_use.annotated.is_empty() {
+            writeln!(
+                report,
+                "No exchanges were rated or annotated, rate them with /rate and /note"
+            )?;
+            writeln!(report)?;
+            continue;
+        }
+
+        let mut request = format!("{}System prompt:\n{}\n\n", SUGGESTION_REQUEST, prompt);
+        for entry in prompt_use.annotated.iter().take(MAX_EXCHANGES) {
+            write!(
+                request,
+                "User: {}\nAssistant: {}\nRating and note: {}\n\n",
+                excerpt(&entry.message, MAX_EXCERPT_CHARS),
+                excerpt(&entry.response, MAX_EXCERPT_CHARS),
+                entry.annotation().unwrap_or_default()
+            )?;
+        }
+        let (suggestions, _) = client.send_request(
+            std::iter::once(Message::new(&request, "user")),
+            "",
+            &SamplingParams::default(),
+        )?;
+        writeln!(report, "{}", suggestions.response.trim_end())?;
+        writeln!(report)?;
+        num_analyzed += 1;
+    }
+    writeln!(
+        report,
+        "Analyzed {} sessions, suggested improvements to {} prompts",
+        files.len(),
+        num_analyzed
+    )?;
+    Ok(report)
+}
@@ -0,0 +1,285 @@
+use std::io::{BufRead, BufReader};
+
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::api::{
+    api_error, metadata_headers, ChatGPTClient, ChatLogEntry, Message, ResponseMetadata,
+};
+use crate::backend::{ChatBackend, ChatRequest};
+use crate::errors::ChatGptError;
+use crate::retry::retry_after;
+use crate::transport::{HttpRequest, HttpResponse};
+
+// Version of the API the requests are written for
+const API_VERSION: &str = "2023-06-01";
+// Environment variable with the API key, used when the config has none
+const API_KEY_VARIABLE: &str = "ANTHROPIC_API_KEY";
+
+/// API key and model of Anthropic, for the anthropic backend
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AnthropicConfig {
+    // Taken from ANTHROPIC_API_KEY if not set
+    pub api_key: Option<String>,
+    pub base_url: String,
+    // Model used unless another one is chosen
+    pub model: String,
+    // Longest answer in tokens when max_response_tokens isn't set, since the API asks for one
+    pub max_tokens: u32,
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            base_url: String::from("https://api.anthropic.com/v1"),
+            model: String::from("claude-3-5-sonnet-latest"),
+            max_tokens: 4096,
+        }
+    }
+}
+
+/// The messages API of Anthropic. Tools are not offered to the model.
+pub struct Anthropic;
+
+impl Anthropic {
+    // Address the endpoints are under, without a trailing slash
+    fn base_url(client: &ChatGPTClient) -> &str {
+        client.config.anthropic.base_url.trim_end_matches('/')
+    }
+
+    // Headers sending the API key and the version of the API. The extra headers of the config are meant for the
+    // OpenAI compatible API and are not sent.
+    fn headers(client: &ChatGPTClient) -> Result<HeaderMap, ChatGptError> {
+        let config = &client.config.anthropic;
+        let key = config
+            .api_key
+            .clone()
+            .or_else(|| std::env::var(API_KEY_VARIABLE).ok())
+            .ok_or_else(|| {
+                ChatGptError::Config(format!(
+                    "Set api_key in the [anthropic] table of the config file, or {}",
+                    API_KEY_VARIABLE
+                ))
+            })?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-api-key",
+            HeaderValue::from_str(&key).map_err(|_| {
+                ChatGptError::Config(
+                    "The API key contains characters that can't be sent".to_string(),
+                )
+            })?,
+        );
+        headers.insert("anthropic-version", HeaderValue::from_static(API_VERSION));
+        Ok(headers)
+    }
+
+    // Send a request to the messages API, answered as it is generated when streaming
+    fn post(
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+        stream: bool,
+    ) -> Result<HttpResponse, ChatGptError> {
+        let (system, messages) = convert_messages(request.messages);
+        let sampling = request.sampling;
+        let mut body = json!({
+            "model": client.config.openai_model,
+            "max_tokens": sampling
+                .max_response_tokens
+                .unwrap_or(client.config.anthropic.max_tokens),
+            "messages": messages,
+        });
+        if !system.is_empty() {
+            body["system"] = json!(system);
+        }
+        if let Some(temperature) = sampling.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = sampling.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if stream {
+            body["stream"] = json!(true);
+        }
+        let url = format!("{}/messages", Self::base_url(client));
+        let response = client.post_json(url, Self::headers(client)?, &body)?;
+        if response.status.is_success() {
+            return Ok(response);
+        }
+        let status = response.status;
+        let wait = retry_after(&response.headers);
+        let error = response.json().unwrap_or(Value::Null);
+        Err(api_error(status, &error, wait))
+    }
+}
+
+// Anthropic takes the system prompt apart from the other messages, which have to alternate between the user and
+// the assistant. Consecutive messages of the same side are joined.
+fn convert_messages(messages: &[Message]) -> (String, Vec<Value>) {
+    let mut system = Vec::new();
+    let mut turns: Vec<(&str, String)> = Vec::new();
+    for message in messages {
+        let role = match message.role.as_str() {
+            "system" => {
+                system.push(message.content.as_str());
+                continue;
+            }
+            "assistant" => "assistant",
+            _ => "user",
+        };
+        match turns.last_mut() {
+            Some((last_role, content)) if *last_role == role => {
+                content.push_str("\n\n");
+                content.push_str(&message.content);
+            }
+            _ => turns.push((role, message.content.clone())),
+        }
+    }
+    let turns = turns
+        .into_iter()
+        .map(|(role, content)| json!({"role": role, "content": content}))
+        .collect();
+    (system.join("\n\n"), turns)
+}
+
+// An answer in the shape of one from the chat completions API, which the client turns into a chat log entry
+fn completion(
+    id: &Value,
+    model: &Value,
+    text: &str,
+    stop_reason: &Value,
+    input_tokens: &Value,
+    output_tokens: &Value,
+) -> Value {
+    let usage = match (input_tokens.as_i64(), output_tokens.as_i64()) {
+        (Some(input), Some(output)) => json!({
+            "prompt_tokens": input,
+            "completion_tokens": output,
+            "total_tokens": input + output,
+        }),
+        _ => Value::Null,
+    };
+    json!({
+        "id": id,
+        "model": model,
+        "choices": [{
+            "message": {"role": "assistant", "content": text},
+            "finish_reason": stop_reason,
+        }],
+        "usage": usage,
+    })
+}
+
+impl ChatBackend for Anthropic {
+    fn send(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        let response = Self::post(client, request, false)?;
+        let headers = metadata_headers(&response.headers);
+        let response = response
+            .json()
+            .map_err(|err| ChatGptError::InvalidResponse(err.to_string()))?;
+        let text: String = response["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|block| block["text"].as_str())
+            .collect();
+        let response = completion(
+            &response["id"],
+            &response["model"],
+            &text,
+            &response["stop_reason"],
+            &response["usage"]["input_tokens"],
+            &response["usage"]["output_tokens"],
+        );
+        client.entry_from_response(request.messages, &response, headers)
+    }
+
+    fn stream(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        let response = Self::post(client, request, true)?;
+        let headers = metadata_headers(&response.headers);
+        // The message is announced with its usage so far, then arrives in pieces, then gets its final usage
+        let mut message = Value::Null;
+        let mut text = String::new();
+        let mut stop_reason = Value::Null;
+        let mut output_tokens = Value::Null;
+        for line in BufReader::new(response.body).lines() {
+            if client.is_cancelled() {
+                return Err(ChatGptError::Cancelled);
+            }
+            let line = line.map_err(|err| ChatGptError::Network(err.to_string()))?;
+            let Some(data) = line.strip_prefix("data: ") else {
+                continue;
+            };
+            let event: Value = serde_json::from_str(data)?;
+            match event["type"].as_str() {
+                Some("message_start") => message = event["message"].clone(),
+                Some("content_block_delta") => {
+                    if let Some(token) = event["delta"]["text"].as_str() {
+                        on_token(token);
+                        text.push_str(token);
+                    }
+                }
+                Some("message_delta") => {
+                    stop_reason = event["delta"]["stop_reason"].clone();
+                    output_tokens = event["usage"]["output_tokens"].clone();
+                }
+                Some("error") => {
+                    return Err(api_error(
+                        reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+                        &event,
+                        None,
+                    ))
+                }
+                Some("message_stop") => break,
+                _ => {}
+            }
+        }
+        let response = completion(
+            &message["id"],
+            &message["model"],
+            &text,
+            &stop_reason,
+            &message["usage"]["input_tokens"],
+            &output_tokens,
+        );
+        client.entry_from_response(request.messages, &response, headers)
+    }
+
+    fn list_models(&self, client: &ChatGPTClient) -> Result<Vec<String>, ChatGptError> {
+        let response = client.transport.send(HttpRequest {
+            method: Method::GET,
+            url: format!("{}/models", Self::base_url(client)),
+            query: Vec::new(),
+            headers: Self::headers(client)?,
+            body: None,
+        })?;
+        let status = response.status;
+        let wait = retry_after(&response.headers);
+        let response = response.json().unwrap_or(Value::Null);
+        if !status.is_success() {
+            return Err(api_error(status, &response, wait));
+        }
+        let mut models: Vec<String> = response["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|model| model["id"].as_str())
+            .map(String::from)
+            .collect();
+        models.sort();
+        Ok(models)
+    }
+}
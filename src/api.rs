@@ -1,12 +1,237 @@
 use core::str;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use chrono::{Datelike, Local, Timelike};
-use reqwest::blocking::Client;
-use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use chrono::{DateTime, Datelike, Local, Timelike};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE,
+};
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 
-use crate::ChatTermConfig;
+use crate::agent::AGENT_INSTRUCTIONS;
+use crate::backend::{BackendKind, ChatRequest};
+use crate::errors::ChatGptError;
+use crate::events::{Event, EventLog};
+use crate::export::{to_html, to_markdown, ExportFormat};
+use crate::filters::apply_filters;
+use crate::locale::Locale;
+use crate::pricing::PriceTable;
+use crate::retry::{retry_after, Retry};
+use crate::sampling::SamplingParams;
+use crate::tokens::{context_size, count_message_tokens, count_tokens, TOKENS_PER_REQUEST};
+use crate::tools::{
+    available_tools, needs_approval, run_tool, Tool, ToolCall, DECLINED_OUTPUT,
+    DEFAULT_MAX_TOOL_ITERATIONS,
+};
+use crate::transport::{HttpRequest, HttpResponse, ReqwestTransport, Transport};
+use crate::usage::Usage;
+use crate::variables::{expand_config_variables, expand_variables};
+use crate::{ChatTermConfig, Example, Persona};
+
+/// Version of the session file format written by this build
+pub const CHATLOG_FORMAT_VERSION: u64 = 1;
+
+// Migrations between session file formats. The migration at index `i` upgrades a file from version `i` to `i + 1`.
+const CHATLOG_MIGRATIONS: [fn(Value) -> Value; CHATLOG_FORMAT_VERSION as usize] =
+    [migrate_v0_to_v1];
+
+// Version 0 files are a bare array of entries without any versioning
+fn migrate_v0_to_v1(entries: Value) -> Value {
+    json!({ "format_version": 1, "entries": entries })
+}
+
+/// Upgrade the JSON contents of a session file to the current format version
+pub fn migrate_chatlog(mut value: Value) -> Result<Value, Box<dyn std::error::Error>> {
+    let version = if value.is_array() {
+        0
+    } else {
+        value["format_version"]
+            .as_u64()
+            .ok_or("session file is missing its format_version")?
+    };
+    if version > CHATLOG_FORMAT_VERSION {
+        return Err(format!(
+            "session file format version {} is newer than the supported version {}",
+            version, CHATLOG_FORMAT_VERSION
+        )
+        .into());
+    }
+    for migration in &CHATLOG_MIGRATIONS[version as usize..] {
+        value = migration(value);
+    }
+    Ok(value)
+}
+
+/// Result of checking a session file against its stored checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatlogIntegrity {
+    /// File contents match the stored checksum
+    Intact,
+    /// No checksum was stored alongside the file (e.g. logs from older versions)
+    Unverified,
+    /// File contents do not match the stored checksum (truncated or corrupted)
+    Corrupted,
+}
+
+/// Path of the checksum file stored next to a session file
+pub fn checksum_path(path: &str) -> String {
+    format!("{}.sha256", path)
+}
+
+/// Path of the backup copy of a session file
+pub fn backup_path(path: &str) -> String {
+    format!("{}.bak", path)
+}
+
+// Hex encoded SHA-256 digest of the given data
+fn checksum(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Check a session file against the checksum stored next to it
+pub fn verify_chatlog(path: &str) -> std::io::Result<ChatlogIntegrity> {
+    let data = std::fs::read(path)?;
+    let expected = match std::fs::read_to_string(checksum_path(path)) {
+        Ok(expected) => expected,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(ChatlogIntegrity::Unverified)
+        }
+        Err(err) => return Err(err),
+    };
+    if expected.trim() == checksum(&data) {
+        Ok(ChatlogIntegrity::Intact)
+    } else {
+        Ok(ChatlogIntegrity::Corrupted)
+    }
+}
+
+// Write data to a file along with its checksum
+fn write_with_checksum(path: &str, data: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, data)?;
+    std::fs::write(checksum_path(path), checksum(data))?;
+    Ok(())
+}
+
+// Request used to condense exchanges that no longer fit in the context
+const SUMMARY_REQUEST: &str = "Summarize the following conversation between a user and an assistant in a short \
+    paragraph, keeping any facts, decisions and instructions that later messages may depend on.\n\n";
+
+const MEMORY_REQUEST: &str = "List facts about the user from the following conversation that are worth remembering \
+    in future conversations, such as their name, job, preferences and ongoing projects. Leave out anything only \
+    relevant to this conversation. Reply with one short fact per line, starting with `- `, or with nothing if there \
+    are none.\n\n";
+
+const REPHRASE_REQUEST: &str = "The following message was blocked by a content filter, possibly by mistake. Rephrase \
+    it so that it asks for the same thing without wording that could be mistaken for harmful content. Reply with \
+    the rephrased message only.\n\n";
+
+// Request used to title exchanges in the outline of a session
+const HEADINGS_REQUEST: &str = "Write a heading of at most six words for each of the following numbered messages \
+    from a conversation, saying what it is about. Reply with one line per message in the form `3. Heading`, and \
+    nothing else.\n\n";
+// Characters of each message sent to have headings written, which is enough to tell what it is about
+const HEADING_MESSAGE_CHARS: usize = 300;
+
+// Longest name suggested for the file of a session, in characters
+const MAX_SUGGESTED_NAME_CHARS: usize = 40;
+
+// Text written as a file name: lowercase words joined by dashes, without punctuation, cut at a word
+fn slugify(text: &str, max_chars: usize) -> String {
+    let mut slug = String::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let word = word.to_lowercase();
+        if !slug.is_empty() && slug.chars().count() + word.chars().count() >= max_chars {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word);
+    }
+    slug
+}
+
+// System message telling the model the current date and time, as it otherwise goes by its training data
+fn current_time_message() -> String {
+    let now = Local::now();
+    let zone = std::env::var("TZ")
+        .ok()
+        .filter(|zone| !zone.is_empty())
+        .map(|zone| format!("{}, ", zone))
+        .unwrap_or_default();
+    format!(
+        "The current date and time is {} ({}UTC{}).",
+        now.format("%A, %Y-%m-%d %H:%M"),
+        zone,
+        now.format("%:z")
+    )
+}
+
+// Expected length of a response when estimating the cost of the first request in a session
+const EXPECTED_RESPONSE_TOKENS: u32 = 500;
+
+/// A message that was sent to the API as part of a request
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SentMessage {
+    // Index of the chat log entry the message came from
+    pub entry: usize,
+    pub role: String,
+    pub num_tokens: u32,
+}
+
+/// Record of what was sent to the API to produce a chat log entry
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SentContext {
+    pub messages: Vec<SentMessage>,
+    // Initial prompt sent as the system message
+    pub initial_prompt: String,
+}
+
+/// A variation of a request, compared against another one in A/B mode
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Variant {
+    // Initial prompt used instead of the one of the session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.initial_prompt, self.temperature) {
+            (Some(prompt), _) => write!(f, "prompt \"{}\"", prompt.trim()),
+            (None, Some(temperature)) => write!(f, "temperature {}", temperature),
+            (None, None) => write!(f, "session prompt"),
+        }
+    }
+}
+
+/// Response produced by one of the variants of a request in A/B mode
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VariantResponse {
+    pub label: String,
+    pub variant: Variant,
+    pub response: String,
+    pub num_tokens_message: u32,
+    pub num_tokens_response: u32,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ChatLogEntry {
@@ -14,7 +239,67 @@ pub struct ChatLogEntry {
     pub response: String,
     pub num_tokens_message: u32,
     pub num_tokens_response: u32,
+    // Not recorded for entries from older session files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent: Option<SentContext>,
+    // Pinned exchanges are always kept in the context
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    // Responses of each variant when the message was sent in A/B mode. The first one is also the response above,
+    // which is the one kept in the context.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<VariantResponse>,
+    // Tools the model ran while answering, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    // Short heading for the outline of the session, written by the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+    // When the answer arrived. Not recorded for entries from older session files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Local>>,
+    // Model that answered, by which the exchange is priced. Not recorded for entries from older session files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // Private rating and note of the user, e.g. to track which prompts worked well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<Rating>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    // The whole response when it was cut to `max_response_chars`, only kept until the app is closed
+    #[serde(skip)]
+    pub full_response: Option<String>,
 }
+
+/// A response cut to a number of characters, with a line saying how much was left out, or None if it is short
+/// enough already
+pub fn truncate_response(response: &str, max_chars: usize) -> Option<String> {
+    let (end, _) = response.char_indices().nth(max_chars)?;
+    Some(format!(
+        "{}\n\n[Cut to {} of {} characters, /full shows the whole response]",
+        &response[..end],
+        max_chars,
+        response.chars().count()
+    ))
+}
+
+/// How well an exchange went, as rated by the user
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+impl Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rating::Up => write!(f, "👍"),
+            Rating::Down => write!(f, "👎"),
+        }
+    }
+}
+
 impl ChatLogEntry {
     pub fn new(message: &str, response: &str) -> Self {
         Self {
@@ -22,23 +307,290 @@ impl ChatLogEntry {
             response: String::from(response),
             num_tokens_message: 0,
             num_tokens_response: 0,
+            sent: None,
+            pinned: false,
+            variants: Vec::new(),
+            tool_calls: Vec::new(),
+            heading: None,
+            timestamp: None,
+            model: None,
+            rating: None,
+            note: None,
+            full_response: None,
+        }
+    }
+
+    /// The rating and note of the user, e.g. `👍 clear and short`, or None if there are neither
+    pub fn annotation(&self) -> Option<String> {
+        match (self.rating, self.note.as_deref()) {
+            (None, None) => None,
+            (Some(rating), None) => Some(rating.to_string()),
+            (None, Some(note)) => Some(note.to_string()),
+            (Some(rating), Some(note)) => Some(format!("{} {}", rating, note)),
         }
     }
+
+    /// Number of prompt and completion tokens used by all the requests made for this exchange
+    pub fn total_tokens(&self) -> (u32, u32) {
+        let answers = if self.variants.is_empty() {
+            (self.num_tokens_message, self.num_tokens_response)
+        } else {
+            self.variants
+                .iter()
+                .fold((0, 0), |(prompt, completion), variant| {
+                    (
+                        prompt + variant.num_tokens_message,
+                        completion + variant.num_tokens_response,
+                    )
+                })
+        };
+        self.tool_calls
+            .iter()
+            .fold(answers, |(prompt, completion), call| {
+                (
+                    prompt + call.num_tokens_message,
+                    completion + call.num_tokens_response,
+                )
+            })
+    }
+}
+
+/// Metadata stored in a session file alongside the chat log
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SessionMetadata {
+    // Favorite sessions are listed before all others
+    pub favorite: bool,
+    // Summary of exchanges that no longer fit in the context, sent along with the initial prompt
+    pub summary: Option<String>,
+    // Named points in the conversation that can be returned to, mapped to the number of exchanges before them
+    pub checkpoints: BTreeMap<String, usize>,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    // System prompt set with /system, used instead of the initial prompt of the persona or config
+    pub system_prompt: Option<String>,
+    // Model chosen for this session with /model or /models, or by its persona, used instead of the configured one
+    pub model: Option<String>,
+    // Profile from the config the messages are sent with, the main settings if None
+    pub profile: Option<String>,
+    // Backend chosen for this session with /backend, used instead of the configured one
+    pub backend: Option<BackendKind>,
+    // Name of the persona the session was started with
+    pub persona: Option<String>,
+    // Summary of the previous session with the same persona, sent along with the initial prompt
+    pub previous_session: Option<String>,
+}
+
+/// Contents of a session file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SessionFile {
+    #[serde(default)]
+    pub metadata: SessionMetadata,
+    pub entries: Vec<ChatLogEntry>,
+}
+
+impl SessionFile {
+    /// Read a session file, migrating it from older format versions if needed
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(serde_json::from_value(migrate_chatlog(value)?)?)
+    }
+
+    /// Write a session file, keeping the previous intact version as a backup
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        if std::path::Path::new(path).exists() && verify_chatlog(path)? == ChatlogIntegrity::Intact
+        {
+            write_with_checksum(&backup_path(path), &std::fs::read(path)?)?;
+        }
+        let json = serde_json::to_string_pretty(&json!({
+            "format_version": CHATLOG_FORMAT_VERSION,
+            "metadata": self.metadata,
+            "entries": self.entries,
+        }))?;
+        write_with_checksum(path, json.as_bytes())
+    }
+}
+
+/// Remove exchanges at the end of a chat log that repeat the exchanges just before them, a common artifact of
+/// re-saving after a crash. Returns the number of entries removed.
+pub fn dedup_trailing_entries(entries: &mut Vec<ChatLogEntry>) -> usize {
+    let mut removed = 0;
+    loop {
+        let n = entries.len();
+        // Look for the longest run of k entries at the end that matches the k entries before it
+        let repeated = (1..=n / 2).rev().find(|&k| {
+            (0..k).all(|i| {
+                let (a, b) = (&entries[n - 2 * k + i], &entries[n - k + i]);
+                a.message == b.message && a.response == b.response
+            })
+        });
+        match repeated {
+            Some(k) => {
+                entries.truncate(n - k);
+                removed += k;
+            }
+            None => return removed,
+        }
+    }
+}
+
+// Where requests go unless the config says otherwise
+const DEFAULT_API_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_AZURE_API_VERSION: &str = "2023-05-15";
+// Parts of the names of models that can't be used for chat
+const NON_CHAT_MODELS: &[&str] = &[
+    "embedding",
+    "whisper",
+    "tts",
+    "dall-e",
+    "davinci",
+    "babbage",
+    "moderation",
+    "instruct",
+    "audio",
+    "realtime",
+    "transcribe",
+    "image",
+];
+
+/// Who requests to an API address go to, for telling accounts and servers apart: OpenAI, Azure or the host of the
+/// server
+pub fn provider_name(api_base_url: Option<&str>) -> String {
+    let Some(url) = api_base_url.and_then(|url| reqwest::Url::parse(url).ok()) else {
+        return String::from("OpenAI");
+    };
+    match (url.host_str(), url.port()) {
+        (Some(host), _) if host.ends_with(".openai.azure.com") => String::from("Azure"),
+        (Some("api.openai.com") | None, _) => String::from("OpenAI"),
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+    }
+}
+
+// The error an API responded with. Running out of quota is also reported with 429, but waiting doesn't help.
+pub(crate) fn api_error(
+    status: reqwest::StatusCode,
+    response: &Value,
+    wait: Option<std::time::Duration>,
+) -> ChatGptError {
+    let message = response["error"]["message"]
+        .as_str()
+        .map(String::from)
+        .unwrap_or_else(|| format!("Unknown error ({})", status));
+    let code = response["error"]["code"].as_str().map(String::from);
+    match code.as_deref() {
+        Some("insufficient_quota") => ChatGptError::Api {
+            status: status.as_u16(),
+            code,
+            message,
+            retry_after: None,
+        },
+        _ if status.as_u16() == 429 => ChatGptError::RateLimited {
+            message,
+            retry_after: wait,
+        },
+        _ => ChatGptError::Api {
+            status: status.as_u16(),
+            code,
+            message,
+            retry_after: wait,
+        },
+    }
+}
+
+// How often a cancelled request is checked for while waiting to try it again
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+// Response headers shown in the response metadata
+const METADATA_HEADERS: [&str; 11] = [
+    "openai-model",
+    "openai-organization",
+    "openai-processing-ms",
+    "openai-version",
+    "x-request-id",
+    "request-id",
+    "x-ratelimit-remaining-requests",
+    "x-ratelimit-remaining-tokens",
+    "x-ratelimit-reset-requests",
+    "x-ratelimit-reset-tokens",
+    "via",
+];
+
+// The response headers shown in the response metadata, with their values
+pub(crate) fn metadata_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    METADATA_HEADERS
+        .iter()
+        .filter_map(|&name| {
+            let value = headers.get(name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Kinds of errors from the API, which scripts can tell apart by the exit codes of non-interactive commands
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiErrorKind {
+    Auth,
+    RateLimit,
+    // The account has run out of credit
+    Quota,
+    Network,
+    // The response could not be read
+    Parse,
+    ContentFilter,
+    Other,
 }
-// Struct holds information from a chatgpt session including prior messages and responses
+
+/// Raw metadata of a response from the API, useful when debugging providers and proxies
+#[derive(Debug, Clone, Default)]
+pub struct ResponseMetadata {
+    pub id: Option<String>,
+    pub model: Option<String>,
+    pub created: Option<i64>,
+    pub finish_reason: Option<String>,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+    // Values of the headers of interest that were present in the response
+    pub headers: Vec<(String, String)>,
+}
+
+/// A conversation: the chat log and its metadata, sent to the model within the token limit as messages are added
 pub struct ChatGPTSession {
     name: String,
     // chat log is a vector of tuples of the form (message, response, num_tokens_message, num_tokens_response)
     chatlog: Vec<ChatLogEntry>,
+    metadata: SessionMetadata,
     max_tokens: u32,
     client: ChatGPTClient,
+    // Initial prompt used instead of the one from the config, e.g. from a persona
+    initial_prompt: Option<String>,
+    last_response: Option<ResponseMetadata>,
+    // Variants each message is sent with in A/B mode
+    ab_variants: Option<[Variant; 2]>,
+    // Shorter answers and more retries without streaming, for flaky connections
+    low_bandwidth: bool,
+    // Sampling parameters from the config, changed by the persona or with /set
+    sampling: SamplingParams,
+    // Example exchanges sent ahead of the chat log, e.g. from a persona
+    examples: Vec<Example>,
+    events: EventLog,
+    // Config the session was created with, which profiles are applied to
+    default_config: ChatTermConfig,
+    // Facts about the user remembered from other sessions, sent along with the initial prompt
+    memories: Vec<String>,
+    // Where new sessions and sessions saved under a bare name go
+    sessions_dir: PathBuf,
+    // File given with --session or recovered, saved back to as it is rather than to a file named after the session
+    path: Option<String>,
 }
 
 impl ChatGPTSession {
-    /// Create session name from current time
-    fn generate_session_name() -> String {
+    /// Create session name from current time, in the sessions directory
+    fn generate_session_name(dir: &Path) -> String {
         let now = Local::now(); // e.g. `2014-11-28T12:45:59.324310806Z`
-        format!(
+        let name = format!(
             "chatlog_{}{}{}{}{}{}",
             now.year(),
             now.month(),
@@ -46,28 +598,412 @@ impl ChatGPTSession {
             now.hour(),
             now.minute(),
             now.second()
-        )
+        );
+        dir.join(name).to_string_lossy().to_string()
     }
     /// Initialize a new ChatGPTSession with a ChatGPTClient and max_tokens
     pub fn new(client: ChatGPTClient, chatlog: Vec<ChatLogEntry>, max_tokens: u32) -> Self {
+        let sampling = client.config.sampling;
+        let default_config = client.config.clone();
+        let sessions_dir = client.config.sessions_dir();
+        let low_bandwidth = client.config.low_bandwidth.enabled;
+        // Variables in the initial prompt from the config are resolved once, when the session starts
+        let initial_prompt = client
+            .config
+            .initial_prompt
+            .contains("{{")
+            .then(|| expand_config_variables(&client.config.initial_prompt, &client.config));
         Self {
-            name: Self::generate_session_name(),
+            name: Self::generate_session_name(&sessions_dir),
             chatlog,
+            metadata: SessionMetadata::default(),
             max_tokens,
             client,
+            initial_prompt,
+            last_response: None,
+            ab_variants: None,
+            low_bandwidth,
+            sampling,
+            examples: Vec::new(),
+            events: EventLog::default(),
+            default_config,
+            memories: Vec::new(),
+            sessions_dir,
+            path: None,
         }
     }
 
-    /// Add data freom log file
-    pub fn load_chatlog(path: &str) -> Result<Vec<ChatLogEntry>, Box<dyn std::error::Error>> {
-        let entries: Vec<ChatLogEntry> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
-        Ok(entries)
+    /// Replace the chat log and metadata with the contents of a session file. If the profile it was using can't be
+    /// used, the main settings are used instead and the error says why.
+    pub fn restore(&mut self, file: SessionFile) -> Result<(), String> {
+        self.chatlog = file.entries;
+        self.metadata = file.metadata;
+        let profile = self.metadata.profile.clone();
+        self.use_profile(profile.as_deref()).map_err(|err| {
+            self.metadata.profile = None;
+            self.use_profile(None).ok();
+            format!("{}, using the main settings instead", err)
+        })
+    }
+
+    // Make requests with the settings of a profile, or the main ones if None
+    fn use_profile(&mut self, name: Option<&str>) -> Result<(), String> {
+        let config = match name {
+            Some(name) => self.default_config.with_profile(name)?,
+            None => self.default_config.clone(),
+        };
+        // Requests keep going through the same transport, e.g. one that answers them in process
+        self.client = ChatGPTClient::with_transport(config, self.client.transport.clone());
+        Ok(())
+    }
+
+    /// Send the following messages with the address, API key and model of a profile, or the main ones if None.
+    /// A model chosen for the session before is dropped for the one of the profile.
+    pub fn set_profile(&mut self, name: Option<&str>) -> Result<(), String> {
+        self.use_profile(name)?;
+        self.metadata.profile = name.map(String::from);
+        self.metadata.model = None;
+        Ok(())
+    }
+
+    /// Name of the profile the messages are sent with, None for the main settings
+    pub fn profile(&self) -> Option<&str> {
+        self.metadata.profile.as_deref()
+    }
+
+    /// Who the messages are sent to, e.g. OpenAI or the host of a local server
+    pub fn provider(&self) -> String {
+        match self.backend() {
+            BackendKind::OpenAi => provider_name(self.client.config.api_base_url.as_deref()),
+            BackendKind::Anthropic => String::from("Anthropic"),
+            BackendKind::Ollama => String::from("Ollama"),
+        }
+    }
+
+    /// Send the following messages to another backend, with the given model or else the one of its config
+    pub fn set_backend(&mut self, backend: BackendKind, model: Option<&str>) {
+        self.metadata.backend = Some(backend);
+        self.metadata.model = model.map(String::from);
+    }
+
+    /// Backend the messages are sent to: the one chosen for this session, or else the configured one
+    pub fn backend(&self) -> BackendKind {
+        self.metadata.backend.unwrap_or(self.client.config.backend)
+    }
+
+    /// The profiles that can be switched to, each with who it sends messages to and its model, starting with the
+    /// main settings as None
+    pub fn profile_choices(&self) -> Vec<(Option<String>, String)> {
+        let config = &self.default_config;
+        let main = format!(
+            "main settings ({}, {})",
+            provider_name(config.api_base_url.as_deref()),
+            config.openai_model
+        );
+        std::iter::once((None, main))
+            .chain(config.profiles.iter().map(|profile| {
+                let label = format!(
+                    "{} ({}, {})",
+                    profile.name,
+                    provider_name(profile.api_base_url.as_deref()),
+                    profile.openai_model.as_ref().unwrap_or(&config.openai_model)
+                );
+                (Some(profile.name.clone()), label)
+            }))
+            .collect()
+    }
+
+    /// Use a different initial prompt for this session
+    pub fn set_initial_prompt(&mut self, prompt: &str) {
+        self.initial_prompt = Some(prompt.to_string());
+    }
+
+    /// Set the system prompt of this session, which is saved with it and takes precedence over the initial prompt
+    /// of the persona or config
+    pub fn set_system_prompt(&mut self, prompt: &str) {
+        self.metadata.system_prompt = Some(prompt.to_string());
+    }
+
+    /// Report requests made by this session to programs observing the app
+    pub fn set_event_log(&mut self, events: EventLog) {
+        self.events = events;
+    }
+
+    /// Report an event to programs observing the app
+    pub fn emit(&self, event: Event) {
+        self.events.emit(event);
+    }
+
+    /// Use the initial prompt, model, parameters and examples of a persona for this session. Variables in its prompt
+    /// are resolved now.
+    pub fn set_persona(&mut self, persona: &Persona) {
+        self.set_initial_prompt(&expand_variables(&persona.prompt, &self.client.config));
+        if persona.temperature.is_some() {
+            self.sampling.temperature = persona.temperature;
+        }
+        if let Some(model) = &persona.model {
+            self.metadata.model = Some(model.clone());
+        }
+        if let Some(max_tokens) = persona.max_tokens {
+            self.max_tokens = max_tokens;
+        }
+        self.examples = persona.examples.clone();
+        self.metadata.persona = Some(persona.name.clone());
+    }
+
+    /// Send each following message with both variants, or stop doing so if None
+    pub fn set_ab_variants(&mut self, variants: Option<[Variant; 2]>) {
+        self.ab_variants = variants;
+    }
+
+    /// Sampling parameters each message is sent with
+    pub fn sampling(&self) -> &SamplingParams {
+        &self.sampling
+    }
+
+    /// Change a sampling parameter for the following messages, given its name and value as typed by the user
+    pub fn set_sampling(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.sampling.set(name, value)
+    }
+
+    /// Variants each message is sent with, if A/B mode is on
+    pub fn ab_variants(&self) -> Option<&[Variant; 2]> {
+        self.ab_variants.as_ref()
+    }
+
+    /// Turn low-bandwidth mode on or off for the following messages
+    pub fn set_low_bandwidth(&mut self, on: bool) {
+        self.low_bandwidth = on;
+    }
+
+    /// Whether messages are sent in low-bandwidth mode
+    pub fn low_bandwidth(&self) -> bool {
+        self.low_bandwidth
     }
 
-    /// Reset the chatlog and session name
+    // Get the configuration of the client used by this session
+    pub fn config(&self) -> &ChatTermConfig {
+        &self.client.config
+    }
+
+    /// Reset the chatlog and session name, staying with the same profile
     pub fn reset(&mut self) {
         self.chatlog = Vec::new();
-        self.name = Self::generate_session_name();
+        self.metadata = SessionMetadata {
+            profile: self.metadata.profile.take(),
+            persona: self.metadata.persona.take(),
+            ..Default::default()
+        };
+        self.last_response = None;
+        self.name = Self::generate_session_name(&self.sessions_dir);
+        self.path = None;
+    }
+
+    /// Name of the file the session is saved to, without the extension. A name without a directory is saved in the
+    /// sessions directory.
+    pub fn set_name(&mut self, name: &str) {
+        let name = name.strip_suffix(".json").unwrap_or(name);
+        self.path = None;
+        self.name = if Path::new(name).parent() == Some(Path::new("")) {
+            self.sessions_dir.join(name).to_string_lossy().to_string()
+        } else {
+            name.to_string()
+        };
+    }
+
+    /// Save the session to exactly this file, whatever its directory and extension
+    pub fn set_path(&mut self, path: &str) {
+        self.path = Some(path.to_string());
+    }
+
+    /// Send the following messages to another model
+    pub fn set_model(&mut self, model: &str) {
+        self.metadata.model = Some(model.to_string());
+    }
+
+    /// Model the messages are sent to: the one chosen for this session, or else the configured one
+    pub fn model(&self) -> &str {
+        self.metadata
+            .model
+            .as_deref()
+            .unwrap_or_else(|| self.backend().default_model(&self.client.config))
+    }
+
+    /// Names of the chat models the backend of the session can use
+    pub fn list_models(&self) -> Result<Vec<String>, ChatGptError> {
+        self.session_client().list_models()
+    }
+
+    // The client, sending requests to the model of this session
+    fn session_client(&self) -> ChatGPTClient {
+        let mut client = self.client.clone();
+        client.config.openai_model = self.model().to_string();
+        client.config.backend = self.backend();
+        if self.low_bandwidth {
+            client.low_bandwidth = true;
+            client.config.retry = client.config.low_bandwidth.retry.clone();
+        }
+        client
+    }
+
+    /// Metadata stored with the chat log
+    pub fn metadata(&self) -> &SessionMetadata {
+        &self.metadata
+    }
+
+    /// The chat log as a document to share, titled with the title of the session or else its name
+    pub fn export(&self, format: ExportFormat) -> String {
+        let title = self.metadata.title.as_deref().unwrap_or(&self.name);
+        let model = self.model();
+        let locale = Locale::from_config(&self.client.config);
+        match format {
+            ExportFormat::Markdown => to_markdown(title, model, &self.chatlog, &locale),
+            ExportFormat::Html => to_html(title, model, &self.chatlog, &locale),
+        }
+    }
+
+    /// Give the session a title, shown when listing sessions
+    pub fn set_title(&mut self, title: &str) {
+        self.metadata.title = Some(title.to_string());
+    }
+
+    /// A title made from the start of the first message, cut at a word, if there is one
+    pub fn generated_title(&self) -> Option<String> {
+        const MAX_TITLE_CHARS: usize = 50;
+        let first_line = self
+            .chatlog
+            .first()?
+            .message
+            .lines()
+            .find(|line| !line.trim().is_empty())?;
+        let mut title = String::new();
+        for word in first_line.split_whitespace() {
+            if !title.is_empty() && title.chars().count() + word.chars().count() >= MAX_TITLE_CHARS
+            {
+                title.push_str("...");
+                break;
+            }
+            if !title.is_empty() {
+                title.push(' ');
+            }
+            title.push_str(word);
+        }
+        Some(title)
+    }
+
+    /// A name for the file of a session that has no title and hasn't been saved yet, made from the start of the
+    /// first message, e.g. "how-do-i-reverse-a-list-in-python" for "How do I reverse a list in Python?". A number
+    /// is added to it if another session already has the name.
+    pub fn suggested_name(&self) -> Option<String> {
+        if self.metadata.title.is_some()
+            || self.path.is_some()
+            || Path::new(&self.file_name()).exists()
+        {
+            return None;
+        }
+        let slug = slugify(&self.generated_title()?, MAX_SUGGESTED_NAME_CHARS);
+        if slug.is_empty() {
+            return None;
+        }
+        let taken = |name: &str| self.sessions_dir.join(format!("{}.json", name)).exists();
+        (1..)
+            .map(|number| match number {
+                1 => slug.clone(),
+                number => format!("{}-{}", slug, number),
+            })
+            .find(|name| !taken(name))
+    }
+
+    /// Add a tag to the session, or remove it if `add` is false
+    pub fn set_tag(&mut self, tag: &str, add: bool) {
+        self.metadata.tags.retain(|existing| existing != tag);
+        if add {
+            self.metadata.tags.push(tag.to_string());
+        }
+    }
+
+    /// Remove the last exchange, so that its message can be sent again for another answer
+    pub fn pop_entry(&mut self) -> Option<ChatLogEntry> {
+        self.chatlog.pop()
+    }
+
+    /// Put back an exchange removed with `pop_entry`
+    pub fn push_entry(&mut self, entry: ChatLogEntry) {
+        self.chatlog.push(entry);
+    }
+
+    /// Mark the current point in the conversation so that it can be returned to later
+    pub fn add_checkpoint(&mut self, name: &str) {
+        self.metadata
+            .checkpoints
+            .insert(name.to_string(), self.chatlog.len());
+    }
+
+    /// Return to a checkpoint, discarding the exchanges after it along with any checkpoints among them. Returns
+    /// false if there is no checkpoint with this name.
+    pub fn rollback(&mut self, name: &str) -> bool {
+        let Some(&num_entries) = self.metadata.checkpoints.get(name) else {
+            return false;
+        };
+        self.chatlog.truncate(num_entries);
+        self.metadata
+            .checkpoints
+            .retain(|_, checkpoint| *checkpoint <= num_entries);
+        true
+    }
+
+    /// Save the conversation so far and continue from a checkpoint as a new session. Returns the file the
+    /// conversation was saved to, or None if there is no checkpoint with this name.
+    pub fn fork(&mut self, name: &str) -> std::io::Result<Option<String>> {
+        if !self.metadata.checkpoints.contains_key(name) {
+            return Ok(None);
+        }
+        let filename = self.save_chatlog()?;
+        self.rollback(name);
+        self.name = Self::generate_session_name(&self.sessions_dir);
+        self.path = None;
+        // Session names only have a resolution of one second
+        if format!("{}.json", self.name) == filename {
+            self.name = format!("{}_fork", self.name);
+        }
+        Ok(Some(filename))
+    }
+
+    /// Estimated cost in US dollars of an exchange, if the price of the model that answered it is known. Exchanges
+    /// from older session files are priced as if the current model answered them.
+    pub fn entry_cost(&self, entry: &ChatLogEntry) -> Option<f64> {
+        let (prompt_tokens, completion_tokens) = entry.total_tokens();
+        self.client.prices.cost(
+            entry.model.as_deref().unwrap_or(self.model()),
+            prompt_tokens,
+            completion_tokens,
+        )
+    }
+
+    /// Estimated cost in US dollars of the exchanges in this session, if the price of the model is known
+    pub fn cost(&self) -> Option<f64> {
+        self.chatlog
+            .iter()
+            .try_fold(0.0, |total, entry| Some(total + self.entry_cost(entry)?))
+    }
+
+    /// Tokens used by the exchanges in this session and their estimated cost, counting only exchanges with a known
+    /// price
+    pub fn usage(&self) -> Usage {
+        let mut usage = Usage::default();
+        for entry in &self.chatlog {
+            let (prompt_tokens, completion_tokens) = entry.total_tokens();
+            usage.requests += 1;
+            usage.prompt_tokens += prompt_tokens as u64;
+            usage.completion_tokens += completion_tokens as u64;
+            usage.cost += self.entry_cost(entry).unwrap_or(0.0);
+        }
+        usage
+    }
+
+    // Get the metadata of the last response received in this session
+    pub fn last_response(&self) -> Option<&ResponseMetadata> {
+        self.last_response.as_ref()
     }
 
     // Get the chat log
@@ -77,57 +1013,697 @@ impl ChatGPTSession {
 
     // save chatlog to json file based on session name
     pub fn save_chatlog(&self) -> std::io::Result<String> {
-        let filename = format!("{}.json", self.name);
+        let filename = self.file_name();
+        if let Some(dir) = Path::new(&filename).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
         self.save_chatlog_to_path(&filename)?;
         Ok(filename)
     }
 
+    /// Name of the file the session is saved to
+    pub fn file_name(&self) -> String {
+        match &self.path {
+            Some(path) => path.clone(),
+            None => format!("{}.json", self.name),
+        }
+    }
+
+    /// The chat log and metadata as they are written to a session file
+    pub fn session_file(&self) -> SessionFile {
+        SessionFile {
+            metadata: self.metadata.clone(),
+            entries: self.chatlog.clone(),
+        }
+    }
+
     // Save chat log to file with given name
     pub fn save_chatlog_to_path(&self, path: &str) -> std::io::Result<()> {
-        let chat_log_json = serde_json::to_string_pretty(&self.chatlog)?;
-        std::fs::write(path, chat_log_json)?;
-        Ok(())
+        self.session_file().save(path)
     }
 
-    // Send a message to the ChatGPT API
-    pub fn send_message(
-        &mut self,
-        message: &str,
-    ) -> Result<ChatLogEntry, Box<dyn std::error::Error>> {
+    // Initial prompt used for this session, including the summary of earlier exchanges
+    fn initial_prompt(&self) -> String {
+        let prompt = self.metadata.system_prompt.as_deref();
+        self.initial_prompt_with(prompt.or(self.initial_prompt.as_deref()))
+    }
+
+    // The given initial prompt, or the one from the config, followed by the remembered facts and the summary of
+    // earlier exchanges
+    fn initial_prompt_with(&self, initial_prompt: Option<&str>) -> String {
+        let mut prompt = initial_prompt
+            .unwrap_or(&self.client.config.initial_prompt)
+            .to_string();
+        if !self.memories.is_empty() {
+            prompt.push_str("\n\nWhat you remember about the user from earlier conversations:");
+            for fact in &self.memories {
+                prompt.push_str(&format!("\n- {}", fact));
+            }
+        }
+        if let Some(previous) = &self.metadata.previous_session {
+            prompt.push_str(&format!(
+                "\n\nSummary of the previous conversation with the user: {}",
+                previous
+            ));
+        }
+        if let Some(summary) = &self.metadata.summary {
+            prompt.push_str(&format!(
+                "\n\nSummary of the earlier conversation: {}",
+                summary
+            ));
+        }
+        prompt
+    }
+
+    /// Tell the model these facts about the user along with the initial prompt
+    pub fn set_memories(&mut self, facts: Vec<String>) {
+        self.memories = facts;
+    }
+
+    // Tokens available for the context of a request: the configured limit, within the context window of the model
+    // less room for the answer when the window is known
+    fn context_budget(&self) -> u32 {
+        match context_size(self.model()) {
+            Some(window) => self
+                .max_tokens
+                .min(window.saturating_sub(EXPECTED_RESPONSE_TOKENS)),
+            None => self.max_tokens,
+        }
+    }
+
+    /// Work out which messages from the chat log fit in the context along with a new message, oldest first. The
+    /// last one is the new message itself.
+    pub fn plan_context(&self, message: &str) -> Vec<SentMessage> {
         // Add previous response and then the message before that and so on as long as the total number of tokens
-        // is less than max_tokens
-        let mut messages: VecDeque<Message> = VecDeque::new();
+        // is within the budget
+        let max_tokens = self.context_budget();
+        let model = self.model();
+        let count = |text: &str| count_message_tokens(model, text);
+        let mut sent_messages: Vec<SentMessage> = Vec::new();
+
+        // The initial prompt, current time and examples are always sent, so they are counted along with the new
+        // message
+        let time_tokens = if self.client.config.send_current_time {
+            count(&current_time_message())
+        } else {
+            0
+        };
+        let mut num_tokens = count(message)
+            + TOKENS_PER_REQUEST
+            + time_tokens
+            + count(&self.initial_prompt())
+            + self
+                .examples
+                .iter()
+                .map(|example| count(&example.user) + count(&example.assistant))
+                .sum::<u32>();
+        sent_messages.push(SentMessage {
+            entry: self.chatlog.len(),
+            role: String::from("user"),
+            num_tokens,
+        });
 
-        let message = Message::new(message, "user");
-        let mut num_tokens = message.content.split(' ').count() as u32;
+        // Pinned exchanges are always included, and the rest of the context is filled with the latest exchanges
+        for (index, entry) in self.chatlog.iter().enumerate() {
+            if entry.pinned {
+                let (message_tokens, resp_tokens) = (count(&entry.message), count(&entry.response));
+                sent_messages.push(SentMessage {
+                    entry: index,
+                    role: String::from("user"),
+                    num_tokens: message_tokens,
+                });
+                sent_messages.push(SentMessage {
+                    entry: index,
+                    role: String::from("assistant"),
+                    num_tokens: resp_tokens,
+                });
+                num_tokens += message_tokens + resp_tokens;
+            }
+        }
 
-        for entry in self.chatlog.iter().rev() {
+        for (index, entry) in self
+            .chatlog
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, entry)| !entry.pinned)
+        {
             // First add the last response
-            let resp_tokens = entry.num_tokens_response;
-            if resp_tokens + num_tokens > self.max_tokens {
+            let resp_tokens = count(&entry.response);
+            if resp_tokens + num_tokens > max_tokens {
                 break;
             }
-            messages.push_front(Message::new(&entry.response, "assistant"));
+            sent_messages.push(SentMessage {
+                entry: index,
+                role: String::from("assistant"),
+                num_tokens: resp_tokens,
+            });
             num_tokens += resp_tokens;
 
             // Then add the message that generated the response
-            let message_tokens = entry.num_tokens_message;
+            let message_tokens = count(&entry.message);
 
-            if message_tokens + num_tokens > self.max_tokens {
+            if message_tokens + num_tokens > max_tokens {
                 break;
             }
-            messages.push_front(Message::new(&entry.message, "user"));
+            sent_messages.push(SentMessage {
+                entry: index,
+                role: String::from("user"),
+                num_tokens: message_tokens,
+            });
             num_tokens += message_tokens;
         }
-        messages.push_back(message);
+        // Each message comes before its response
+        sent_messages.sort_by_key(|sent| (sent.entry, sent.role == "assistant"));
+        sent_messages
+    }
+
+    /// Exchanges that were part of the context of the last request but would be dropped from it when sending a
+    /// message
+    pub fn newly_dropped_entries(&self, message: &str) -> Vec<usize> {
+        let planned: BTreeSet<usize> = self
+            .plan_context(message)
+            .iter()
+            .map(|sent| sent.entry)
+            .collect();
+        // Exchanges restored from older session files don't say what was sent with them, so the latest one that
+        // does is gone by, or nothing is if none does
+        let previous: BTreeSet<usize> = self
+            .chatlog
+            .iter()
+            .rev()
+            .find_map(|entry| entry.sent.as_ref())
+            .map(|sent| sent.messages.iter().map(|sent| sent.entry).collect())
+            .unwrap_or_default();
+        previous.difference(&planned).copied().collect()
+    }
+
+    /// Tokens the pinned exchanges take along with a new message, the initial prompt and the examples, if they alone
+    /// are more than the context allows, along with the limit. They are sent all the same, so the request may fail.
+    pub fn pinned_over_budget(&self, message: &str) -> Option<(u32, u32)> {
+        let budget = self.context_budget();
+        let tokens: u32 = self
+            .plan_context(message)
+            .iter()
+            .filter(|sent| {
+                self.chatlog
+                    .get(sent.entry)
+                    .is_none_or(|entry| entry.pinned)
+            })
+            .map(|sent| sent.num_tokens)
+            .sum();
+        (tokens > budget).then_some((tokens, budget))
+    }
+
+    /// Rate an exchange, or remove its rating with None
+    pub fn set_rating(&mut self, index: usize, rating: Option<Rating>) {
+        self.chatlog[index].rating = rating;
+    }
+
+    /// Attach a note to an exchange, or remove it with None
+    pub fn set_note(&mut self, index: usize, note: Option<String>) {
+        self.chatlog[index].note = note;
+    }
+
+    /// Pin exchanges so that they are always kept in the context
+    pub fn pin_entries(&mut self, entries: &[usize]) {
+        for &index in entries {
+            self.chatlog[index].pinned = true;
+        }
+    }
+
+    /// Condense exchanges into a summary that is sent along with the initial prompt, so that they are not
+    /// forgotten when they no longer fit in the context
+    pub fn summarize_entries(&mut self, entries: &[usize]) -> Result<(), ChatGptError> {
+        let entries: Vec<&ChatLogEntry> =
+            entries.iter().map(|&index| &self.chatlog[index]).collect();
+        let summary = self.write_summary(self.metadata.summary.as_deref(), &entries)?;
+        self.metadata.summary = Some(summary);
+        Ok(())
+    }
+
+    // Have the summary model condense exchanges, following the summary of what came before them if any
+    fn write_summary(
+        &self,
+        earlier: Option<&str>,
+        entries: &[&ChatLogEntry],
+    ) -> Result<String, ChatGptError> {
+        let mut conversation = String::new();
+        if let Some(summary) = earlier {
+            conversation.push_str(&format!("Summary of what came before: {}\n\n", summary));
+        }
+        for entry in entries {
+            conversation.push_str(&format!(
+                "User: {}\nAssistant: {}\n\n",
+                entry.message, entry.response
+            ));
+        }
+        let request = Message::new(&format!("{}{}", SUMMARY_REQUEST, conversation), "user");
+        let mut client = self.session_client();
+        if let Some(model) = &self.client.config.summary_model {
+            client.config.openai_model = model.clone();
+        }
+        let (summary, _) =
+            client.send_request(std::iter::once(request), "", &SamplingParams::default())?;
+        Ok(summary.response)
+    }
+
+    /// Carry on from a previous session by sending a summary of it along with the initial prompt. Only its latest
+    /// exchanges that fit within the token limit are summarized, along with its own summary of what came before.
+    pub fn continue_from(&mut self, previous: &SessionFile) -> Result<(), ChatGptError> {
+        let budget = self.context_budget();
+        let mut num_tokens = 0;
+        let mut entries: Vec<&ChatLogEntry> = Vec::new();
+        for entry in previous.entries.iter().rev() {
+            num_tokens += count_message_tokens(self.model(), &entry.message)
+                + count_message_tokens(self.model(), &entry.response);
+            if num_tokens > budget && !entries.is_empty() {
+                break;
+            }
+            entries.push(entry);
+        }
+        entries.reverse();
+        if entries.is_empty() && previous.metadata.summary.is_none() {
+            return Ok(());
+        }
+        let summary = self.write_summary(previous.metadata.summary.as_deref(), &entries)?;
+        self.metadata.previous_session = Some(summary);
+        Ok(())
+    }
+
+    /// Condense the exchanges that sending a message would drop from the context into the summary, if
+    /// `auto_summarize` is on. Returns how many were summarized.
+    pub fn summarize_dropped(&mut self, message: &str) -> Result<usize, ChatGptError> {
+        if !self.client.config.auto_summarize {
+            return Ok(0);
+        }
+        let dropped = self.newly_dropped_entries(message);
+        if !dropped.is_empty() {
+            self.summarize_entries(&dropped)?;
+        }
+        Ok(dropped.len())
+    }
+
+    /// Have the model pick out facts about the user from the exchanges that fit in the context, to be approved before
+    /// they are remembered. Facts that are already remembered are left out.
+    pub fn extract_memories(&self) -> Result<Vec<String>, ChatGptError> {
+        let entries: BTreeSet<usize> = self
+            .plan_context("")
+            .iter()
+            .map(|sent| sent.entry)
+            .filter(|&index| index < self.chatlog.len())
+            .collect();
+        let mut conversation = String::new();
+        for index in entries {
+            let entry = &self.chatlog[index];
+            conversation.push_str(&format!(
+                "User: {}\nAssistant: {}\n\n",
+                entry.message, entry.response
+            ));
+        }
+        let request = Message::new(&format!("{}{}", MEMORY_REQUEST, conversation), "user");
+        let (facts, _) = self.session_client().send_request(
+            std::iter::once(request),
+            "",
+            &SamplingParams::default(),
+        )?;
+        Ok(facts
+            .response
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("- "))
+            .map(|fact| fact.trim().to_string())
+            .filter(|fact| !fact.is_empty() && !self.memories.contains(fact))
+            .collect())
+    }
+
+    /// Ask the model to rephrase a message that was blocked by the content filter, so that it can be sent again
+    pub fn rephrase(&self, message: &str) -> Result<String, ChatGptError> {
+        let request = Message::new(&format!("{}{}", REPHRASE_REQUEST, message), "user");
+        let (rephrased, _) =
+            self.session_client()
+                .send_request(std::iter::once(request), "", &SamplingParams::default())?;
+        Ok(rephrased.response.trim().to_string())
+    }
+
+    /// Have the model write headings for the outline of the exchanges that have none yet, returning how many were
+    /// written
+    pub fn generate_headings(&mut self) -> Result<usize, ChatGptError> {
+        let missing: Vec<usize> = (0..self.chatlog.len())
+            .filter(|&index| self.chatlog[index].heading.is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(0);
+        }
+        let mut messages = String::new();
+        for &index in &missing {
+            let message: String = self.chatlog[index]
+                .message
+                .chars()
+                .take(HEADING_MESSAGE_CHARS)
+                .collect();
+            messages.push_str(&format!("{}. {}\n\n", index + 1, message.replace('\n', " ")));
+        }
+        let request = Message::new(&format!("{}{}", HEADINGS_REQUEST, messages), "user");
+        let (headings, _) =
+            self.session_client()
+                .send_request(std::iter::once(request), "", &SamplingParams::default())?;
+        let mut written = 0;
+        for line in headings.response.lines() {
+            let Some((number, heading)) = line.trim().split_once(". ") else {
+                continue;
+            };
+            let heading = heading.trim().trim_matches(['"', '*', '`']);
+            let index = number.trim().parse::<usize>().ok().and_then(|n| n.checked_sub(1));
+            if let Some(entry) = index
+                .filter(|index| missing.contains(index))
+                .and_then(|index| self.chatlog.get_mut(index))
+            {
+                if !heading.is_empty() {
+                    entry.heading = Some(heading.to_string());
+                    written += 1;
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    /// Estimated cost in US dollars of sending a message, assuming a response as long as the average response so
+    /// far. None if the price of the model is unknown.
+    pub fn estimate_cost(&self, message: &str) -> Option<f64> {
+        let prompt_tokens: u32 = self
+            .plan_context(message)
+            .iter()
+            .map(|sent| sent.num_tokens)
+            .sum();
+        let response_tokens = if self.chatlog.is_empty() {
+            EXPECTED_RESPONSE_TOKENS
+        } else {
+            self.chatlog
+                .iter()
+                .map(|entry| entry.num_tokens_response)
+                .sum::<u32>()
+                / self.chatlog.len() as u32
+        };
+        let cost = self.client.prices.cost(
+            self.model(),
+            prompt_tokens,
+            response_tokens,
+        )?;
+        // Every variant is a separate request in A/B mode
+        let num_requests = self
+            .ab_variants
+            .as_ref()
+            .map_or(1, |variants| variants.len());
+        Some(cost * num_requests as f64)
+    }
+
+    // Send a message to the ChatGPT API
+    pub fn send_message(&mut self, message: &str) -> Result<ChatLogEntry, ChatGptError> {
+        self.send_message_streaming(message, None)
+    }
+
+    /// Send a message to the ChatGPT API, streaming the answer to `on_token` as it is generated if given. Answers
+    /// are not streamed in A/B mode. There is no one to ask for approval, so only auto-approved tools are run.
+    pub fn send_message_streaming(
+        &mut self,
+        message: &str,
+        on_token: Option<&mut dyn FnMut(&str)>,
+    ) -> Result<ChatLogEntry, ChatGptError> {
+        self.summarize_dropped(message)?;
+        let request = self.prepare_message(message);
+        let result = request.send(on_token, &mut |_, _| false);
+        self.finish_message(request, result)
+    }
+
+    /// Prepare the request for sending a message, with the context that fits within the token limit
+    pub fn prepare_message(&self, message: &str) -> PendingRequest {
+        let sent_messages = self.plan_context(message);
+        let examples = self.examples.iter().flat_map(|example| {
+            [
+                Message::new(&example.user, "user"),
+                Message::new(&example.assistant, "assistant"),
+            ]
+        });
+        // The time is taken anew for each message, so that it is never stale
+        let time = self
+            .client
+            .config
+            .send_current_time
+            .then(|| Message::new(&current_time_message(), "system"));
+        let messages: Vec<Message> = time
+            .into_iter()
+            .chain(examples)
+            .chain(
+                sent_messages
+                    .iter()
+                    .map(|sent| match self.chatlog.get(sent.entry) {
+                        Some(entry) if sent.role == "assistant" => {
+                            Message::new(&entry.response, "assistant")
+                        }
+                        Some(entry) => Message::new(&entry.message, "user"),
+                        None => Message::new(message, "user"),
+                    }),
+            )
+            .collect();
+
+        let initial_prompt = self.initial_prompt();
+        let ab_variants = self.ab_variants.as_ref().map(|variants| {
+            variants.clone().map(|variant| {
+                let prompt = match &variant.initial_prompt {
+                    Some(prompt) => self.initial_prompt_with(Some(prompt)),
+                    None => initial_prompt.clone(),
+                };
+                (variant, prompt)
+            })
+        });
+        let mut sampling = self.sampling;
+        if self.low_bandwidth {
+            let max_tokens = self.client.config.low_bandwidth.max_response_tokens;
+            sampling.max_response_tokens = Some(
+                sampling
+                    .max_response_tokens
+                    .map_or(max_tokens, |tokens| tokens.min(max_tokens)),
+            );
+        }
+        PendingRequest {
+            message: message.to_string(),
+            client: self.session_client(),
+            messages,
+            sent_messages,
+            initial_prompt,
+            sampling,
+            ab_variants,
+            agent: false,
+            events: self.events.clone(),
+        }
+    }
 
-        // Make API request to get ChatLogEntry
-        let response = self.client.send_request(messages.into_iter())?;
+    /// Prepare the request for having the model work towards a goal in agent mode, running tools step by step
+    pub fn prepare_agent(&self, goal: &str) -> PendingRequest {
+        let mut request = self.prepare_message(goal);
+        request.initial_prompt = format!("{}\n\n{}", request.initial_prompt, AGENT_INSTRUCTIONS);
+        // The steps of the two variants could not be compared
+        request.ab_variants = None;
+        request.agent = true;
+        request
+    }
 
-        // // Create a fake ChatLogEntry with a dummy response
-        // let response = ChatLogEntry::new(&message.content, "Some response from bot");
-        self.chatlog.push(response.clone());
-        Ok(response)
+    /// Add the answer to a prepared request to the chat log
+    pub fn finish_message(
+        &mut self,
+        request: PendingRequest,
+        result: Result<(ChatLogEntry, ResponseMetadata), ChatGptError>,
+    ) -> Result<ChatLogEntry, ChatGptError> {
+        let result = result.and_then(|(mut response, metadata)| {
+            let filters = &self.client.config.response_filters;
+            let filter = |text: &str| {
+                apply_filters(filters, text).map_err(|err| {
+                    ChatGptError::Config(format!("Error applying response filters: {}", err))
+                })
+            };
+            response.response = filter(&response.response)?;
+            for variant in &mut response.variants {
+                variant.response = filter(&variant.response)?;
+            }
+            if let Some(max_chars) = self.client.config.max_response_chars {
+                if let Some(truncated) = truncate_response(&response.response, max_chars) {
+                    response.full_response =
+                        Some(std::mem::replace(&mut response.response, truncated));
+                }
+            }
+            response.sent = Some(SentContext {
+                messages: request.sent_messages,
+                initial_prompt: request.initial_prompt,
+            });
+            response.timestamp = Some(Local::now());
+            response.model = Some(self.model().to_string());
+            self.last_response = Some(metadata);
+            self.chatlog.push(response.clone());
+            Ok(response)
+        });
+        match &result {
+            Ok(entry) => self.events.emit(Event::Completed {
+                prompt_tokens: entry.num_tokens_message,
+                completion_tokens: entry.num_tokens_response,
+                finish_reason: self
+                    .last_response
+                    .as_ref()
+                    .and_then(|metadata| metadata.finish_reason.as_deref()),
+            }),
+            Err(err) => self.events.emit(Event::Error {
+                kind: Some(err.kind()),
+                message: &err.to_string(),
+            }),
+        }
+        result
+    }
+}
+
+/// A message ready to be sent. It holds everything needed to make the request, so that it can be sent from another
+/// thread while the session is in use, and the answer added with `ChatGPTSession::finish_message`.
+pub struct PendingRequest {
+    message: String,
+    client: ChatGPTClient,
+    messages: Vec<Message>,
+    sent_messages: Vec<SentMessage>,
+    initial_prompt: String,
+    sampling: SamplingParams,
+    // Variants with their initial prompts in A/B mode
+    ab_variants: Option<[(Variant, String); 2]>,
+    // Work towards the message as a goal in agent mode, with the limits and checkpoints of the agent config
+    agent: bool,
+    events: EventLog,
+}
+
+impl PendingRequest {
+    /// Have `on_retry` told about each failed request that is tried again while this one is made
+    pub fn on_retry(&mut self, on_retry: impl Fn(&Retry) + Send + Sync + 'static) {
+        self.client.on_retry = Some(Arc::new(on_retry));
+    }
+
+    /// Flag that cancels the request when set, from another thread. A request already on its way can't be called
+    /// back, but it stops before the next attempt, tool or streamed token.
+    pub fn cancel_flag(&mut self) -> Arc<AtomicBool> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.client.cancelled = Some(cancelled.clone());
+        cancelled
+    }
+
+    /// Make the request, streaming the answer to `on_token` as it is generated if given. Tools that are not
+    /// auto-approved, and steps at checkpoints in agent mode, are only run if `approve` agrees when given the
+    /// tool and the number of the step.
+    pub fn send(
+        &self,
+        mut on_token: Option<&mut dyn FnMut(&str)>,
+        approve: &mut dyn FnMut(&ToolCall, usize) -> bool,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        let streaming =
+            on_token.is_some() && self.ab_variants.is_none() && !self.client.low_bandwidth;
+        self.events.emit(Event::RequestStarted {
+            model: &self.client.config.openai_model,
+            streaming,
+        });
+        let mut on_token_with_events = |token: &str| {
+            self.events.emit(Event::Token { text: token });
+            if let Some(on_token) = on_token.as_mut() {
+                on_token(token);
+            }
+        };
+        let Some(variants) = &self.ab_variants else {
+            return self.send_with_tools(
+                streaming.then_some(&mut on_token_with_events as &mut dyn FnMut(&str)),
+                approve,
+            );
+        };
+
+        // Tools are not offered in A/B mode. The first variant's response is the one kept in the chat log
+        let mut results = Vec::new();
+        for ((variant, prompt), label) in variants.iter().zip(["A", "B"]) {
+            let (entry, metadata) = self.client.send_request_streaming(
+                self.messages.clone().into_iter(),
+                prompt,
+                &SamplingParams {
+                    temperature: variant.temperature.or(self.sampling.temperature),
+                    ..self.sampling
+                },
+                &[],
+                None,
+            )?;
+            results.push((
+                VariantResponse {
+                    label: label.to_string(),
+                    variant: variant.clone(),
+                    response: entry.response.clone(),
+                    num_tokens_message: entry.num_tokens_message,
+                    num_tokens_response: entry.num_tokens_response,
+                },
+                entry,
+                metadata,
+            ));
+        }
+        let variants = results
+            .iter()
+            .map(|(variant, _, _)| variant.clone())
+            .collect();
+        let (_, mut entry, metadata) = results.swap_remove(0);
+        entry.variants = variants;
+        Ok((entry, metadata))
+    }
+
+    // Send the message, running the tools the model asks for and sending their output back until it answers
+    fn send_with_tools(
+        &self,
+        mut on_token: Option<&mut dyn FnMut(&str)>,
+        approve: &mut dyn FnMut(&ToolCall, usize) -> bool,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        let config = &self.client.config;
+        let max_iterations = if self.agent {
+            config.agent.max_steps
+        } else {
+            config
+                .max_tool_iterations
+                .unwrap_or(DEFAULT_MAX_TOOL_ITERATIONS)
+        } as usize;
+        let available = available_tools(config);
+        let mut messages = self.messages.clone();
+        let mut tool_calls = Vec::new();
+        loop {
+            // Once the limit is reached the tools are no longer offered, so that the model has to answer
+            let tools = if tool_calls.len() < max_iterations {
+                available.as_slice()
+            } else {
+                &[]
+            };
+            let (mut entry, metadata) = self.client.send_request_streaming(
+                messages.clone().into_iter(),
+                &self.initial_prompt,
+                &self.sampling,
+                tools,
+                on_token
+                    .as_mut()
+                    .map(|on_token| &mut **on_token as &mut dyn FnMut(&str)),
+            )?;
+            let Some(mut call) = entry.tool_calls.pop() else {
+                // The last message sent may be the output of a tool rather than the user's
+                entry.message = self.message.clone();
+                entry.tool_calls = tool_calls;
+                return Ok((entry, metadata));
+            };
+            let step = tool_calls.len() + 1;
+            let ask = needs_approval(&available, &call.name)
+                || (self.agent && config.agent.is_checkpoint(step));
+            if ask && !approve(&call, step) {
+                call.output = DECLINED_OUTPUT.to_string();
+            } else {
+                run_tool(config, &mut call);
+            }
+            let mut request = Message::new(&call.plan, "assistant");
+            request.function_call = Some(json!({"name": call.name, "arguments": call.arguments}));
+            let mut output = Message::new(&call.output, "function");
+            output.name = Some(call.name.clone());
+            messages.extend([request, output]);
+            tool_calls.push(call);
+        }
     }
 }
 
@@ -136,6 +1712,12 @@ impl ChatGPTSession {
 pub struct Message {
     pub content: String,
     pub role: String,
+    // Name of the tool whose output a "function" message carries
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Tool the model asked to run in an assistant message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<Value>,
 }
 
 impl Message {
@@ -143,16 +1725,30 @@ impl Message {
         Self {
             content: String::from(content),
             role: String::from(role),
+            name: None,
+            function_call: None,
         }
     }
 }
 
-// Struct representing a ChatGPT client with an auth token
-// Uses a type state marker to represent the state of the client
+/// Callback told about a failed request that is about to be tried again
+pub type OnRetry = Arc<dyn Fn(&Retry) + Send + Sync>;
+
+/// Makes requests to the backend of the config: the chat completions API of OpenAI or of another server with a
+/// compatible API, Anthropic or Ollama
+#[derive(Clone)]
 pub struct ChatGPTClient {
     pub config: ChatTermConfig,
-    // reqwest client
-    pub client: Client,
+    // Sends the requests, over HTTP unless another transport was given
+    pub transport: Arc<dyn Transport>,
+    // Built-in model prices combined with those from the config
+    pub prices: PriceTable,
+    // Told about each failed request that is tried again, e.g. to show it in the status line
+    pub on_retry: Option<OnRetry>,
+    // Set when the user cancels the request, which then stops at the next chance
+    pub cancelled: Option<Arc<AtomicBool>>,
+    // Compress requests as set in the low-bandwidth config
+    pub low_bandwidth: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -161,16 +1757,261 @@ struct ChatGPTRequest {
     model: String,
     #[serde(rename = "messages")]
     messages: Vec<Message>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    // Longest answer, unlike max_tokens in the config which limits the context
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    functions: Vec<Value>,
+}
+
+// Collect a streamed response into the shape of a regular one, passing each piece of the answer to `on_token` as it
+// arrives. Streamed responses don't report token usage, so it is estimated from the number of words sent and the
+// number of pieces received.
+fn read_event_stream(
+    response: impl Read,
+    model: &str,
+    messages: &[Message],
+    on_token: &mut dyn FnMut(&str),
+    cancelled: Option<&AtomicBool>,
+) -> Result<Value, ChatGptError> {
+    let mut content = String::new();
+    let mut finish_reason = Value::Null;
+    let mut last_chunk = Value::Null;
+    // A tool the model asks to run arrives in pieces too
+    let mut function_name = String::new();
+    let mut function_arguments = String::new();
+    for line in BufReader::new(response).lines() {
+        if cancelled.is_some_and(|cancelled| cancelled.load(Ordering::Relaxed)) {
+            return Err(ChatGptError::Cancelled);
+        }
+        let line = line.map_err(|err| ChatGptError::Network(err.to_string()))?;
+        let Some(data) = line.strip_prefix("data: ") else {
+            continue;
+        };
+        if data == "[DONE]" {
+            break;
+        }
+        let chunk: Value = serde_json::from_str(data)?;
+        if let Some(token) = chunk["choices"][0]["delta"]["content"].as_str() {
+            on_token(token);
+            content.push_str(token);
+        }
+        let function_call = &chunk["choices"][0]["delta"]["function_call"];
+        if function_call.is_object() {
+            function_name.push_str(function_call["name"].as_str().unwrap_or_default());
+            function_arguments.push_str(function_call["arguments"].as_str().unwrap_or_default());
+        }
+        if !chunk["choices"][0]["finish_reason"].is_null() {
+            finish_reason = chunk["choices"][0]["finish_reason"].clone();
+        }
+        last_chunk = chunk;
+    }
+    // Streamed responses don't report usage, so it is counted with the tokenizer of the model
+    let prompt_tokens = TOKENS_PER_REQUEST
+        + messages
+            .iter()
+            .map(|message| count_message_tokens(model, &message.content))
+            .sum::<u32>();
+    let completion_tokens = count_tokens(model, &content)
+        + count_tokens(model, &function_name)
+        + count_tokens(model, &function_arguments);
+    let mut message = json!({"content": content});
+    if !function_name.is_empty() {
+        message["function_call"] = json!({"name": function_name, "arguments": function_arguments});
+    }
+    Ok(json!({
+        "id": last_chunk["id"],
+        "model": last_chunk["model"],
+        "created": last_chunk["created"],
+        "choices": [{"message": message, "finish_reason": finish_reason}],
+        "usage": {
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "total_tokens": prompt_tokens + completion_tokens,
+        },
+    }))
 }
 
 impl ChatGPTClient {
     // Construct new client from auth token, initializes reqwest client
     pub fn new(config: ChatTermConfig) -> Self {
+        let transport = Arc::new(ReqwestTransport::new(&config.network));
+        Self::with_transport(config, transport)
+    }
+
+    /// A client sending its requests with another transport than HTTP, e.g. one that answers them in process
+    pub fn with_transport(config: ChatTermConfig, transport: Arc<dyn Transport>) -> Self {
         Self {
-            config: config,
-            client: Client::new(),
+            prices: PriceTable::new(&config.model_prices),
+            transport,
+            config,
+            on_retry: None,
+            cancelled: None,
+            low_bandwidth: false,
+        }
+    }
+
+    // Whether the user cancelled the request being made
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::Relaxed))
+    }
+    // Whether requests go to an Azure OpenAI resource, which addresses models by deployment and takes the key in
+    // its own header
+    fn is_azure(&self) -> bool {
+        self.config.backend == BackendKind::OpenAi
+            && self
+                .config
+                .api_base_url
+                .as_deref()
+                .and_then(|url| reqwest::Url::parse(url).ok())
+                .and_then(|url| {
+                    url.host_str()
+                        .map(|host| host.ends_with(".openai.azure.com"))
+                })
+                .unwrap_or(false)
+    }
+
+    // Address the endpoints are under, without a trailing slash
+    fn base_url(&self) -> &str {
+        self.config
+            .api_base_url
+            .as_deref()
+            .unwrap_or(DEFAULT_API_BASE_URL)
+            .trim_end_matches('/')
+    }
+
+    // Headers sending the API key, which Azure takes in its own header, along with the extra headers of the config
+    fn auth_headers(&self) -> Result<HeaderMap, ChatGptError> {
+        let mut headers = HeaderMap::new();
+        let key = &self.config.openai_api_key;
+        let auth = if self.is_azure() {
+            ("api-key", key.clone())
+        } else {
+            (AUTHORIZATION.as_str(), format!("Bearer {}", key))
+        };
+        headers.insert(
+            auth.0,
+            HeaderValue::from_str(&auth.1).map_err(|_| {
+                ChatGptError::Config(
+                    "The API key contains characters that can't be sent".to_string(),
+                )
+            })?,
+        );
+        self.add_extra_headers(&mut headers)?;
+        Ok(headers)
+    }
+
+    // Add the extra headers of the config. They are meant for the OpenAI compatible API or a gateway in front of
+    // it, so they are not sent to the servers of other backends, which could be given a token of the gateway.
+    fn add_extra_headers(&self, headers: &mut HeaderMap) -> Result<(), ChatGptError> {
+        if self.config.backend != BackendKind::OpenAi {
+            return Ok(());
+        }
+        for (name, value) in &self.config.extra_headers {
+            let invalid = || ChatGptError::Config(format!("Invalid extra header {}", name));
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?,
+                HeaderValue::from_str(&expand_config_variables(value, &self.config))
+                    .map_err(|_| invalid())?,
+            );
         }
+        Ok(())
+    }
+
+    // Query parameters of the config, added to every request to the OpenAI compatible API
+    fn query_params(&self) -> Vec<(String, String)> {
+        if self.config.backend != BackendKind::OpenAi {
+            return Vec::new();
+        }
+        self.config
+            .query_params
+            .iter()
+            .map(|(name, value)| (name.clone(), expand_config_variables(value, &self.config)))
+            .collect()
     }
+
+    /// Address of the chat completions endpoint. On Azure the model is the name of the deployment.
+    pub fn completions_url(&self) -> String {
+        let base = self.base_url();
+        if self.is_azure() {
+            format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                base,
+                self.config.openai_model,
+                self.config
+                    .azure_api_version
+                    .as_deref()
+                    .unwrap_or(DEFAULT_AZURE_API_VERSION)
+            )
+        } else {
+            format!("{}/chat/completions", base)
+        }
+    }
+
+    /// Names of the chat models the backend of the config can use, sorted
+    pub fn list_models(&self) -> Result<Vec<String>, ChatGptError> {
+        self.config.backend.backend().list_models(self)
+    }
+
+    // Names of the chat models the API key can use with the chat completions API. Models for embeddings, audio,
+    // images and the older completions endpoint are left out.
+    pub(crate) fn list_openai_models(&self) -> Result<Vec<String>, ChatGptError> {
+        if self.is_azure() {
+            return Err(ChatGptError::Config(
+                "Azure resources can't list their deployments, set openai_model to the name of one".to_string(),
+            ));
+        }
+        let response = self.transport.send(HttpRequest {
+            method: Method::GET,
+            url: format!("{}/models", self.base_url()),
+            query: self.query_params(),
+            headers: self.auth_headers()?,
+            body: None,
+        })?;
+        let status = response.status;
+        let wait = retry_after(&response.headers);
+        let response: Value = response.json().map_err(|err| {
+            if status.is_success() {
+                ChatGptError::InvalidResponse(err.to_string())
+            } else {
+                ChatGptError::Api {
+                    status: status.as_u16(),
+                    code: None,
+                    message: format!("Unknown error ({})", status),
+                    retry_after: wait,
+                }
+            }
+        })?;
+        if response["error"].is_object() || !status.is_success() {
+            return Err(api_error(status, &response, wait));
+        }
+        let Some(models) = response["data"].as_array() else {
+            return Err(ChatGptError::InvalidResponse(
+                "The response has no list of models".to_string(),
+            ));
+        };
+        let mut models: Vec<String> = models
+            .iter()
+            .filter_map(|model| model["id"].as_str())
+            .filter(|id| !NON_CHAT_MODELS.iter().any(|marker| id.contains(marker)))
+            .map(String::from)
+            .collect();
+        models.sort();
+        Ok(models)
+    }
+
     // Create new session consuming the client
     // FIXME: Change this later to use a reference to a client
     pub fn new_session(self, chatlog: Vec<ChatLogEntry>, max_tokens: u32) -> ChatGPTSession {
@@ -182,65 +2023,252 @@ impl ChatGPTClient {
     pub fn send_request(
         &self,
         messages: impl Iterator<Item = Message>,
-    ) -> Result<ChatLogEntry, Box<dyn std::error::Error>> {
-        let initial_prompt = r#"You are Assistant, a very enthusiastic chatbot. You are chatting with a user.
-            If you don't know the answer to something, say \"I don't know\".\n\n"#;
+        initial_prompt: &str,
+        sampling: &SamplingParams,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        self.send_request_streaming(messages, initial_prompt, sampling, &[], None)
+    }
 
-        let mut messages: Vec<_> = messages.collect();
-        // Prefix first message with initial prompt
-        messages[0].content = format!("{}{}", initial_prompt, messages[0].content);
+    /// Send a request to the backend, streaming the answer to `on_token` as it is generated if given. Errors
+    /// can be passed to other threads, so requests can be made in the background. Requests failing with transient
+    /// errors are tried again as set in the config, unless part of the answer was already streamed.
+    pub fn send_request_streaming(
+        &self,
+        messages: impl Iterator<Item = Message>,
+        initial_prompt: &str,
+        sampling: &SamplingParams,
+        tools: &[Tool],
+        mut on_token: Option<&mut dyn FnMut(&str)>,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        // The initial prompt goes first as the system message
+        let messages: Vec<_> = (!initial_prompt.is_empty())
+            .then(|| Message::new(initial_prompt, "system"))
+            .into_iter()
+            .chain(messages)
+            .collect();
+        let retry = &self.config.retry;
+        let streaming = on_token.is_some();
+        let mut attempt = 1;
+        loop {
+            if self.is_cancelled() {
+                return Err(ChatGptError::Cancelled);
+            }
+            let mut streamed = false;
+            let mut on_token_tracked = |token: &str| {
+                streamed = true;
+                if let Some(on_token) = on_token.as_mut() {
+                    on_token(token);
+                }
+            };
+            let backend = self.config.backend.backend();
+            let request = ChatRequest {
+                messages: &messages,
+                sampling,
+                tools,
+            };
+            let result = if streaming {
+                backend.stream(self, &request, &mut on_token_tracked)
+            } else {
+                backend.send(self, &request)
+            };
+            let err = match result {
+                Err(err) if attempt < retry.max_attempts && !streamed => err,
+                result => return result,
+            };
+            let wait = match retry.backoff(attempt, err.retry_after()) {
+                Some(wait) if err.is_transient() => wait,
+                _ => return Err(err),
+            };
+            if let Some(on_retry) = &self.on_retry {
+                on_retry(&Retry {
+                    attempt,
+                    max_attempts: retry.max_attempts,
+                    wait,
+                    reason: err.to_string(),
+                });
+            }
+            // Waited out in steps, so that cancelling doesn't have to wait for the next attempt
+            let until = std::time::Instant::now() + wait;
+            while std::time::Instant::now() < until && !self.is_cancelled() {
+                std::thread::sleep(CANCEL_POLL.min(until - std::time::Instant::now()));
+            }
+            attempt += 1;
+        }
+    }
 
+    // Make a single attempt at a request to the chat completions API
+    pub(crate) fn send_request_once(
+        &self,
+        messages: Vec<Message>,
+        sampling: &SamplingParams,
+        tools: &[Tool],
+        on_token: Option<&mut dyn FnMut(&str)>,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
         let request: ChatGPTRequest = ChatGPTRequest {
             model: self.config.openai_model.clone(),
             messages,
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+            presence_penalty: sampling.presence_penalty,
+            frequency_penalty: sampling.frequency_penalty,
+            max_tokens: sampling.max_response_tokens,
+            stream: on_token.is_some(),
+            functions: tools.iter().map(Tool::function_spec).collect(),
         };
 
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            AUTHORIZATION,
-            format!("Bearer {}", self.config.openai_api_key)
-                .parse()
-                .unwrap(),
-        );
-
-        headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
-        let json_data = serde_json::to_string(&request).unwrap();
-        let response = self
-            .client
-            .post("https://api.openai.com/v1/chat/completions".to_string())
-            .headers(headers)
-            .body(json_data)
-            .send()
-            .unwrap()
-            .json::<serde_json::Value>()
-            .unwrap();
+        let response = self.post_json(self.completions_url(), self.auth_headers()?, &request)?;
+        let status = response.status;
+        let wait = retry_after(&response.headers);
+        let response_headers = metadata_headers(&response.headers);
+        let is_event_stream = response
+            .headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        // Errors are reported as regular responses even when streaming was requested
+        let response = match on_token {
+            Some(on_token) if is_event_stream => read_event_stream(
+                response.body,
+                &self.config.openai_model,
+                &request.messages,
+                on_token,
+                self.cancelled.as_deref(),
+            )?,
+            // Proxies in front of the API may answer server errors with a page that isn't JSON
+            _ => response.json().map_err(|err| {
+                if status.is_server_error() {
+                    ChatGptError::Api {
+                        status: status.as_u16(),
+                        code: None,
+                        message: format!("Unknown error ({})", status),
+                        retry_after: wait,
+                    }
+                } else {
+                    ChatGptError::InvalidResponse(err.to_string())
+                }
+            })?,
+        };
 
         // if the response is an error, cast it into an error and return Err()
-        if response["error"].is_object() {
-            let error = response["error"]["message"].as_str().unwrap();
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                error,
-            )));
-        }
-        // Create the ChatLogEntry from the response
-        let prompt_tokens = response["usage"]["prompt_tokens"].as_i64().unwrap();
-        let answer_tokens = response["usage"]["completion_tokens"].as_i64().unwrap();
+        if response["error"].is_object() || status.is_server_error() {
+            return Err(api_error(status, &response, wait));
+        }
+        self.entry_from_response(&request.messages, &response, response_headers)
+    }
+
+    // Send a JSON body to an endpoint with the query parameters of the config, compressed in low-bandwidth mode
+    pub(crate) fn post_json(
+        &self,
+        url: String,
+        mut headers: HeaderMap,
+        body: &impl Serialize,
+    ) -> Result<HttpResponse, ChatGptError> {
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let mut body = serde_json::to_vec(body)?;
+        if self.low_bandwidth && self.config.low_bandwidth.compress_requests {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            body = encoder
+                .write_all(&body)
+                .and_then(|_| encoder.finish())
+                .map_err(|err| ChatGptError::Network(err.to_string()))?;
+            headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        self.transport.send(HttpRequest {
+            method: Method::POST,
+            url,
+            query: self.query_params(),
+            headers,
+            body: Some(body),
+        })
+    }
+
+    /// Turn a response in the shape of one from the chat completions API into a chat log entry and its metadata,
+    /// given the messages that were sent. Other backends convert their responses to that shape first.
+    pub(crate) fn entry_from_response(
+        &self,
+        messages: &[Message],
+        response: &Value,
+        response_headers: Vec<(String, String)>,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        if !response["choices"][0]["message"].is_object() {
+            return Err(ChatGptError::InvalidResponse(
+                "The response has no message".to_string(),
+            ));
+        }
+        // Create the ChatLogEntry from the response. Servers that don't report usage have it counted with the
+        // tokenizer of the model.
+        let model = &self.config.openai_model;
+        let prompt_tokens = response["usage"]["prompt_tokens"]
+            .as_i64()
+            .unwrap_or_else(|| {
+                TOKENS_PER_REQUEST as i64
+                    + messages
+                        .iter()
+                        .map(|message| count_message_tokens(model, &message.content) as i64)
+                        .sum::<i64>()
+            });
+        let answer_tokens = response["usage"]["completion_tokens"]
+            .as_i64()
+            .unwrap_or_else(|| {
+                count_tokens(
+                    model,
+                    response["choices"][0]["message"]["content"]
+                        .as_str()
+                        .unwrap_or_default(),
+                ) as i64
+            });
+        // There is no answer when the model asks to run a tool
         let answer = response["choices"][0]["message"]["content"]
             .as_str()
-            .unwrap();
+            .unwrap_or_default();
         let answer = Message::new(answer, "assistant");
-        let prompt = Message::new(
-            &request.messages[request.messages.len() - 1].content,
-            "user",
-        );
+        let prompt = Message::new(&messages[messages.len() - 1].content, "user");
         let entry = ChatLogEntry {
-            message: prompt.content.replace(initial_prompt, ""),
-            response: answer.content,
+            message: prompt.content,
+            response: answer.content.clone(),
             num_tokens_message: prompt_tokens as u32,
             num_tokens_response: answer_tokens as u32,
+            sent: None,
+            pinned: false,
+            variants: Vec::new(),
+            // The output of a requested tool is filled in once it has run
+            tool_calls: response["choices"][0]["message"]["function_call"]["name"]
+                .as_str()
+                .map(|name| ToolCall {
+                    name: name.to_string(),
+                    arguments: response["choices"][0]["message"]["function_call"]["arguments"]
+                        .as_str()
+                        .unwrap_or("{}")
+                        .to_string(),
+                    plan: answer.content.clone(),
+                    output: String::new(),
+                    raw_results: None,
+                    num_tokens_message: prompt_tokens as u32,
+                    num_tokens_response: answer_tokens as u32,
+                })
+                .into_iter()
+                .collect(),
+            heading: None,
+            // Set once the exchange is added to the chat log
+            timestamp: None,
+            model: None,
+            rating: None,
+            note: None,
+            full_response: None,
+        };
+        let metadata = ResponseMetadata {
+            id: response["id"].as_str().map(String::from),
+            model: response["model"].as_str().map(String::from),
+            created: response["created"].as_i64(),
+            finish_reason: response["choices"][0]["finish_reason"]
+                .as_str()
+                .map(String::from),
+            prompt_tokens: Some(prompt_tokens),
+            completion_tokens: Some(answer_tokens),
+            total_tokens: response["usage"]["total_tokens"].as_i64(),
+            headers: response_headers,
         };
 
-        Ok(entry)
+        Ok((entry, metadata))
     }
 }
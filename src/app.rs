@@ -1,69 +1,1318 @@
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use chrono::{DateTime, Local};
+use crossterm::cursor::Show;
+use crossterm::event::{
+    DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEvent, KeyModifiers,
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, is_raw_mode_enabled, EnterAlternateScreen,
     LeaveAlternateScreen,
 };
 use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, VecDeque};
 use std::fmt::Display;
-use std::io;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 use tui::backend::CrosstermBackend;
-use tui::layout::{Alignment, Constraint, Direction, Layout};
+use tui::buffer::Buffer;
+use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, Paragraph};
+use tui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Widget, Wrap};
 
 use tui::Terminal;
-use tui_textarea::{CursorMove, Input, Key, TextArea};
+use tui_textarea::{CursorMove, Input, Key, Scrolling, TextArea};
+use unicode_bidi::{BidiInfo, Level};
+
+use crate::api::{
+    truncate_response, ApiErrorKind, ChatGPTClient, ChatGPTSession, ChatLogEntry, PendingRequest,
+    Rating, ResponseMetadata, SessionFile, Variant,
+};
+use crate::attachments::{attach, Attachment};
+use crate::autosave::{Autosave, AutosavedSession};
+use crate::backend::BackendKind;
+use crate::calculator::CALCULATOR_TOOL;
+use crate::clipboard::Clipboard;
+use crate::commands::{parse_command, COMMANDS};
+use crate::completion::{complete_path, mentioned_paths, path_word};
+use crate::diff::{unified_diff, FileEdit};
+use crate::errors::{ChatGptError, ErrorReport};
+use crate::events::{Event, EventLog};
+use crate::export::ExportFormat;
+use crate::fence::{detect_language, fence_code, looks_like_code};
+use crate::history::PromptHistory;
+use crate::lint::lint_prompt;
+use crate::locale::Locale;
+use crate::markdown::{code_blocks, fenced_blocks, line_styles, render_markdown};
+use crate::memory::{Memory, MemoryStore};
+use crate::present::Presentation;
+use crate::retry::Retry;
+use crate::search::cited_results;
+use crate::sessions::{SessionManager, SessionSummary};
+use crate::snippets::{save_code, suggest_path};
+use crate::stats::TextStats;
+use crate::tokens::count_tokens;
+use crate::tools::{available_tools, ToolCall};
+use crate::tts::ReadAloud;
+use crate::usage::{Usage, UsageStore};
+use crate::whitespace::normalize_whitespace;
+use crate::{ChatColors, Persona, SendKey};
 
-use crate::api::{ChatGPTClient, ChatGPTSession, ChatLogEntry};
+// Number of recent sessions listed on the start screen
+const NUM_RECENT_SESSIONS: usize = 10;
+// How often the screen is redrawn while reading aloud, to keep the progress current
+const READ_ALOUD_REFRESH: Duration = Duration::from_millis(250);
+// How often the spinner moves and streamed tokens are shown while waiting for an answer
+const REQUEST_REFRESH: Duration = Duration::from_millis(100);
+// Longest time quitting may take before the app exits anyway, unless the config sets another one
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+// How long quitting waits for a cancelled request to stop before leaving it behind
+const REQUEST_STOP_WAIT: Duration = Duration::from_secs(1);
+// Lines of the output of a tool shown in an expanded step, the rest can be seen with F3
+const MAX_STEP_OUTPUT_LINES: usize = 8;
+// Status and error messages kept for /log, the oldest are dropped
+const MAX_STATUS_LOG: usize = 200;
+// Width of the outline beside the chat log, and how many exchanges PageUp and PageDown move through in it
+const OUTLINE_WIDTH: u16 = 32;
+const OUTLINE_PAGE: usize = 10;
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 #[derive(Debug, Clone)]
 pub enum UiEvent {
     Quit,
     SendMessage(String),
+    // Continue with the checks done before sending a message, starting from the given one
+    ContinueSending(String, usize),
+    // Pin or summarize exchanges that would be dropped from the context, then continue sending
+    PinAndContinueSending(Vec<usize>, String, usize),
+    SummarizeAndContinueSending(Vec<usize>, String, usize),
+    // Have the model rephrase a message blocked by the content filter, then send it again
+    RephraseAndRetry(String),
     SaveSession,
-    // Help(String),
+    // Save the conversation as a document to the given path
+    Export(ExportFormat, String),
+    // Mark the current point in the conversation, or return to a marked point
+    Checkpoint(String),
+    Rollback(String),
+    // Save the conversation and continue from a marked point as a new session
+    Fork(String),
+    // Turn A/B mode on with the given variants, or off
+    AbMode(String),
+    // Turn low-bandwidth mode on if it is off, or off if it is on
+    ToggleLowBandwidth,
+    // Use another system prompt for this session
+    SetSystemPrompt(String),
+    // Copy the response at the cursor to the clipboard, or only its last code block if true
+    Copy(bool),
+    // Save a code block of the response at the cursor, counting from 1 and the last if not given, to a file. Without
+    // a path, one is suggested to edit first.
+    ExtractCode(Option<usize>, Option<String>),
+    // Write code to a file, replacing it if it exists
+    SaveCode(String, String),
+    // Send the contents of a file along with the next message
+    AttachFile(String),
+    // Run a command and send its output along with the next message
+    RunCommand(String),
+    SetTitle(String),
+    // Rate the exchange at the cursor or attach a note to it, removing the rating or note with None
+    Rate(Option<Rating>),
+    Note(Option<String>),
+    AddTag(String),
+    RemoveTag(String),
+    // Save the current session and open a saved one in its place
+    OpenSession(String),
+    // List the saved sessions to switch between them
+    ShowSessions,
+    // Save the current session and start a new one
+    NewSession,
+    // Delete a saved session, overwriting its files first if true
+    DeleteSession(String, bool),
+    // Bind recorded keys to Alt and a digit
+    BindMacro(char, Vec<Input>),
+    // List the slash commands
+    Help,
+    SaveSessionAs(String),
+    // Start a new conversation
+    Reset,
+    SetModel(String),
+    // List the models the API key can use to pick one for this session
+    ShowModels,
+    // Send the following messages with a profile from the config, or the main settings if None
+    SetProfile(Option<String>),
+    // Send the following messages to another backend, given its name and optionally a model
+    SetBackend(String),
+    // List the profiles to switch between them
+    ShowProfiles,
+    // Change a sampling parameter, given its name and value
+    SetSampling(String, String),
+    // List the recent status and error messages
+    ShowLog,
+    // Show the tokens used and their estimated cost in this session and across all sessions
+    ShowUsage,
+    // Show the whole of a response that was cut to max_response_chars
+    ShowFullResponse,
+    // Show the word and character counts of the exchange at the cursor
+    ShowStats,
+    // Open the outline of the session beside the chat log
+    ShowOutline,
+    // Have the model write headings for the outline
+    GenerateHeadings,
+    // Show the changes in a new version of a file given by the model, then write it if they are approved
+    ReviewEdit(FileEdit),
+    ApplyEdit(FileEdit),
+    // Run the tool the model asked for while answering
+    ApproveTool,
+    // Have the model work towards a goal, running tools step by step
+    Agent(String),
+    // Send the last message again for another answer in place of the last one
+    Regenerate,
+    // List the messages held back while the account is out of quota, send them or drop them
+    ShowQueue,
+    SendQueue,
+    ClearQueue,
+    // Remember a fact about the user, or have the model pick some out of the conversation if None
+    Remember(Option<String>),
+    // Ask whether to remember the first of the facts picked out by the model, then the rest
+    ReviewMemories(Vec<String>),
+    // Remember the first of the facts picked out by the model and ask about the rest
+    ApproveMemory(Vec<String>),
+    // List the remembered facts, forget one of them counting from 1, or forget them all
+    ShowMemories,
+    ForgetMemory(usize),
+    ClearMemories,
+    // Serve the chat log as a web page on localhost, or stop serving it
+    Present,
+    StopPresenting,
+}
+
+impl UiEvent {
+    // Whether handling the event may change the chat log or the metadata of the session, which then has to be
+    // autosaved again
+    fn changes_session(&self) -> bool {
+        matches!(
+            self,
+            UiEvent::SendMessage(_)
+                | UiEvent::ContinueSending(..)
+                | UiEvent::PinAndContinueSending(..)
+                | UiEvent::SummarizeAndContinueSending(..)
+                | UiEvent::RephraseAndRetry(_)
+                | UiEvent::Checkpoint(_)
+                | UiEvent::Rollback(_)
+                | UiEvent::Fork(_)
+                | UiEvent::AbMode(_)
+                | UiEvent::SetSystemPrompt(_)
+                | UiEvent::SetTitle(_)
+                | UiEvent::Rate(_)
+                | UiEvent::Note(_)
+                | UiEvent::AddTag(_)
+                | UiEvent::RemoveTag(_)
+                | UiEvent::OpenSession(_)
+                | UiEvent::NewSession
+                | UiEvent::SaveSessionAs(_)
+                | UiEvent::Reset
+                | UiEvent::SetModel(_)
+                | UiEvent::SetProfile(_)
+                | UiEvent::SetBackend(_)
+                | UiEvent::SetSampling(..)
+                | UiEvent::GenerateHeadings
+                | UiEvent::Agent(_)
+                | UiEvent::Regenerate
+                | UiEvent::SendQueue
+        )
+    }
+}
+
+// Entries that can be picked on the start screen
+enum StartEntry {
+    // The session left behind by a run that crashed
+    Recover(AutosavedSession),
+    NewConversation,
+    Persona(Persona),
+    Session(SessionSummary),
+}
+
+impl Display for StartEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartEntry::Recover(autosaved) => write!(
+                f,
+                "Recover the session autosaved at {} ({} messages)",
+                autosaved.saved.format("%Y-%m-%d %H:%M"),
+                autosaved.file.entries.len()
+            ),
+            StartEntry::NewConversation => write!(f, "New conversation"),
+            StartEntry::Persona(persona) => {
+                write!(f, "New conversation as {}", persona.name)?;
+                match &persona.model {
+                    Some(model) => write!(f, " ({})", model),
+                    None => Ok(()),
+                }
+            }
+            StartEntry::Session(session) => write!(f, "{}", session),
+        }
+    }
+}
+
+// Whether a paragraph is written in a right-to-left script such as Arabic or Hebrew
+fn is_right_to_left(paragraph: &str) -> bool {
+    BidiInfo::new(paragraph, None)
+        .paragraphs
+        .first()
+        .is_some_and(|para| para.level.is_rtl())
+}
+
+// Terminals draw characters strictly left to right, so right-to-left lines are reordered into their visual order
+// and right-aligned within the given width
+fn right_to_left_display(line: &str, width: usize) -> String {
+    let bidi_info = BidiInfo::new(line, Some(Level::rtl()));
+    let visual = match bidi_info.paragraphs.first() {
+        Some(para) => bidi_info.reorder_line(para, para.range.clone()),
+        None => return line.to_string(),
+    };
+    let padding = width.saturating_sub(textwrap::core::display_width(&visual));
+    format!("{}{}", " ".repeat(padding), visual)
+}
+
+// Wrap a paragraph to the given width, laying out right-to-left text for display
+fn wrap_paragraph(paragraph: &str, width: usize) -> Vec<String> {
+    let rtl = is_right_to_left(paragraph);
+    textwrap::wrap(paragraph, width)
+        .into_iter()
+        .map(|line| {
+            if rtl {
+                right_to_left_display(&line, width)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect()
+}
+
+// Single line preview of a message, truncated to the given number of characters
+fn preview(text: &str, max_chars: usize) -> String {
+    let text = text.replace('\n', " ");
+    if text.chars().count() > max_chars {
+        format!("{}...", text.chars().take(max_chars).collect::<String>())
+    } else {
+        text
+    }
+}
+
+// A check done before sending a message, see `ChatTermApp::SEND_CHECKS`
+type SendCheck<'a> = fn(&ChatTermApp<'a>, &str, usize) -> Option<Question>;
+
+// Result of a request made in the background, along with the request so that its answer can be added to the session
+type RequestResult = (
+    PendingRequest,
+    Result<(ChatLogEntry, ResponseMetadata), ChatGptError>,
+);
+
+// A message being sent in the background while the UI keeps handling input
+struct InFlight<'a> {
+    message: String,
+    // Message area before the exchange, below which the answer so far is shown
+    chat_log: TextArea<'a>,
+    answer: String,
+    tokens: mpsc::Receiver<String>,
+    // Tools the model asked to run that need approval, each with the number of the step and where to send the answer
+    approvals: mpsc::Receiver<(ToolCall, usize, mpsc::Sender<bool>)>,
+    // Where to send the answer to the approval being asked for
+    approval: Option<mpsc::Sender<bool>>,
+    handle: thread::JoinHandle<RequestResult>,
+    started: Instant,
+    retries: mpsc::Receiver<Retry>,
+    // The last failed attempt that is being retried, and when it failed
+    retry: Option<(Retry, Instant)>,
+    // Exchange whose answer is being regenerated, which is put back if the request fails
+    replacing: Option<ChatLogEntry>,
+    // Set to have the request stop, after which its answer is ignored
+    cancelled: Arc<AtomicBool>,
+}
+
+// A command run with /run in the background, whose output is attached to the next message once it finishes
+struct RunningCommand {
+    command: String,
+    started: Instant,
+    handle: thread::JoinHandle<Result<Attachment, String>>,
+}
+
+// A question shown in place of the help line, answered by pressing the key of one of its choices. Any other key
+// dismisses it and puts `restore_input` back into the input box.
+struct Question {
+    text: String,
+    choices: Vec<(char, UiEvent)>,
+    restore_input: Option<String>,
+}
+
+// Whether a key is used by a search once its query is typed: jumping between matches, typing another query or
+// stopping
+fn is_search_key(input: &Input) -> bool {
+    matches!(
+        input,
+        Input {
+            key: Key::Char('n' | 'N'),
+            ctrl: false,
+            alt: false,
+        } | Input {
+            key: Key::Char('f'),
+            ctrl: true,
+            alt: false,
+        } | Input { key: Key::Esc, .. }
+    )
+}
+
+// Whether a key controls macros rather than being recorded in one. Scrolling is left out too.
+fn is_macro_control(input: &Input) -> bool {
+    matches!(
+        input,
+        Input {
+            key: Key::F(7) | Key::Null | Key::MouseScrollDown | Key::MouseScrollUp,
+            ..
+        } | Input {
+            key: Key::Char('0'..='9'),
+            alt: true,
+            ..
+        }
+    )
+}
+
+// Whether all characters of the query appear in the text in the same order, ignoring case
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| text.any(|c| c == q))
+}
+
+// What happens when an entry of the command palette is picked
+enum PaletteAction {
+    Event(UiEvent),
+    // Put text into the input box, e.g. a command that takes an argument or a previous prompt
+    Insert(String),
+}
+
+struct PaletteEntry {
+    label: String,
+    action: PaletteAction,
+    // Prompt from the history this entry stands for, which can be marked as a favorite
+    prompt: Option<String>,
+}
+
+// Commands and previous prompts searchable by typing, opened with Ctrl+P
+struct Palette {
+    query: String,
+    state: ListState,
+    // Saved sessions, listed once when the palette is opened
+    sessions: Vec<SessionSummary>,
+}
+
+impl Palette {
+    fn open(sessions: &SessionManager) -> Self {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Self {
+            query: String::new(),
+            state,
+            // Sessions that can't be listed are left out rather than failing to open the palette
+            sessions: sessions.list().unwrap_or_default(),
+        }
+    }
+}
+
+// Saved sessions to switch between, create or delete, opened with F8
+struct SessionPicker {
+    sessions: Vec<SessionSummary>,
+    state: ListState,
+}
+
+impl SessionPicker {
+    fn open(sessions: &SessionManager) -> io::Result<Self> {
+        let mut state = ListState::default();
+        state.select(Some(0));
+        Ok(Self {
+            sessions: sessions.list()?,
+            state,
+        })
+    }
+}
+
+// Models the API key can use, to pick one for the session, opened with /models
+struct ModelPicker {
+    models: Vec<String>,
+    state: ListState,
+}
+
+// Profiles from the config to switch between, each with its label, opened with /profile
+struct ProfilePicker {
+    profiles: Vec<(Option<String>, String)>,
+    state: ListState,
+}
+
+// Outline of the session beside the chat log with a line for each exchange, opened with F2
+struct Outline {
+    state: ListState,
+    // Cursor of the chat log when the outline was opened, which Esc goes back to
+    cursor: (usize, usize),
+}
+
+// Incremental search in the chat log, opened with Ctrl+F, or / in read-only mode
+struct Search {
+    query: String,
+    // Whether the query is still being typed, rather than jumping between the matches with n and N
+    editing: bool,
+    // Rows and character columns where the query was found in the chat log, in order
+    matches: Vec<(usize, usize)>,
+    // Index of the match the cursor was moved to
+    current: Option<usize>,
+    // Cursor of the chat log when the search was opened, which Esc goes back to while typing
+    cursor: (usize, usize),
+}
+
+impl Search {
+    // Find the query in the lines of the chat log, ignoring case unless it has capitals like in vim
+    fn find(&mut self, lines: &[String]) {
+        self.matches.clear();
+        let ignore_case = !self.query.chars().any(char::is_uppercase);
+        let fold = |c: char| {
+            if ignore_case {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                c
+            }
+        };
+        let query: Vec<char> = self.query.chars().map(fold).collect();
+        if !query.is_empty() {
+            for (row, line) in lines.iter().enumerate() {
+                let chars: Vec<char> = line.chars().map(fold).collect();
+                let mut col = 0;
+                while col + query.len() <= chars.len() {
+                    if chars[col..col + query.len()] == query[..] {
+                        self.matches.push((row, col));
+                        col += query.len();
+                    } else {
+                        col += 1;
+                    }
+                }
+            }
+        }
+        self.current = self.current.filter(|&current| current < self.matches.len());
+    }
+
+    // Index of the first match at or after a position in the chat log, wrapping around to the first one
+    fn first_from(&self, position: (usize, usize)) -> Option<usize> {
+        if self.matches.is_empty() {
+            return None;
+        }
+        Some(
+            self.matches
+                .iter()
+                .position(|&found| found >= position)
+                .unwrap_or(0),
+        )
+    }
+
+    // Line shown at the bottom while searching
+    fn status(&self) -> String {
+        let count = match (self.current, self.matches.len()) {
+            (_, 0) => String::from("no matches"),
+            (Some(current), total) => format!("{} of {} matches", current + 1, total),
+            (None, total) => format!("{} matches", total),
+        };
+        if self.editing {
+            format!(
+                "Search: {}  ({}, Enter to keep, Esc to cancel)",
+                self.query, count
+            )
+        } else {
+            format!(
+                "{} for \"{}\". Press n or N to jump between them, Esc to stop",
+                count, self.query
+            )
+        }
+    }
+}
+
+// Line of the outline for an exchange: its heading, or else the start of the message
+fn outline_title(entry: &ChatLogEntry) -> String {
+    match &entry.heading {
+        Some(heading) => heading.clone(),
+        None => entry
+            .message
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+// Paths offered above the input box for the word at the cursor
+struct PathCompletion {
+    row: usize,
+    // Characters of the line that are replaced by the picked path
+    word: Range<usize>,
+    candidates: Vec<String>,
+    state: ListState,
+}
+
+// A window drawn over the chat, closed by pressing any key
+struct Popup {
+    title: String,
+    lines: Vec<String>,
+    // Lines scrolled past with the arrow keys or the mouse wheel
+    scroll: u16,
+    // Keys that close the popup with an event, any other key just closes it
+    choices: Vec<(char, UiEvent)>,
+}
+
+impl Popup {
+    // List of the slash commands
+    fn help() -> Self {
+        // Descriptions are aligned in a column, except for long usages which get their own line
+        const WIDTH: usize = 20;
+        let mut lines = Vec::new();
+        for command in COMMANDS {
+            let usage = command.usage();
+            if usage.len() > WIDTH {
+                lines.push(usage);
+                lines.push(format!("{:WIDTH$}  {}", "", command.description));
+            } else {
+                lines.push(format!("{:WIDTH$}  {}", usage, command.description));
+            }
+        }
+        Self {
+            title: String::from("Commands"),
+            lines,
+            scroll: 0,
+            choices: Vec::new(),
+        }
+    }
+
+    // Changes the model made to a file, to apply them once reviewed
+    fn edit(edit: &FileEdit) -> Self {
+        Self {
+            title: format!("Changes to {}, y to apply", edit.path),
+            lines: unified_diff(&edit.path, &edit.original, &edit.text),
+            scroll: 0,
+            choices: vec![('y', UiEvent::ApplyEdit(edit.clone()))],
+        }
+    }
+
+    // Status and error messages shown so far, the latest first
+    fn status_log(log: &VecDeque<(DateTime<Local>, String)>, locale: &Locale) -> Self {
+        let lines = if log.is_empty() {
+            vec![String::from("No messages yet")]
+        } else {
+            log.iter()
+                .rev()
+                .map(|(time, message)| format!("{}  {}", locale.time_with_seconds(time), message))
+                .collect()
+        };
+        Self {
+            title: String::from("Messages"),
+            lines,
+            scroll: 0,
+            choices: Vec::new(),
+        }
+    }
+
+    // Messages held back while the account is out of quota, in the order they will be sent
+    fn queue(queued: &VecDeque<String>) -> Self {
+        let lines = if queued.is_empty() {
+            vec![String::from("No messages are queued")]
+        } else {
+            queued
+                .iter()
+                .enumerate()
+                .map(|(index, message)| format!("{}. {}", index + 1, preview(message, 200)))
+                .collect()
+        };
+        Self {
+            title: String::from("Queued messages"),
+            lines,
+            scroll: 0,
+            choices: Vec::new(),
+        }
+    }
+
+    // Tokens used and their estimated cost in this session and over longer periods across all sessions
+    fn usage(
+        session: Usage,
+        session_cost: Option<f64>,
+        store: &UsageStore,
+        locale: &Locale,
+    ) -> Self {
+        let line = |period: &str, usage: Usage, cost: Option<f64>| {
+            format!(
+                "{:<13} {:>6} requests {:>12} prompt + {:>12} completion tokens  {}",
+                period,
+                locale.number(usage.requests as u64),
+                locale.number(usage.prompt_tokens),
+                locale.number(usage.completion_tokens),
+                cost.map_or(String::from("$?"), |cost| locale.dollars(cost, 4))
+            )
+        };
+        let mut lines = vec![line("This session", session, session_cost), String::new()];
+        for (period, usage) in [
+            ("Today", store.today()),
+            ("This week", store.this_week()),
+            ("This month", store.this_month()),
+            ("All time", store.all_time()),
+        ] {
+            lines.push(line(period, usage, Some(usage.cost)));
+        }
+        lines.push(String::new());
+        lines.push(String::from(
+            "Costs are estimates, counting only models with a known price",
+        ));
+        Self {
+            title: String::from("Usage"),
+            lines,
+            scroll: 0,
+            choices: Vec::new(),
+        }
+    }
+
+    fn memories(memories: &[Memory], locale: &Locale) -> Self {
+        let lines = if memories.is_empty() {
+            vec![String::from(
+                "Nothing is remembered yet. Add facts with /remember",
+            )]
+        } else {
+            memories
+                .iter()
+                .enumerate()
+                .map(|(index, memory)| {
+                    format!(
+                        "{}. {} ({})",
+                        index + 1,
+                        memory.fact,
+                        memory.added.format(locale.date_format)
+                    )
+                })
+                .collect()
+        };
+        Self {
+            title: String::from("Remembered about you (/memories forget <number>)"),
+            lines,
+            scroll: 0,
+            choices: Vec::new(),
+        }
+    }
+
+    // Show exactly what was sent to the API for an exchange
+    fn inspect_entry(chatlog: &[ChatLogEntry], index: usize, locale: &Locale) -> Self {
+        let entry = &chatlog[index];
+        let mut lines = vec![
+            format!("Exchange {} of {}", index + 1, chatlog.len()),
+            format!(
+                "API usage: {} prompt tokens, {} completion tokens",
+                locale.number(entry.num_tokens_message as u64),
+                locale.number(entry.num_tokens_response as u64)
+            ),
+            format!(
+                "Message: {}",
+                TextStats::of(&entry.message).describe(locale)
+            ),
+            format!(
+                "Response: {}",
+                TextStats::of(&entry.response).describe(locale)
+            ),
+            String::new(),
+        ];
+        if !entry.tool_calls.is_empty() {
+            lines.push(format!("Tools run ({}):", entry.tool_calls.len()));
+            for call in entry.tool_calls.iter() {
+                lines.push(format!(
+                    "  {} {}  ->  {}",
+                    call.name,
+                    preview(&call.arguments, 30),
+                    preview(&call.output, 40)
+                ));
+            }
+            lines.push(String::new());
+        }
+        match &entry.sent {
+            None => lines.push(String::from(
+                "What was sent for this exchange was not recorded",
+            )),
+            Some(sent) => {
+                lines.push(String::from("Initial prompt (sent as the system message):"));
+                lines.push(format!("  {}", preview(sent.initial_prompt.trim(), 70)));
+                lines.push(String::new());
+                lines.push(format!("Messages sent ({}):", sent.messages.len()));
+                for message in sent.messages.iter() {
+                    let (label, text) = if message.role == "assistant" {
+                        ("Bot", &chatlog[message.entry].response)
+                    } else {
+                        ("You", &chatlog[message.entry].message)
+                    };
+                    lines.push(format!(
+                        "  #{:<3} {} {:>5} tokens  {}",
+                        message.entry + 1,
+                        label,
+                        message.num_tokens,
+                        preview(text, 50)
+                    ));
+                }
+                let total: u32 = sent.messages.iter().map(|message| message.num_tokens).sum();
+                lines.push(format!(
+                    "Estimated context size: {} tokens",
+                    locale.number(total as u64)
+                ));
+            }
+        }
+        Self {
+            title: String::from("Sent content"),
+            lines,
+            scroll: 0,
+            choices: Vec::new(),
+        }
+    }
+}
+
+// Lines shown in the response metadata panel
+fn response_metadata_lines(metadata: Option<&ResponseMetadata>) -> Vec<String> {
+    let Some(metadata) = metadata else {
+        return vec![String::from("No response received yet")];
+    };
+    let field = |value: Option<String>| value.unwrap_or_else(|| String::from("-"));
+    let mut lines = vec![
+        format!("model: {}", field(metadata.model.clone())),
+        format!("id: {}", field(metadata.id.clone())),
+        format!(
+            "created: {}",
+            field(metadata.created.map(|c| c.to_string()))
+        ),
+        format!("finish_reason: {}", field(metadata.finish_reason.clone())),
+        format!(
+            "usage: {} prompt, {} completion, {} total",
+            field(metadata.prompt_tokens.map(|t| t.to_string())),
+            field(metadata.completion_tokens.map(|t| t.to_string())),
+            field(metadata.total_tokens.map(|t| t.to_string())),
+        ),
+        String::new(),
+        String::from("headers:"),
+    ];
+    lines.extend(
+        metadata
+            .headers
+            .iter()
+            .map(|(name, value)| format!("  {}: {}", name, value)),
+    );
+    lines
+}
+
+// Area of the given percentage size centered within another area
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = area.height * percent_y / 100;
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+// Row and column of the chat log shown at the top left of the message area when it was last drawn. The text area
+// keeps them to itself, but moving the cursor into view from the top left corner lands on them.
+fn scroll_position(message_area: &mut TextArea) -> (usize, usize) {
+    let (row, col) = message_area.cursor();
+    message_area.move_cursor(CursorMove::Jump(0, 0));
+    message_area.move_cursor(CursorMove::InViewport);
+    let top_row = message_area.cursor().0;
+    message_area.move_cursor(CursorMove::Jump(row as u16, 0));
+    message_area.move_cursor(CursorMove::InViewport);
+    let top_col = message_area.cursor().1;
+    message_area.move_cursor(CursorMove::Jump(row as u16, col as u16));
+    (top_row, top_col)
+}
+
+// A color of the config: a name such as "cyan" or "light_green", a number of the 256-color palette, or "#rrggbb"
+fn parse_color(name: &str) -> Option<Color> {
+    let name = name.trim().to_lowercase().replace(['-', ' '], "_");
+    if let Some(hex) = name.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        return Some(Color::Rgb(
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ));
+    }
+    if let Ok(index) = name.parse::<u8>() {
+        return Some(Color::Indexed(index));
+    }
+    Some(match name.as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "dark_gray" | "dark_grey" => Color::DarkGray,
+        "light_red" => Color::LightRed,
+        "light_green" => Color::LightGreen,
+        "light_yellow" => Color::LightYellow,
+        "light_blue" => Color::LightBlue,
+        "light_magenta" => Color::LightMagenta,
+        "light_cyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    })
+}
+
+// Colors of the messages in the chat log by who wrote them. Colors the config doesn't know are left as they were.
+#[derive(Debug, Clone, Copy)]
+struct RoleColors {
+    user: Color,
+    assistant: Color,
+}
+
+impl RoleColors {
+    fn from_config(colors: &ChatColors) -> Self {
+        Self {
+            user: parse_color(&colors.user).unwrap_or(Color::Cyan),
+            assistant: parse_color(&colors.assistant).unwrap_or(Color::White),
+        }
+    }
+}
+
+// Colors the messages in the message area by who wrote them, with their labels in bold. Drawn over the text area
+// before the styles of the markdown, which take precedence.
+struct RoleStyles<'a> {
+    lines: &'a [String],
+    colors: RoleColors,
+    cursor: (usize, usize),
+    top_row: usize,
+    top_col: usize,
+}
+
+impl<'a> Widget for RoleStyles<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = Block::default().borders(Borders::ALL).inner(area);
+        let bottom_row = (self.top_row + area.height as usize).min(self.lines.len());
+        // Lines continuing a message have a blank prefix, and tool steps and annotations keep the default color
+        let mut color = None;
+        for (row, line) in self.lines[..bottom_row].iter().enumerate() {
+            let prefix: String = line.chars().take(5).collect();
+            let labelled = !prefix.trim().is_empty();
+            if labelled {
+                color = if prefix == "You: " {
+                    Some(self.colors.user)
+                } else if prefix.trim_end().ends_with(':') {
+                    Some(self.colors.assistant)
+                } else {
+                    None
+                };
+            }
+            let Some(color) = color else {
+                continue;
+            };
+            if row < self.top_row {
+                continue;
+            }
+            let y = area.y + (row - self.top_row) as u16;
+            let mut x = 0;
+            for (index, c) in line.chars().enumerate() {
+                let width = textwrap::core::display_width(&c.to_string());
+                // The cursor keeps its own style
+                if x >= self.top_col
+                    && x - self.top_col < area.width as usize
+                    && (row, index) != self.cursor
+                {
+                    let mut style = Style::default().fg(color);
+                    if labelled && index < 5 {
+                        style = style.add_modifier(Modifier::BOLD);
+                    }
+                    buf.get_mut(area.x + (x - self.top_col) as u16, y)
+                        .set_style(style);
+                }
+                x += width;
+            }
+        }
+    }
+}
+
+// Styles the markdown of the responses shown in the message area, drawn over the text area since it can only
+// style the text as a whole
+struct ResponseStyles<'a> {
+    lines: &'a [String],
+    cursor: (usize, usize),
+    top_row: usize,
+    top_col: usize,
+}
+
+impl<'a> Widget for ResponseStyles<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = Block::default().borders(Borders::ALL).inner(area);
+        let bottom_row = (self.top_row + area.height as usize).min(self.lines.len());
+        // Lines continuing a message have a blank prefix, so whether they belong to a response is known from the
+        // last prefix before them
+        let mut in_response = false;
+        for (row, line) in self.lines[..bottom_row].iter().enumerate() {
+            let prefix: String = line.chars().take(5).collect();
+            if !prefix.trim().is_empty() {
+                in_response = prefix != "You: " && prefix.trim_end().ends_with(':');
+            }
+            if row < self.top_row || !in_response {
+                continue;
+            }
+            let y = area.y + (row - self.top_row) as u16;
+            let chars: Vec<char> = line.chars().collect();
+            let content: String = chars.iter().skip(5).collect();
+            for (range, style) in line_styles(&content) {
+                let (start, end) = (5 + range.start, 5 + range.end);
+                let mut x =
+                    textwrap::core::display_width(&chars[..start].iter().collect::<String>());
+                for (index, c) in chars.iter().enumerate().take(end).skip(start) {
+                    let width = textwrap::core::display_width(&c.to_string());
+                    // The cursor keeps its own style
+                    if x >= self.top_col
+                        && x - self.top_col < area.width as usize
+                        && (row, index) != self.cursor
+                    {
+                        let x = area.x + (x - self.top_col) as u16;
+                        buf.get_mut(x, y).set_style(style);
+                    }
+                    x += width;
+                }
+            }
+        }
+    }
+}
+
+// Highlights the matches of a search in the message area, the one jumped to in another color
+struct SearchHighlights<'a> {
+    lines: &'a [String],
+    search: &'a Search,
+    cursor: (usize, usize),
+    top_row: usize,
+    top_col: usize,
+}
+
+impl<'a> Widget for SearchHighlights<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = Block::default().borders(Borders::ALL).inner(area);
+        let len = self.search.query.chars().count();
+        for (index, &(row, col)) in self.search.matches.iter().enumerate() {
+            if row < self.top_row || row >= self.top_row + area.height as usize {
+                continue;
+            }
+            let Some(line) = self.lines.get(row) else {
+                continue;
+            };
+            let style = if Some(index) == self.search.current {
+                Style::default().fg(Color::Black).bg(Color::LightRed)
+            } else {
+                Style::default().fg(Color::Black).bg(Color::Yellow)
+            };
+            let y = area.y + (row - self.top_row) as u16;
+            let chars: Vec<char> = line.chars().collect();
+            let mut x = textwrap::core::display_width(&chars[..col].iter().collect::<String>());
+            for (index, c) in chars.iter().enumerate().skip(col).take(len) {
+                let width = textwrap::core::display_width(&c.to_string());
+                // The cursor keeps its own style
+                if x >= self.top_col
+                    && x - self.top_col < area.width as usize
+                    && (row, index) != self.cursor
+                {
+                    let x = area.x + (x - self.top_col) as u16;
+                    buf.get_mut(x, y).set_style(style);
+                }
+                x += width;
+            }
+        }
+    }
+}
+
+// Room kept at the end of the first line of each message for the time of the exchange
+const TIMESTAMP_WIDTH: usize = 11;
+
+// When an exchange took place, as the time of day for those of today and the date for older ones
+fn timestamp_label(timestamp: &DateTime<Local>, locale: &Locale) -> String {
+    if timestamp.date_naive() == Local::now().date_naive() {
+        locale.time(timestamp)
+    } else {
+        timestamp.format(locale.date_format).to_string()
+    }
+}
+
+// Dimmed times of the exchanges at the right of the first line of their messages, which are wrapped to leave room
+// for them. Messages are matched to exchanges in order, counting from the first line starting with "You: ".
+struct Timestamps<'a> {
+    lines: &'a [String],
+    labels: &'a [String],
+    top_row: usize,
+    top_col: usize,
+}
+
+impl<'a> Widget for Timestamps<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let area = Block::default().borders(Borders::ALL).inner(area);
+        let bottom_row = (self.top_row + area.height as usize).min(self.lines.len());
+        let messages = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.starts_with("You: "));
+        for ((row, line), label) in messages.zip(self.labels) {
+            if row >= bottom_row {
+                break;
+            }
+            let width = textwrap::core::display_width(label);
+            let line_width = textwrap::core::display_width(line).saturating_sub(self.top_col);
+            // Skipped rather than drawn over the message when the chat log is wider than the screen
+            if row < self.top_row || label.is_empty() || line_width + width >= area.width as usize {
+                continue;
+            }
+            let x = area.x + area.width - width as u16;
+            let y = area.y + (row - self.top_row) as u16;
+            buf.set_string(x, y, label, Style::default().fg(Color::DarkGray));
+        }
+    }
+}
+
+// Draw the chat log with the messages colored by who wrote them, the markdown of the responses styled, the times
+// of the exchanges given as `timestamps`, and the matches of a search highlighted
+fn render_message_area(
+    f: &mut tui::Frame<CrosstermBackend<io::Stdout>>,
+    message_area: &mut TextArea,
+    area: Rect,
+    colors: RoleColors,
+    timestamps: &[String],
+    search: Option<&Search>,
+) {
+    f.render_widget(message_area.widget(), area);
+    let (top_row, top_col) = scroll_position(message_area);
+    let roles = RoleStyles {
+        lines: message_area.lines(),
+        colors,
+        cursor: message_area.cursor(),
+        top_row,
+        top_col,
+    };
+    f.render_widget(roles, area);
+    let styles = ResponseStyles {
+        lines: message_area.lines(),
+        cursor: message_area.cursor(),
+        top_row,
+        top_col,
+    };
+    f.render_widget(styles, area);
+    let timestamps = Timestamps {
+        lines: message_area.lines(),
+        labels: timestamps,
+        top_row,
+        top_col,
+    };
+    f.render_widget(timestamps, area);
+    if let Some(search) = search {
+        let highlights = SearchHighlights {
+            lines: message_area.lines(),
+            search,
+            cursor: message_area.cursor(),
+            top_row,
+            top_col,
+        };
+        f.render_widget(highlights, area);
+    }
+}
+
+// How far the chat log is scrolled, like in vim: Top, Bot or a percentage, or nothing if it fits on the screen
+fn scroll_indicator(top_row: usize, height: usize, num_lines: usize) -> Option<String> {
+    if num_lines <= height {
+        None
+    } else if top_row == 0 {
+        Some(String::from("Top"))
+    } else if top_row + height >= num_lines {
+        Some(String::from("Bot"))
+    } else {
+        Some(format!("{}%", top_row * 100 / (num_lines - height)))
+    }
+}
+
+// Input of a terminal event. Input has no room for Shift, so Shift+Enter becomes a typed newline.
+fn key_input(event: crossterm::event::Event) -> Input {
+    match event {
+        crossterm::event::Event::Key(KeyEvent {
+            code: KeyCode::Enter,
+            modifiers: KeyModifiers::SHIFT,
+            ..
+        }) => Input {
+            key: Key::Char('\n'),
+            ctrl: false,
+            alt: false,
+        },
+        event => event.into(),
+    }
 }
 
+// Most lines the input box grows to before it scrolls
+const MAX_INPUT_LINES: usize = 10;
+
 struct ChatEntryBox<'a> {
     textarea: TextArea<'a>,
+    send_key: SendKey,
+    // Sent along with the next message
+    attachments: Vec<Attachment>,
 }
 
-impl<'a> Default for ChatEntryBox<'a> {
-    fn default() -> Self {
+impl<'a> ChatEntryBox<'a> {
+    fn new(send_key: SendKey) -> Self {
         let mut textarea = TextArea::default();
-        textarea.set_block(Block::default().borders(Borders::ALL).title("Input"));
         textarea.set_cursor_line_style(Style::default().fg(Color::Red));
-        Self { textarea }
+        let mut input = Self {
+            textarea,
+            send_key,
+            attachments: Vec::new(),
+        };
+        input.update_title();
+        input
     }
-}
 
-impl<'a> ChatEntryBox<'a> {
     fn clear(&mut self) {
         // Remove input for next input. Do not recreate `self.textarea` instance to keep undo history so that users can
         // restore previous input easily.
-        self.textarea.move_cursor(CursorMove::End);
-        self.textarea.delete_line_by_head();
+        loop {
+            self.textarea.move_cursor(CursorMove::Bottom);
+            self.textarea.move_cursor(CursorMove::End);
+            self.textarea.delete_line_by_head();
+            if !self.textarea.delete_newline() {
+                break;
+            }
+        }
+    }
+
+    // Replace the input with a message that may span several lines
+    fn set_text(&mut self, text: &str) {
+        self.clear();
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.textarea.insert_newline();
+            }
+            self.textarea.insert_str(line);
+        }
+    }
+
+    // Title of the input box, mentioning the send key and any attachments
+    fn title(&self) -> String {
+        let keys = match self.send_key {
+            SendKey::Enter => "Alt+Enter for a new line",
+            SendKey::CtrlEnter => "Ctrl+Enter to send",
+            SendKey::AltEnter => "Alt+Enter to send",
+        };
+        match self.attachments.len() {
+            0 => format!("Input ({})", keys),
+            1 => format!("Input ({}, 1 attachment)", keys),
+            count => format!("Input ({}, {} attachments)", keys, count),
+        }
     }
 
+    fn attach(&mut self, attachment: Attachment) {
+        self.attachments.push(attachment);
+        self.update_title();
+    }
+
+    // The attachments to send with a message, which are then removed
+    fn take_attachments(&mut self) -> Vec<Attachment> {
+        let attachments = std::mem::take(&mut self.attachments);
+        self.update_title();
+        attachments
+    }
+
+    // Whether an input is the key that sends the message
+    fn is_send_key(&self, input: &Input) -> bool {
+        match self.send_key {
+            SendKey::Enter => matches!(
+                input,
+                Input {
+                    key: Key::Enter,
+                    alt: false,
+                    ..
+                }
+            ),
+            // Most terminals report Ctrl+Enter as Ctrl+J
+            SendKey::CtrlEnter => matches!(
+                input,
+                Input {
+                    key: Key::Enter,
+                    ctrl: true,
+                    ..
+                } | Input {
+                    key: Key::Char('j'),
+                    ctrl: true,
+                    alt: false
+                }
+            ),
+            SendKey::AltEnter => matches!(
+                input,
+                Input {
+                    key: Key::Enter,
+                    alt: true,
+                    ..
+                }
+            ),
+        }
+    }
+
+    // Height of the box, which grows with the lines of the input
     fn height(&self) -> u16 {
-        3
+        self.textarea.lines().len().clamp(1, MAX_INPUT_LINES) as u16 + 2
     }
 
     fn input(&mut self, input: Input) -> Option<String> {
+        if self.is_send_key(&input) {
+            let message = self.textarea.lines().join("\n").trim().to_string();
+            self.clear();
+            return Some(message);
+        }
         match input {
+            // Enter inserts a new line when another key sends the message, and Alt+Enter otherwise. Shift+Enter
+            // arrives as a typed newline, which the text area inserts.
             Input {
-                key: Key::Enter, ..
+                key: Key::Enter,
+                alt,
+                ..
             } => {
-                let message = self.textarea.lines()[0].trim().to_string();
-                self.clear();
-                Some(message)
+                if self.send_key != SendKey::Enter || alt {
+                    self.textarea.insert_newline();
+                }
+                None
             }
             Input {
                 key: Key::Char('m'),
                 ctrl: true,
                 ..
             } => None, // Disable shortcuts which inserts a newline. See `single_line` example
+            // Sending clears the input as an edit that can be undone, so the last message can be recalled
+            Input {
+                key: Key::Char('z'),
+                ctrl: true,
+                alt: false,
+            } => {
+                self.textarea.undo();
+                None
+            }
+            Input {
+                key: Key::Char('y'),
+                ctrl: true,
+                alt: false,
+            } => {
+                self.textarea.redo();
+                None
+            }
             input => {
                 self.textarea.input(input);
                 None
@@ -71,16 +1320,9 @@ impl<'a> ChatEntryBox<'a> {
         }
     }
 
-    fn set_error(&mut self, err: Option<impl Display>) {
-        let b = if let Some(err) = err {
-            Block::default()
-                .borders(Borders::ALL)
-                .title(format!("Input: {}", err))
-                .style(Style::default().fg(Color::Red))
-        } else {
-            Block::default().borders(Borders::ALL).title("Input")
-        };
-        self.textarea.set_block(b);
+    fn update_title(&mut self) {
+        self.textarea
+            .set_block(Block::default().borders(Borders::ALL).title(self.title()));
     }
 }
 
@@ -90,73 +1332,2220 @@ struct ChatTermApp<'a> {
     message_area: TextArea<'a>,
     term: Terminal<CrosstermBackend<io::Stdout>>,
     error_message: Option<Cow<'static, str>>,
+    // Files sent to the model whole, with the contents that were sent
+    attached_files: Vec<(String, String)>,
+    // Messages shown in the status line and errors, with the time they were shown, for /log
+    status_log: VecDeque<(DateTime<Local>, String)>,
+    // Set once a request fails for lack of quota, after which messages are queued rather than sent until another
+    // profile is picked or sending is tried again with /queue send
+    out_of_quota: bool,
+    queued: VecDeque<String>,
+    // Shown above the input box until the next request
+    error: Option<ErrorReport>,
     input: ChatEntryBox<'a>,
+    read_aloud: Option<ReadAloud>,
+    running_command: Option<RunningCommand>,
+    // Exits the app if quitting hangs, from when quitting starts until the terminal is restored
+    watchdog: Option<Watchdog>,
+    presentation: Option<Presentation>,
+    popup: Option<Popup>,
+    show_metadata: bool,
+    usage: UsageStore,
+    question: Option<Question>,
+    history: PromptHistory,
+    palette: Option<Palette>,
+    sessions: SessionManager,
+    session_picker: Option<SessionPicker>,
+    model_picker: Option<ModelPicker>,
+    profile_picker: Option<ProfilePicker>,
+    outline: Option<Outline>,
+    search: Option<Search>,
+    request: Option<InFlight<'a>>,
+    // Keys recorded so far while recording a macro
+    recording: Option<Vec<Input>>,
+    macros: HashMap<char, Vec<Input>>,
+    // Keys of a macro still to be replayed
+    replaying: VecDeque<Input>,
+    // Exchanges whose tool steps are shown in full rather than one line each
+    expanded_steps: BTreeSet<usize>,
+    clipboard: Clipboard,
+    completion: Option<PathCompletion>,
+    // Text of the input box when the completion was closed with Esc, so that it stays closed until the text changes
+    completion_dismissed: Option<String>,
+    // Existing paths mentioned in the chat log, along with the number of exchanges they were looked for in
+    mentioned_paths: (usize, Vec<String>),
+    // Show the chat log without the input box and never change or save the session
+    read_only: bool,
+    // Saves the session as it goes if turned on in the config
+    autosave: Option<Autosave>,
+    // How numbers, dates and times are written
+    locale: Locale,
+    // Colors of the messages in the chat log by who wrote them
+    role_colors: RoleColors,
+    // Facts about the user remembered across sessions, if turned on in the config
+    memory: Option<MemoryStore>,
 }
 
 impl<'a> ChatTermApp<'a> {
-    fn new(session: ChatGPTSession) -> io::Result<Self> {
+    // Checks done before sending a message, in order. Each may ask a question whose answer continues with the
+    // check at the given index.
+    const SEND_CHECKS: [SendCheck<'a>; 5] = [
+        Self::offer_code_fence,
+        Self::confirm_lint_warnings,
+        Self::confirm_pinned_over_budget,
+        Self::confirm_trimming,
+        Self::confirm_expensive_message,
+    ];
+
+    fn new(session: ChatGPTSession, usage: UsageStore, history: PromptHistory) -> io::Result<Self> {
         let mut stdout = io::stdout();
         if !is_raw_mode_enabled()? {
             enable_raw_mode()?;
             crossterm::execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+            // Lets terminals that support it report Shift+Enter apart from Enter. Others ignore it.
+            let _ = crossterm::execute!(
+                stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            );
         }
         let backend = CrosstermBackend::new(stdout);
         let term = Terminal::new(backend)?;
 
-        let message_area = ChatTermApp::create_message_area_from_session(session.get_chatlog());
-        Ok(Self {
-            current: 0,
-            session,
-            term,
-            error_message: None,
-            message_area,
-            // TODO: Add help box above input that pops up when typing /help
-            input: ChatEntryBox::default(),
-        })
+        let message_area =
+            ChatTermApp::create_message_area_from_session(session.get_chatlog(), &BTreeSet::new());
+        let send_key = session.config().send_key;
+        let locale = Locale::from_config(session.config());
+        let role_colors = RoleColors::from_config(&session.config().chat_colors);
+        let sessions = SessionManager::from_config(session.config());
+        Ok(Self {
+            current: 0,
+            session,
+            term,
+            error_message: None,
+            status_log: VecDeque::new(),
+            out_of_quota: false,
+            queued: VecDeque::new(),
+            attached_files: Vec::new(),
+            error: None,
+            message_area,
+            input: ChatEntryBox::new(send_key),
+            read_aloud: None,
+            running_command: None,
+            watchdog: None,
+            presentation: None,
+            popup: None,
+            show_metadata: false,
+            usage,
+            question: None,
+            history,
+            palette: None,
+            sessions,
+            session_picker: None,
+            model_picker: None,
+            profile_picker: None,
+            outline: None,
+            search: None,
+            request: None,
+            recording: None,
+            macros: HashMap::new(),
+            replaying: VecDeque::new(),
+            expanded_steps: BTreeSet::new(),
+            clipboard: Clipboard::default(),
+            completion: None,
+            completion_dismissed: None,
+            mentioned_paths: (0, Vec::new()),
+            read_only: false,
+            autosave: None,
+            locale,
+            role_colors,
+            memory: None,
+        })
+    }
+
+    // Run the checks done before sending a message starting from the given one, and send it if none of them asks
+    // a question
+    fn check_and_send(&mut self, message: &str, first_check: usize) {
+        for (index, check) in Self::SEND_CHECKS.iter().enumerate().skip(first_check) {
+            if let Some(question) = check(self, message, index + 1) {
+                self.question = Some(question);
+                return;
+            }
+        }
+        self.send_message(message);
+    }
+
+    // Collapse redundant whitespace in a large message if enabled in the config, reporting the savings
+    fn normalize_message(&mut self, message: String) -> String {
+        if !self.session.config().normalize_whitespace {
+            return message;
+        }
+        match normalize_whitespace(&message, self.session.model()) {
+            Some(normalized) => {
+                self.error_message = Some(
+                    format!(
+                        "Collapsed whitespace, saving {} characters (~{} tokens)",
+                        normalized.saved_chars, normalized.saved_tokens
+                    )
+                    .into(),
+                );
+                normalized.text
+            }
+            None => message,
+        }
+    }
+
+    // Offer to wrap a message that looks like pasted code in a fenced block if enabled in the config
+    fn offer_code_fence(&self, message: &str, next: usize) -> Option<Question> {
+        if !self.session.config().offer_code_fences || !looks_like_code(message) {
+            return None;
+        }
+        let language = detect_language(message);
+        let fenced = fence_code(message, language);
+        Some(Question {
+            text: format!(
+                "This looks like pasted {}code. Press f to wrap it in a ```{} block, y to send it as is, any \
+                other key to cancel",
+                language.map(|language| format!("{} ", language)).unwrap_or_default(),
+                language.unwrap_or("")
+            ),
+            choices: vec![
+                ('f', UiEvent::ContinueSending(fenced, next)),
+                ('y', UiEvent::ContinueSending(message.to_string(), next)),
+            ],
+            restore_input: Some(message.to_string()),
+        })
+    }
+
+    // Warn about likely mistakes in a message if enabled in the config
+    fn confirm_lint_warnings(&self, message: &str, next: usize) -> Option<Question> {
+        if !self.session.config().lint_prompts {
+            return None;
+        }
+        let warnings = lint_prompt(message);
+        if warnings.is_empty() {
+            return None;
+        }
+        Some(Question {
+            text: format!(
+                "Check your message: {}. Press y to send anyway, any other key to edit",
+                warnings.join(", ")
+            ),
+            choices: vec![('y', UiEvent::ContinueSending(message.to_string(), next))],
+            restore_input: Some(message.to_string()),
+        })
+    }
+
+    // Warn when the pinned exchanges alone don't fit in the context along with the message, since they are sent all
+    // the same
+    fn confirm_pinned_over_budget(&self, message: &str, next: usize) -> Option<Question> {
+        let (tokens, budget) = self.session.pinned_over_budget(message)?;
+        Some(Question {
+            text: format!(
+                "Pinned exchanges and the message take {} tokens, more than the limit of {}. Press y to send anyway",
+                self.locale.number(tokens as u64),
+                self.locale.number(budget as u64)
+            ),
+            choices: vec![('y', UiEvent::ContinueSending(message.to_string(), next))],
+            restore_input: Some(message.to_string()),
+        })
+    }
+
+    // Let the user decide what to do with exchanges that would be dropped from the context by sending a message,
+    // unless they are summarized without asking
+    fn confirm_trimming(&self, message: &str, next: usize) -> Option<Question> {
+        if self.session.config().auto_summarize {
+            return None;
+        }
+        let dropped = self.session.newly_dropped_entries(message);
+        if dropped.is_empty() {
+            return None;
+        }
+        let numbers: Vec<String> = dropped
+            .iter()
+            .map(|index| format!("#{}", index + 1))
+            .collect();
+        Some(Question {
+            text: format!(
+                "Sending drops exchanges {} from the context. Press p to pin, s to summarize, y to send anyway",
+                numbers.join(", ")
+            ),
+            choices: vec![
+                (
+                    'p',
+                    UiEvent::PinAndContinueSending(dropped.clone(), message.to_string(), next),
+                ),
+                (
+                    's',
+                    UiEvent::SummarizeAndContinueSending(dropped, message.to_string(), next),
+                ),
+                ('y', UiEvent::ContinueSending(message.to_string(), next)),
+            ],
+            restore_input: Some(message.to_string()),
+        })
+    }
+
+    // Ask for confirmation if the estimated cost of sending a message exceeds the configured threshold
+    fn confirm_expensive_message(&self, message: &str, next: usize) -> Option<Question> {
+        let threshold = self.session.config().confirm_cost_threshold?;
+        let cost = self.session.estimate_cost(message)?;
+        if cost <= threshold {
+            return None;
+        }
+        Some(Question {
+            text: format!(
+                "Estimated cost of this request is {}. Press y to send, any other key to cancel",
+                self.locale.dollars(cost, 4)
+            ),
+            choices: vec![('y', UiEvent::ContinueSending(message.to_string(), next))],
+            restore_input: Some(message.to_string()),
+        })
+    }
+
+    // Offer paths for the word at the cursor in the input box if it is a path, unless they were closed for this text
+    fn update_completion(&mut self) {
+        let lines = self.input.textarea.lines();
+        let text = lines.join("\n");
+        let (row, col) = self.input.textarea.cursor();
+        let word = match path_word(lines, (row, col)) {
+            Some(word) if self.completion_dismissed.as_ref() != Some(&text) => word,
+            _ => {
+                self.completion = None;
+                return;
+            }
+        };
+        self.completion_dismissed = None;
+        let typed: String = lines[row]
+            .chars()
+            .skip(word.start)
+            .take(word.len())
+            .collect();
+        let chatlog = self.session.get_chatlog();
+        if self.mentioned_paths.0 != chatlog.len() {
+            let text: Vec<&str> = chatlog
+                .iter()
+                .flat_map(|entry| [entry.message.as_str(), entry.response.as_str()])
+                .collect();
+            self.mentioned_paths = (chatlog.len(), mentioned_paths(&text.join("\n")));
+        }
+        let candidates = complete_path(&typed, &self.mentioned_paths.1);
+        if candidates.is_empty() {
+            self.completion = None;
+            return;
+        }
+        // The selection stays while the same paths are offered
+        let selected = self
+            .completion
+            .as_ref()
+            .filter(|completion| completion.candidates == candidates)
+            .and_then(|completion| completion.state.selected())
+            .unwrap_or(0);
+        let mut state = ListState::default();
+        state.select(Some(selected));
+        self.completion = Some(PathCompletion {
+            row,
+            word,
+            candidates,
+            state,
+        });
+    }
+
+    // Replace the word at the cursor with the selected path, offering what is in it if it is a directory
+    fn accept_completion(&mut self) {
+        let Some(completion) = self.completion.take() else {
+            return;
+        };
+        let path = &completion.candidates[completion.state.selected().unwrap_or(0)];
+        let textarea = &mut self.input.textarea;
+        textarea.move_cursor(CursorMove::Jump(
+            completion.row as u16,
+            completion.word.end as u16,
+        ));
+        textarea.delete_str(completion.word.start, completion.word.len());
+        textarea.insert_str(path);
+        self.update_completion();
+    }
+
+    // Keys that pick from the offered paths, returning whether the key was used
+    fn completion_input(&mut self, input: &Input) -> bool {
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+        let selected = completion.state.selected().unwrap_or(0);
+        match input {
+            Input {
+                key: Key::Tab,
+                ctrl: false,
+                alt: false,
+            } => self.accept_completion(),
+            Input { key: Key::Up, .. } => completion.state.select(Some(
+                selected
+                    .checked_sub(1)
+                    .unwrap_or(completion.candidates.len() - 1),
+            )),
+            Input { key: Key::Down, .. } => completion
+                .state
+                .select(Some((selected + 1) % completion.candidates.len())),
+            Input { key: Key::Esc, .. } => {
+                self.completion = None;
+                self.completion_dismissed = Some(self.input.textarea.lines().join("\n"));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Read a file to send along with the next message
+    fn attach_file(&mut self, path: &str) {
+        match Attachment::file(path) {
+            Ok(attachment) => {
+                self.error_message = Some(format!("Attached {}", path).into());
+                self.input.attach(attachment);
+            }
+            Err(err) => self.error_message = Some(err.into()),
+        }
+    }
+
+    // Keep the files about to be sent whole, so that new versions of them in answers can be offered
+    fn remember_attached_files(&mut self, attachments: &[Attachment]) {
+        for attachment in attachments {
+            if let Some(path) = &attachment.path {
+                self.attached_files.retain(|(attached, _)| attached != path);
+                self.attached_files
+                    .push((path.clone(), attachment.text.clone()));
+            }
+        }
+    }
+
+    // Write a new version of a file, which the next new version is compared to
+    fn apply_edit(&mut self, edit: &FileEdit) {
+        self.error_message = Some(
+            match edit.apply() {
+                Ok(text) => {
+                    for (path, contents) in &mut self.attached_files {
+                        if *path == edit.path {
+                            *contents = text.clone();
+                        }
+                    }
+                    format!("Applied the changes to {}", edit.path)
+                }
+                Err(err) => err,
+            }
+            .into(),
+        );
+    }
+
+    // Start running a command in the background to send its output along with the next message
+    fn attach_command_output(&mut self, command: &str) {
+        if let Some(running) = &self.running_command {
+            self.error_message = Some(format!("Still running {}", running.command).into());
+            return;
+        }
+        let owned = command.to_string();
+        self.running_command = Some(RunningCommand {
+            command: command.to_string(),
+            started: Instant::now(),
+            handle: thread::spawn(move || Attachment::command_output(&owned)),
+        });
+    }
+
+    // Attach the output of the command run with /run once it has finished
+    fn poll_command(&mut self) {
+        if !self
+            .running_command
+            .as_ref()
+            .is_some_and(|running| running.handle.is_finished())
+        {
+            return;
+        }
+        let running = self.running_command.take().unwrap();
+        let result = running
+            .handle
+            .join()
+            .unwrap_or_else(|_| Err(String::from("The command could not be run")));
+        match result {
+            Ok(attachment) => {
+                let lines = attachment.text.lines().count();
+                self.error_message = Some(
+                    format!("Attached {} lines of output of {}", lines, running.command).into(),
+                );
+                self.input.attach(attachment);
+            }
+            Err(err) => self.error_message = Some(err.into()),
+        }
+    }
+
+    // Start sending a message in the background. The answer is shown in the message area as it is generated.
+    fn send_message(&mut self, message: &str) {
+        if self.out_of_quota {
+            self.queued.push_back(message.to_string());
+            self.error_message = Some(
+                format!(
+                    "Queued the message, the account is out of quota. Switch with /profile, or try again with \
+                    /queue send ({} queued)",
+                    self.queued.len()
+                )
+                .into(),
+            );
+            return;
+        }
+        if self.session.config().auto_summarize {
+            self.error_message =
+                Some("Summarizing exchanges that no longer fit in the context...".into());
+            self.draw();
+            match self.session.summarize_dropped(message) {
+                Ok(0) => self.error_message = None,
+                Ok(count) => {
+                    self.error_message = Some(
+                        format!(
+                            "Summarized {} exchanges that no longer fit in the context",
+                            count
+                        )
+                        .into(),
+                    )
+                }
+                Err(err) => {
+                    self.error_message = None;
+                    self.report_error(&err);
+                    self.input.set_text(message);
+                    return;
+                }
+            }
+        }
+        let request = self.session.prepare_message(message);
+        self.start_request(message, request);
+    }
+
+    // Have the model work towards a goal in the background, running tools step by step
+    fn start_agent(&mut self, goal: &str) {
+        if available_tools(self.session.config()).is_empty() {
+            self.error_message = Some("Agent mode needs tools, add some to the config file".into());
+            return;
+        }
+        let request = self.session.prepare_agent(goal);
+        self.start_request(goal, request);
+    }
+
+    // Keep a message for /log
+    fn log_status(&mut self, message: String) {
+        if self.status_log.len() == MAX_STATUS_LOG {
+            self.status_log.pop_front();
+        }
+        self.status_log.push_back((Local::now(), message));
+    }
+
+    // Show a failed request above the input box until the next one, and keep it for /log
+    fn report_error(&mut self, err: &ChatGptError) {
+        let error = ErrorReport::new(err);
+        self.log_status(format!("{}: {}", error.title(), error.message));
+        self.error = Some(error);
+    }
+
+    fn start_request(&mut self, message: &str, mut request: PendingRequest) {
+        self.error = None;
+        let (sender, tokens) = mpsc::channel();
+        let (approval_sender, approvals) = mpsc::channel();
+        let (retry_sender, retries) = mpsc::channel();
+        let cancelled = request.cancel_flag();
+        request.on_retry(move |retry| {
+            retry_sender.send(retry.clone()).ok();
+        });
+        let handle = thread::spawn(move || {
+            let result = request.send(
+                Some(&mut |token: &str| {
+                    sender.send(token.to_string()).ok();
+                }),
+                // Wait for the user to answer in the UI
+                &mut |call: &ToolCall, step: usize| {
+                    let (reply, answer) = mpsc::channel();
+                    approval_sender.send((call.clone(), step, reply)).is_ok()
+                        && answer.recv().unwrap_or(false)
+                },
+            );
+            (request, result)
+        });
+        self.request = Some(InFlight {
+            message: message.to_string(),
+            chat_log: self.message_area.clone(),
+            answer: String::new(),
+            tokens,
+            approvals,
+            approval: None,
+            handle,
+            started: Instant::now(),
+            retries,
+            retry: None,
+            replacing: None,
+            cancelled,
+        });
+        self.show_answer_so_far();
+    }
+
+    // Stop waiting for the answer, leaving the chat log as it was and putting the message back in the input box, or
+    // the previous answer back when regenerating. The request is left to stop in the background, and whatever it
+    // returns is ignored.
+    fn cancel_request(&mut self) {
+        let Some(request) = self.request.take() else {
+            return;
+        };
+        request.cancelled.store(true, Ordering::Relaxed);
+        self.message_area = request.chat_log;
+        match request.replacing {
+            Some(previous) => {
+                self.session.push_entry(previous);
+                self.session_changed();
+                self.refresh_message_area();
+            }
+            None => self.input.set_text(&request.message),
+        }
+        // A tool waiting for approval is declined when the approval is dropped
+        self.question = None;
+        self.error_message = Some("Cancelled the request".into());
+    }
+
+    // Save the session. One that has no title and was never saved is named after its first message instead of the
+    // time it started, with the name put in the input box as `/save <name>` to be edited or saved with Enter. A
+    // message being written there is kept, and the session saved under the name right away.
+    fn save_session(&mut self) {
+        if let Some(name) = self.session.suggested_name() {
+            if self.input.textarea.is_empty() {
+                self.input.set_text(&format!("/save {}", name));
+                self.error_message =
+                    Some("Press Enter to save under this name, or edit it first".into());
+                return;
+            }
+            self.session.set_name(&name);
+        }
+        self.error_message = Some(
+            match self.session.save_chatlog() {
+                Ok(filename) => format!("Saved session to {}", filename),
+                Err(err) => format!("Error: {:?}", err),
+            }
+            .into(),
+        );
+    }
+
+    // Wind down before quitting: stop reading aloud and presenting, cancel the request being made and give it a
+    // moment to stop, and write the last changes to the autosave file so that the session can be recovered if
+    // saving it hangs. Keys pressed meanwhile are dropped rather than left for the shell to read.
+    fn shutdown(&mut self) {
+        let timeout = self
+            .session
+            .config()
+            .shutdown_timeout_secs
+            .map_or(SHUTDOWN_TIMEOUT, Duration::from_secs);
+        self.watchdog = Some(Watchdog::start(timeout));
+        if let Some(read_aloud) = self.read_aloud.take() {
+            read_aloud.stop();
+        }
+        if let Some(presentation) = self.presentation.take() {
+            presentation.stop();
+        }
+        if let Some(request) = self.request.take() {
+            let InFlight {
+                approvals,
+                approval,
+                handle,
+                replacing,
+                cancelled,
+                ..
+            } = request;
+            cancelled.store(true, Ordering::Relaxed);
+            // A tool waiting for approval is declined when the approval is dropped
+            drop((approvals, approval));
+            if let Some(previous) = replacing {
+                self.session.push_entry(previous);
+                self.session_changed();
+            }
+            // Whatever the request returns is ignored, as when cancelling it
+            let deadline = Instant::now() + REQUEST_STOP_WAIT;
+            while !handle.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        // The autosave file is only read if quitting doesn't finish, so failing to write it doesn't stop quitting
+        if let Some(autosave) = &mut self.autosave {
+            autosave.flush(&self.session).ok();
+        }
+        while crossterm::event::poll(Duration::ZERO).unwrap_or(false) {
+            if crossterm::event::read().is_err() {
+                break;
+            }
+        }
+    }
+
+    // Try sending the queued messages again, one after the other as the answers arrive
+    fn send_queue(&mut self) {
+        self.out_of_quota = false;
+        if let Some(message) = self.queued.pop_front() {
+            self.send_message(&message);
+        }
+    }
+
+    // Send the last message again, dropping its answer from the chat log
+    fn regenerate(&mut self) {
+        if self.out_of_quota {
+            self.error_message =
+                Some("The account is out of quota, switch with /profile or try again with /queue send".into());
+            return;
+        }
+        let Some(entry) = self.session.pop_entry() else {
+            self.error_message = Some("There is no answer to regenerate yet".into());
+            return;
+        };
+        self.refresh_message_area();
+        let message = entry.message.clone();
+        self.send_message(&message);
+        match &mut self.request {
+            Some(request) => request.replacing = Some(entry),
+            // Nothing was sent, e.g. because summarizing the context failed, so the answer stays
+            None => {
+                self.session.push_entry(entry);
+                self.refresh_message_area();
+            }
+        }
+    }
+
+    // Show the exchange being sent below the chat log, with the answer received so far
+    fn show_answer_so_far(&mut self) {
+        if self.request.is_none() {
+            return;
+        }
+        let width = self.term.get_frame().size().width as usize - 4;
+        let view = self.view_position();
+        let request = self.request.as_ref().unwrap();
+        self.message_area = request.chat_log.clone();
+        let answer = self
+            .session
+            .config()
+            .max_response_chars
+            .and_then(|max_chars| truncate_response(&request.answer, max_chars));
+        let entry = ChatLogEntry::new(
+            &request.message,
+            answer.as_deref().unwrap_or(&request.answer),
+        );
+        ChatTermApp::add_chatlog_entry(&mut self.message_area, &entry, width, false);
+        self.restore_view(view);
+    }
+
+    // Cursor and top left corner of the chat log shown, unless the cursor is on the last line, in which case the view
+    // follows the end of the chat log as an answer comes in
+    fn view_position(&mut self) -> Option<((usize, usize), (usize, usize))> {
+        let cursor = self.message_area.cursor();
+        if cursor.0 + 1 >= self.message_area.lines().len() {
+            return None;
+        }
+        Some((cursor, scroll_position(&mut self.message_area)))
+    }
+
+    // Put the cursor and scroll position back after the message area was rebuilt, so that browsing the chat log while
+    // an answer comes in isn't undone by every token
+    fn restore_view(&mut self, view: Option<((usize, usize), (usize, usize))>) {
+        let Some(((row, col), (top_row, top_col))) = view else {
+            return;
+        };
+        let (current_row, current_col) = scroll_position(&mut self.message_area);
+        self.message_area.scroll(Scrolling::Delta {
+            rows: (top_row as i64 - current_row as i64) as i16,
+            cols: (top_col as i64 - current_col as i64) as i16,
+        });
+        self.message_area
+            .move_cursor(CursorMove::Jump(row as u16, col as u16));
+    }
+
+    // Add tokens that arrived since the last check to the answer being shown, and finish the exchange once the
+    // request is done
+    fn poll_request(&mut self) {
+        let Some(request) = &mut self.request else {
+            return;
+        };
+        let num_chars = request.answer.len();
+        request.answer.extend(request.tokens.try_iter());
+        if let Some(retry) = request.retries.try_iter().last() {
+            request.retry = Some((retry, Instant::now()));
+        }
+        if request.answer.len() > num_chars {
+            self.show_answer_so_far();
+        }
+        self.ask_tool_approval();
+        if self
+            .request
+            .as_ref()
+            .is_some_and(|request| request.handle.is_finished())
+        {
+            let view = self.view_position();
+            let request = self.request.take().unwrap();
+            self.message_area = request.chat_log;
+            // A panic while making the request is reported like any other failure, rather than taking down the UI
+            let result = match request.handle.join() {
+                Ok((pending, result)) => self.session.finish_message(pending, result),
+                Err(_) => Err(ChatGptError::InvalidResponse(
+                    "The request failed unexpectedly".to_string(),
+                )),
+            };
+            if result
+                .as_ref()
+                .is_err_and(|err| err.kind() == ApiErrorKind::Quota)
+            {
+                self.out_of_quota = true;
+            }
+            match (request.replacing, result) {
+                // The previous answer stays when another one can't be had
+                (Some(previous), Err(err)) => {
+                    self.session.push_entry(previous);
+                    self.session_changed();
+                    self.refresh_message_area();
+                    self.report_error(&err);
+                }
+                (_, result) => self.finish_exchange(&request.message, result),
+            }
+            self.restore_view(view);
+        }
+    }
+
+    // Ask whether to run a tool the model asked for. Dismissing the question declines it.
+    fn ask_tool_approval(&mut self) {
+        let Some(request) = &mut self.request else {
+            return;
+        };
+        if self.question.is_none() {
+            if let Some(approval) = request.approval.take() {
+                approval.send(false).ok();
+                self.error_message = Some("Declined to run the tool".into());
+            }
+        }
+        if request.approval.is_some() {
+            return;
+        }
+        if let Ok((call, step, approval)) = request.approvals.try_recv() {
+            request.approval = Some(approval);
+            self.question = Some(Question {
+                text: format!(
+                    "Step {}: the model wants to run {} with {}. Press y to run it, any other key to decline",
+                    step,
+                    call.name,
+                    preview(&call.arguments, 60)
+                ),
+                choices: vec![('y', UiEvent::ApproveTool)],
+                restore_input: None,
+            });
+        }
+    }
+
+    // Add a finished exchange to the message area, or report why it failed
+    fn finish_exchange(&mut self, message: &str, result: Result<ChatLogEntry, ChatGptError>) {
+        match result {
+            Ok(entry) => {
+                let cost = self.session.entry_cost(&entry);
+                let (prompt_tokens, completion_tokens) = entry.total_tokens();
+                if let Err(err) = self.usage.record(prompt_tokens, completion_tokens, cost) {
+                    self.error_message = Some(format!("Error saving usage: {:?}", err).into());
+                }
+                self.session.emit(Event::Cost {
+                    request: cost,
+                    session: self.session.cost(),
+                    today: self.usage.today().cost,
+                });
+                if let Err(err) = self.history.record(message) {
+                    self.error_message =
+                        Some(format!("Error saving prompt history: {:?}", err).into());
+                }
+                self.session_changed();
+                let width = self.term.get_frame().size().width as usize - 4;
+                ChatTermApp::add_chatlog_entry(&mut self.message_area, &entry, width, false);
+                let finish_reason = self
+                    .session
+                    .last_response()
+                    .and_then(|metadata| metadata.finish_reason.as_deref());
+                if finish_reason == Some("content_filter") {
+                    self.question = Some(Question {
+                        text: "The answer was cut short by the content filter. Press r to rephrase the \
+                            message and retry, any other key to continue"
+                            .into(),
+                        choices: vec![('r', UiEvent::RephraseAndRetry(message.to_string()))],
+                        restore_input: None,
+                    });
+                } else if !self.queued.is_empty() && self.question.is_none() {
+                    self.send_queue();
+                } else if let Some(edit) = FileEdit::find(&entry.response, &self.attached_files) {
+                    self.question = Some(Question {
+                        text: format!(
+                            "The answer has a new version of {}. Press d to review the changes, any other key to \
+                            continue",
+                            edit.path
+                        ),
+                        choices: vec![('d', UiEvent::ReviewEdit(edit))],
+                        restore_input: None,
+                    });
+                }
+            }
+            Err(err) => match err.kind() {
+                ApiErrorKind::ContentFilter => {
+                    self.question = Some(Question {
+                        text: "The message was blocked by the content filter. Press r to rephrase it and \
+                            retry, any other key to edit it"
+                            .into(),
+                        choices: vec![('r', UiEvent::RephraseAndRetry(message.to_string()))],
+                        restore_input: Some(message.to_string()),
+                    });
+                }
+                // The message waits for the account to be changed rather than failing again
+                ApiErrorKind::Quota => {
+                    self.report_error(&err);
+                    self.queued.push_front(message.to_string());
+                }
+                // The message is put back so that it can be sent again
+                _ => {
+                    self.report_error(&err);
+                    if self.input.textarea.is_empty() {
+                        self.input.set_text(message);
+                    }
+                }
+            },
+        }
+    }
+
+    // Entries of the command palette matching its query: commands, then saved sessions by title and tags, then
+    // favorite and previous prompts
+    fn palette_entries(&self, palette: &Palette) -> Vec<PaletteEntry> {
+        let command = |label: &str, action: PaletteAction| PaletteEntry {
+            label: label.to_string(),
+            action,
+            prompt: None,
+        };
+        let mut entries = vec![
+            command("Save session", PaletteAction::Event(UiEvent::SaveSession)),
+            command("List commands", PaletteAction::Event(UiEvent::Help)),
+            command(
+                "Manage sessions",
+                PaletteAction::Event(UiEvent::ShowSessions),
+            ),
+            command("New session", PaletteAction::Event(UiEvent::NewSession)),
+            command("Switch model", PaletteAction::Event(UiEvent::ShowModels)),
+            command("Switch profile", PaletteAction::Event(UiEvent::ShowProfiles)),
+            command("Show word counts", PaletteAction::Event(UiEvent::ShowStats)),
+            command(
+                "Add checkpoint",
+                PaletteAction::Insert("/checkpoint ".into()),
+            ),
+            command(
+                "Roll back to checkpoint",
+                PaletteAction::Insert("/rollback ".into()),
+            ),
+            command(
+                "Fork from checkpoint",
+                PaletteAction::Insert("/fork ".into()),
+            ),
+            command("Turn A/B mode on", PaletteAction::Insert("/ab ".into())),
+            command("Switch backend", PaletteAction::Insert("/backend ".into())),
+            command("Set title", PaletteAction::Insert("/title ".into())),
+            command("Add tag", PaletteAction::Insert("/tag ".into())),
+            command("Remove tag", PaletteAction::Insert("/untag ".into())),
+            command(
+                "Turn A/B mode off",
+                PaletteAction::Event(UiEvent::AbMode("off".into())),
+            ),
+            command(
+                "Toggle low-bandwidth mode",
+                PaletteAction::Event(UiEvent::ToggleLowBandwidth),
+            ),
+            command("Quit", PaletteAction::Event(UiEvent::Quit)),
+        ];
+        entries.extend(palette.sessions.iter().map(|session| {
+            let mut label = format!(
+                "Open {}",
+                session
+                    .title
+                    .clone()
+                    .or_else(|| session.first_message.as_deref().map(|m| preview(m, 50)))
+                    .unwrap_or_else(|| session.path.clone())
+            );
+            for tag in session.tags.iter() {
+                label.push_str(&format!(" #{}", tag));
+            }
+            PaletteEntry {
+                label,
+                action: PaletteAction::Event(UiEvent::OpenSession(session.path.clone())),
+                prompt: None,
+            }
+        }));
+        entries.extend(
+            self.history
+                .ranked()
+                .into_iter()
+                .map(|prompt| PaletteEntry {
+                    label: if prompt.favorite {
+                        format!("* {}", preview(&prompt.text, 70))
+                    } else {
+                        format!("  {} ({}x)", preview(&prompt.text, 70), prompt.count)
+                    },
+                    action: PaletteAction::Insert(prompt.text.clone()),
+                    prompt: Some(prompt.text.clone()),
+                }),
+        );
+        entries.retain(|entry| fuzzy_match(&palette.query, &entry.label));
+        entries
+    }
+
+    // Handle a key pressed while the command palette is open
+    fn palette_input(&mut self, input: Input) -> Option<UiEvent> {
+        let mut palette = self.palette.take()?;
+        let entries = self.palette_entries(&palette);
+        let selected = palette.state.selected().unwrap_or(0);
+        match input {
+            Input { key: Key::Esc, .. } => return None,
+            Input { key: Key::Up, .. } => palette.state.select(Some(selected.saturating_sub(1))),
+            Input { key: Key::Down, .. } => palette
+                .state
+                .select(Some((selected + 1).min(entries.len().saturating_sub(1)))),
+            Input {
+                key: Key::Enter, ..
+            } => {
+                return match entries.into_iter().nth(selected)?.action {
+                    PaletteAction::Event(event) => Some(event),
+                    PaletteAction::Insert(text) => {
+                        self.input.set_text(&text);
+                        None
+                    }
+                };
+            }
+            Input { key: Key::Tab, .. } => {
+                if let Some(prompt) = entries
+                    .get(selected)
+                    .and_then(|entry| entry.prompt.as_ref())
+                {
+                    if let Err(err) = self.history.toggle_favorite(prompt) {
+                        self.error_message =
+                            Some(format!("Error saving prompt history: {:?}", err).into());
+                    }
+                }
+            }
+            Input {
+                key: Key::Backspace,
+                ..
+            } => {
+                palette.query.pop();
+                palette.state.select(Some(0));
+            }
+            Input {
+                key: Key::Char(c),
+                ctrl: false,
+                alt: false,
+            } => {
+                palette.query.push(c);
+                palette.state.select(Some(0));
+            }
+            _ => {}
+        }
+        self.palette = Some(palette);
+        None
+    }
+
+    // Open the list of saved sessions
+    fn show_sessions(&mut self) {
+        match SessionPicker::open(&self.sessions) {
+            Ok(picker) => self.session_picker = Some(picker),
+            Err(err) => {
+                self.error_message = Some(format!("Error listing sessions: {:?}", err).into())
+            }
+        }
+    }
+
+    // Fetch the models the API key can use and list them, with the current one selected
+    fn show_models(&mut self) {
+        self.error_message = Some("Fetching the list of models...".into());
+        self.draw();
+        match self.session.list_models() {
+            Ok(models) if models.is_empty() => {
+                self.error_message = Some("The API lists no chat models".into());
+            }
+            Ok(models) => {
+                let mut state = ListState::default();
+                let current = self.session.model();
+                state.select(Some(
+                    models
+                        .iter()
+                        .position(|model| model == current)
+                        .unwrap_or(0),
+                ));
+                self.error_message = None;
+                self.model_picker = Some(ModelPicker { models, state });
+            }
+            Err(err) => self.report_error(&err),
+        }
+    }
+
+    // Handle a key pressed while the list of models is open
+    fn model_picker_input(&mut self, input: Input) -> Option<UiEvent> {
+        let mut picker = self.model_picker.take()?;
+        let selected = picker.state.selected().unwrap_or(0);
+        match input {
+            Input { key: Key::Esc, .. } => return None,
+            Input { key: Key::Up, .. } => picker.state.select(Some(selected.saturating_sub(1))),
+            Input { key: Key::Down, .. } => picker.state.select(Some(
+                (selected + 1).min(picker.models.len().saturating_sub(1)),
+            )),
+            Input {
+                key: Key::Enter, ..
+            } => return picker.models.get(selected).cloned().map(UiEvent::SetModel),
+            _ => {}
+        }
+        self.model_picker = Some(picker);
+        None
+    }
+
+    // List the profiles from the config, with the current one selected
+    fn show_profiles(&mut self) {
+        if self.session.config().profiles.is_empty() {
+            self.error_message =
+                Some("There are no profiles in the config, add them as [[profiles]] tables".into());
+            return;
+        }
+        let profiles = self.session.profile_choices();
+        let mut state = ListState::default();
+        state.select(Some(
+            profiles
+                .iter()
+                .position(|(name, _)| name.as_deref() == self.session.profile())
+                .unwrap_or(0),
+        ));
+        self.profile_picker = Some(ProfilePicker { profiles, state });
+    }
+
+    // Handle a key pressed while the list of profiles is open
+    fn profile_picker_input(&mut self, input: Input) -> Option<UiEvent> {
+        let mut picker = self.profile_picker.take()?;
+        let selected = picker.state.selected().unwrap_or(0);
+        match input {
+            Input { key: Key::Esc, .. } => return None,
+            Input { key: Key::Up, .. } => picker.state.select(Some(selected.saturating_sub(1))),
+            Input { key: Key::Down, .. } => picker.state.select(Some(
+                (selected + 1).min(picker.profiles.len().saturating_sub(1)),
+            )),
+            Input {
+                key: Key::Enter, ..
+            } => {
+                let (name, _) = picker.profiles.get(selected)?;
+                return Some(UiEvent::SetProfile(name.clone()));
+            }
+            _ => {}
+        }
+        self.profile_picker = Some(picker);
+        None
+    }
+
+    // Switch to a profile, saying where the messages go from now on. Messages queued while the previous account
+    // was out of quota are sent with it.
+    fn set_profile(&mut self, name: Option<&str>) {
+        if let Err(err) = self.session.set_profile(name) {
+            self.error_message = Some(err.into());
+            return;
+        }
+        self.error_message = Some(
+            format!(
+                "Sending messages to {} on {} with {}",
+                self.session.model(),
+                self.session.provider(),
+                name.map_or(String::from("the main settings"), |name| format!(
+                    "the {} profile",
+                    name
+                ))
+            )
+            .into(),
+        );
+        self.out_of_quota = false;
+        self.send_queue();
+    }
+
+    // Switch to the backend named by the argument of /backend, with the model following it or else the one of the
+    // backend's config
+    fn set_backend(&mut self, argument: &str) {
+        let mut words = argument.split_whitespace();
+        let backend = match words.next().unwrap_or_default().parse::<BackendKind>() {
+            Ok(backend) => backend,
+            Err(err) => {
+                self.error_message = Some(err.into());
+                return;
+            }
+        };
+        self.session.set_backend(backend, words.next());
+        self.error_message = Some(
+            format!(
+                "Sending messages to {} on {}",
+                self.session.model(),
+                self.session.provider()
+            )
+            .into(),
+        );
+        self.out_of_quota = false;
+    }
+
+    // Profile, provider and model the messages are sent with, shown in the status bar once there are profiles or
+    // another server is used so that messages are not sent to the wrong account by mistake
+    fn profile_indicator(&self) -> Option<String> {
+        let provider = self.session.provider();
+        if self.session.config().profiles.is_empty() && provider == "OpenAI" {
+            return None;
+        }
+        Some(format!(
+            " {} · {} · {} ",
+            self.session.profile().unwrap_or("main"),
+            provider,
+            self.session.model()
+        ))
+    }
+
+    // Handle a key pressed while the list of saved sessions is open
+    fn session_picker_input(&mut self, input: Input) -> Option<UiEvent> {
+        let mut picker = self.session_picker.take()?;
+        let selected = picker.state.selected().unwrap_or(0);
+        match input {
+            Input { key: Key::Esc, .. } => return None,
+            Input { key: Key::Up, .. } => picker.state.select(Some(selected.saturating_sub(1))),
+            Input { key: Key::Down, .. } => picker.state.select(Some(
+                (selected + 1).min(picker.sessions.len().saturating_sub(1)),
+            )),
+            Input {
+                key: Key::Enter, ..
+            } => {
+                let session = picker.sessions.get(selected)?;
+                return Some(UiEvent::OpenSession(session.path.clone()));
+            }
+            Input {
+                key: Key::Char('n'),
+                ctrl: false,
+                alt: false,
+            } => return Some(UiEvent::NewSession),
+            Input {
+                key: Key::Char('d'),
+                ctrl: false,
+                alt: false,
+            } => {
+                let session = picker.sessions.get(selected)?;
+                self.question = Some(Question {
+                    text: format!(
+                        "Delete {} and its backup? Press y to delete it, w to overwrite it first, any other key to \
+                        keep it",
+                        session.path
+                    ),
+                    choices: vec![
+                        ('y', UiEvent::DeleteSession(session.path.clone(), false)),
+                        ('w', UiEvent::DeleteSession(session.path.clone(), true)),
+                    ],
+                    restore_input: None,
+                });
+                return None;
+            }
+            _ => {}
+        }
+        self.session_picker = Some(picker);
+        None
+    }
+
+    // Open the outline at the exchange at the cursor, or close it
+    fn toggle_outline(&mut self) {
+        if self.outline.take().is_some() {
+            return;
+        }
+        let count = self.session.get_chatlog().len();
+        if count == 0 {
+            self.error_message = Some("There is nothing to outline yet".into());
+            return;
+        }
+        let mut state = ListState::default();
+        state.select(Some(
+            self.entry_at_cursor()
+                .unwrap_or(count - 1)
+                .min(count - 1),
+        ));
+        self.outline = Some(Outline {
+            state,
+            cursor: self.message_area.cursor(),
+        });
+    }
+
+    // Start typing a search of the chat log, beginning with the last query
+    fn open_search(&mut self) {
+        let query = self
+            .search
+            .take()
+            .map(|search| search.query)
+            .unwrap_or_default();
+        let mut search = Search {
+            query,
+            editing: true,
+            matches: Vec::new(),
+            current: None,
+            cursor: self.message_area.cursor(),
+        };
+        search.find(self.message_area.lines());
+        self.search = Some(search);
+        self.jump_to_match(|search| search.first_from(search.cursor));
+    }
+
+    // Move the cursor to the match picked from the current search
+    fn jump_to_match(&mut self, pick: impl FnOnce(&Search) -> Option<usize>) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        search.current = pick(search);
+        if let Some(&(row, col)) = search.current.and_then(|index| search.matches.get(index)) {
+            self.message_area
+                .move_cursor(CursorMove::Jump(row as u16, col as u16));
+        }
+    }
+
+    fn search_input(&mut self, input: Input) -> Option<UiEvent> {
+        let mut search = self.search.take()?;
+        match input {
+            Input { key: Key::Esc, .. } if search.editing => {
+                let (row, col) = search.cursor;
+                self.message_area
+                    .move_cursor(CursorMove::Jump(row as u16, col as u16));
+                return None;
+            }
+            Input { key: Key::Esc, .. } => return None,
+            Input {
+                key: Key::Enter, ..
+            } if search.editing => {
+                if search.matches.is_empty() {
+                    self.error_message = Some(format!("No matches for {}", search.query).into());
+                    return None;
+                }
+                search.editing = false;
+            }
+            Input {
+                key: Key::Char('f'),
+                ctrl: true,
+                alt: false,
+            } => {
+                search.editing = true;
+                search.cursor = self.message_area.cursor();
+            }
+            Input {
+                key: Key::Backspace,
+                ..
+            } if search.editing => {
+                search.query.pop();
+                search.find(self.message_area.lines());
+            }
+            Input {
+                key: Key::Char(c),
+                ctrl: false,
+                alt: false,
+            } if search.editing => {
+                search.query.push(c);
+                search.find(self.message_area.lines());
+            }
+            Input {
+                key: Key::Char(c @ ('n' | 'N')),
+                ..
+            } => {
+                let total = search.matches.len();
+                let cursor = self.message_area.cursor();
+                self.search = Some(search);
+                self.jump_to_match(|search| match search.current {
+                    _ if total == 0 => None,
+                    Some(current) if c == 'n' => Some((current + 1) % total),
+                    Some(current) => Some((current + total - 1) % total),
+                    None => search.first_from(cursor),
+                });
+                return None;
+            }
+            _ => {}
+        }
+        let changed = search.editing;
+        self.search = Some(search);
+        if changed {
+            self.jump_to_match(|search| search.first_from(search.cursor));
+        }
+        None
+    }
+
+    // Move through the outline, taking the chat log to the selected exchange
+    fn outline_input(&mut self, input: Input) -> Option<UiEvent> {
+        let mut outline = self.outline.take()?;
+        let last = self.session.get_chatlog().len().saturating_sub(1);
+        let selected = outline.state.selected().unwrap_or(0);
+        let target = match input {
+            Input { key: Key::Esc, .. } => {
+                let (row, col) = outline.cursor;
+                self.message_area
+                    .move_cursor(CursorMove::Jump(row as u16, col as u16));
+                return None;
+            }
+            Input {
+                key: Key::Enter | Key::F(2),
+                ..
+            } => return None,
+            Input {
+                key: Key::Up | Key::MouseScrollUp,
+                ..
+            } => selected.saturating_sub(1),
+            Input {
+                key: Key::Down | Key::MouseScrollDown,
+                ..
+            } => selected + 1,
+            Input {
+                key: Key::PageUp, ..
+            } => selected.saturating_sub(OUTLINE_PAGE),
+            Input {
+                key: Key::PageDown, ..
+            } => selected + OUTLINE_PAGE,
+            Input { key: Key::Home, .. } => 0,
+            Input { key: Key::End, .. } => last,
+            _ => selected,
+        }
+        .min(last);
+        outline.state.select(Some(target));
+        self.jump_to_entry(target);
+        self.outline = Some(outline);
+        None
+    }
+
+    // Have the model write headings for the outline, waiting for them
+    fn generate_headings(&mut self) {
+        self.error_message = Some("Writing headings for the outline...".into());
+        self.draw();
+        match self.session.generate_headings() {
+            Ok(0) => {
+                self.error_message = Some("Every exchange has a heading already".into());
+            }
+            Ok(count) => {
+                self.error_message = Some(format!("Wrote {} headings for the outline", count).into());
+                if self.outline.is_none() {
+                    self.toggle_outline();
+                }
+            }
+            Err(err) => self.report_error(&err),
+        }
+    }
+
+    // The memory store, or None after saying how to turn it on
+    fn memory_store(&mut self) -> Option<&mut MemoryStore> {
+        if self.memory.is_none() {
+            self.error_message =
+                Some("Set memory = true in the config file to remember facts".into());
+        }
+        self.memory.as_mut()
+    }
+
+    // Remember a fact and tell the session about it
+    fn remember(&mut self, fact: &str) {
+        let Some(store) = self.memory_store() else {
+            return;
+        };
+        let result = store.add(fact);
+        let facts = store.facts();
+        self.session.set_memories(facts);
+        self.error_message = Some(match result {
+            Ok(()) => format!("Remembered: {}", fact).into(),
+            Err(err) => format!("Error saving memories: {:?}", err).into(),
+        });
+    }
+
+    // Have the model pick out facts about the user from the conversation, to ask which to remember
+    fn extract_memories(&mut self) {
+        if self.memory_store().is_none() {
+            return;
+        }
+        if self.session.get_chatlog().is_empty() {
+            self.error_message = Some("There is no conversation to remember facts from".into());
+            return;
+        }
+        self.error_message = Some("Looking for facts to remember...".into());
+        self.draw();
+        match self.session.extract_memories() {
+            Ok(facts) if facts.is_empty() => {
+                self.error_message = Some("Found nothing new to remember".into());
+            }
+            Ok(facts) => self.review_memories(facts),
+            Err(err) => self.report_error(&err),
+        }
+    }
+
+    // Ask whether to remember the first of the facts, continuing with the rest either way
+    fn review_memories(&mut self, facts: Vec<String>) {
+        let Some(fact) = facts.first() else {
+            return;
+        };
+        let rest = facts[1..].to_vec();
+        self.question = Some(Question {
+            text: format!(
+                "Remember \"{}\"? Press y to remember it, n to skip it, any other key to stop ({} more)",
+                fact,
+                rest.len()
+            ),
+            choices: vec![
+                ('y', UiEvent::ApproveMemory(facts.clone())),
+                ('n', UiEvent::ReviewMemories(rest)),
+            ],
+            restore_input: None,
+        });
+    }
+
+    // Forget a remembered fact, counting from 1
+    fn forget_memory(&mut self, number: usize) {
+        let Some(store) = self.memory_store() else {
+            return;
+        };
+        let result = store.remove(number.wrapping_sub(1));
+        let facts = store.facts();
+        self.session.set_memories(facts);
+        self.error_message = Some(match result {
+            Ok(Some(memory)) => format!("Forgot: {}", memory.fact).into(),
+            Ok(None) => format!("There is no remembered fact {}", number).into(),
+            Err(err) => format!("Error saving memories: {:?}", err).into(),
+        });
+    }
+
+    // Forget every remembered fact
+    fn clear_memories(&mut self) {
+        let Some(store) = self.memory_store() else {
+            return;
+        };
+        let count = store.memories().len();
+        let result = store.clear();
+        self.session.set_memories(Vec::new());
+        self.error_message = Some(match result {
+            Ok(()) => format!("Forgot {} facts", count).into(),
+            Err(err) => format!("Error saving memories: {:?}", err).into(),
+        });
+    }
+
+    // Serve the chat log as a web page, or the latest version of it at the same address if it is already served
+    fn present(&mut self) {
+        let html = self.session.export(ExportFormat::Html);
+        if let Some(presentation) = self.presentation.as_ref().filter(|p| !p.is_finished()) {
+            presentation.set_page(html);
+        } else {
+            match Presentation::start(html) {
+                Ok(presentation) => self.presentation = Some(presentation),
+                Err(err) => {
+                    self.error_message = Some(format!("Error serving the page: {}", err).into());
+                    return;
+                }
+            }
+        }
+        if let Some(presentation) = &self.presentation {
+            self.error_message = Some(
+                format!(
+                    "Presenting the conversation at {} until {}, /present stop to stop",
+                    presentation.url(),
+                    self.locale.time(&presentation.expires_at())
+                )
+                .into(),
+            );
+        }
+    }
+
+    // Start reading the last response aloud, or pause/resume if already reading
+    fn toggle_read_aloud(&mut self) {
+        if let Some(read_aloud) = &self.read_aloud {
+            read_aloud.toggle_pause();
+            return;
+        }
+        let Some(command) = &self.session.config().tts_command else {
+            self.error_message = Some("Set tts_command in the config file to read aloud".into());
+            return;
+        };
+        if let Some(entry) = self.session.get_chatlog().last() {
+            self.read_aloud = Some(ReadAloud::start(command, &entry.response));
+        }
+    }
+
+    // Add a message to the message area after wrapping it to width, labelled with a five character prefix
+    fn add_line_wrapped(text_area: &mut TextArea, prefix: &str, text: &str, width: usize) {
+        let wrap_width = if width > 6 { width - 5 } else { width };
+        let lines = text
+            .split('\n')
+            .flat_map(|paragraph| wrap_paragraph(paragraph, wrap_width))
+            .collect();
+        ChatTermApp::add_lines(text_area, prefix, lines);
+    }
+
+    // Add a response laid out as markdown
+    fn add_markdown(text_area: &mut TextArea, prefix: &str, text: &str, width: usize) {
+        let wrap_width = if width > 6 { width - 5 } else { width };
+        let lines = render_markdown(text, wrap_width, &wrap_paragraph);
+        ChatTermApp::add_lines(text_area, prefix, lines);
+    }
+
+    fn add_lines(text_area: &mut TextArea, prefix: &str, lines: Vec<String>) {
+        for (ctr, line) in lines.into_iter().enumerate() {
+            if ctr > 0 {
+                // Prefix with five spaces to indicate a continuation of the previous line
+                text_area.insert_str("     ");
+            } else {
+                text_area.insert_str(prefix);
+            }
+            text_area.insert_str(line);
+            text_area.insert_newline();
+        }
+    }
+    // Add the tools run while answering, one block per step. Collapsed blocks only show the tool and its arguments.
+    fn add_tool_steps(
+        message_area: &mut TextArea,
+        entry: &ChatLogEntry,
+        width: usize,
+        expanded: bool,
+    ) {
+        for (index, call) in entry.tool_calls.iter().enumerate() {
+            // Computations are shown with their result, as the output of the calculator is a single line
+            let summary = if call.name == CALCULATOR_TOOL && !call.output.contains('\n') {
+                &call.output
+            } else {
+                &call.arguments
+            };
+            let step = format!("Step {}: {} {}", index + 1, call.name, preview(summary, 60));
+            if !expanded {
+                ChatTermApp::add_line_wrapped(message_area, "  ▸  ", &step, width);
+                continue;
+            }
+            ChatTermApp::add_line_wrapped(message_area, "  ▾  ", &step, width);
+            if !call.plan.is_empty() {
+                ChatTermApp::add_line_wrapped(message_area, "     ", &call.plan, width);
+            }
+            let lines: Vec<&str> = call.output.trim_end().lines().collect();
+            let mut output = lines[..lines.len().min(MAX_STEP_OUTPUT_LINES)].join("\n");
+            if lines.len() > MAX_STEP_OUTPUT_LINES {
+                output.push_str(&format!(
+                    "\n({} more lines, F3 to see all)",
+                    lines.len() - MAX_STEP_OUTPUT_LINES
+                ));
+            }
+            ChatTermApp::add_line_wrapped(message_area, "   > ", &output, width);
+        }
+    }
+
+    fn add_chatlog_entry(
+        message_area: &mut TextArea,
+        entry: &ChatLogEntry,
+        width: usize,
+        expanded: bool,
+    ) {
+        // Always append at the end, even if the cursor was moved to browse the log
+        message_area.move_cursor(CursorMove::Bottom);
+        message_area.move_cursor(CursorMove::End);
+        // Add both message and response to message_area after wrapping them to width, leaving room for the time
+        // of the exchange after the message
+        let message_width = if entry.timestamp.is_some() {
+            width.saturating_sub(TIMESTAMP_WIDTH)
+        } else {
+            width
+        };
+        ChatTermApp::add_line_wrapped(message_area, "You: ", &entry.message, message_width);
+        ChatTermApp::add_tool_steps(message_area, entry, width, expanded);
+        if entry.variants.is_empty() {
+            ChatTermApp::add_markdown(message_area, "Bot: ", &entry.response, width);
+        }
+        // Responses sent in A/B mode are labelled with their variant
+        for variant in entry.variants.iter() {
+            let prefix = format!("{:<5}", format!("{}:", variant.label));
+            ChatTermApp::add_markdown(message_area, &prefix, &variant.response, width);
+        }
+        // Search results cited in the answer, with their snippets when the exchange is expanded
+        for (number, result) in cited_results(&entry.response, &entry.tool_calls) {
+            let footnote = format!("[{}] {} {}", number, result.title, result.url);
+            ChatTermApp::add_line_wrapped(message_area, "     ", &footnote, width);
+            if expanded && !result.snippet.is_empty() {
+                ChatTermApp::add_line_wrapped(message_area, "   > ", &result.snippet, width);
+            }
+        }
+        if let Some(annotation) = entry.annotation() {
+            ChatTermApp::add_line_wrapped(message_area, "  ✎  ", &annotation, width);
+        }
+    }
+
+    // Turn A/B mode on or off as given by the argument of the /ab command
+    fn set_ab_mode(&mut self, argument: &str) {
+        let words: Vec<&str> = argument.split_whitespace().collect();
+        let variants = match words.as_slice() {
+            ["off"] => None,
+            ["temperature", a, b] => match (a.parse(), b.parse()) {
+                (Ok(a), Ok(b)) => Some([
+                    Variant {
+                        initial_prompt: None,
+                        temperature: Some(a),
+                    },
+                    Variant {
+                        initial_prompt: None,
+                        temperature: Some(b),
+                    },
+                ]),
+                _ => {
+                    self.error_message = Some("Temperatures must be numbers".into());
+                    return;
+                }
+            },
+            _ => Some([
+                Variant::default(),
+                Variant {
+                    initial_prompt: Some(argument.to_string()),
+                    temperature: None,
+                },
+            ]),
+        };
+        self.session.set_ab_variants(variants);
+        self.error_message = Some(match self.session.ab_variants() {
+            Some([a, b]) => format!("A/B mode on. A: {}, B: {}", a, b).into(),
+            None => "A/B mode off".into(),
+        });
+    }
+
+    // Index of the chat log entry at the cursor of the message area
+    fn entry_at_cursor(&self) -> Option<usize> {
+        let (row, _) = self.message_area.cursor();
+        let lines = self.message_area.lines();
+        let num_user_messages = lines[..=row.min(lines.len() - 1)]
+            .iter()
+            .filter(|line| line.starts_with("You: "))
+            .count();
+        num_user_messages.checked_sub(1)
+    }
+
+    // Rate the exchange at the cursor, or the last one if the cursor is past it, and attach a note to it
+    fn annotate_at_cursor(&mut self, rating: Option<Option<Rating>>, note: Option<Option<String>>) {
+        let len = self.session.get_chatlog().len();
+        let Some(index) = self
+            .entry_at_cursor()
+            .filter(|&index| index < len)
+            .or_else(|| len.checked_sub(1))
+        else {
+            self.error_message = Some("There is no exchange to annotate yet".into());
+            return;
+        };
+        if let Some(rating) = rating {
+            self.session.set_rating(index, rating);
+        }
+        if let Some(note) = note {
+            self.session.set_note(index, note);
+        }
+        let (row, _) = self.message_area.cursor();
+        self.refresh_message_area();
+        self.message_area
+            .move_cursor(CursorMove::Jump(row as u16, 0));
+        self.error_message = Some(
+            match self.session.get_chatlog()[index].annotation() {
+                Some(annotation) => format!("Annotated exchange {}: {}", index + 1, annotation),
+                None => format!("Removed the annotation of exchange {}", index + 1),
+            }
+            .into(),
+        );
+    }
+
+    // Show the whole response of the exchange at the cursor, or the last one, when it was cut
+    fn show_full_response(&mut self) {
+        let chatlog = self.session.get_chatlog();
+        let Some(index) = self
+            .entry_at_cursor()
+            .filter(|&index| index < chatlog.len())
+            .or_else(|| chatlog.len().checked_sub(1))
+        else {
+            self.error_message = Some("There is no response yet".into());
+            return;
+        };
+        match &chatlog[index].full_response {
+            Some(response) => {
+                self.popup = Some(Popup {
+                    title: format!("Response {}", index + 1),
+                    lines: response.lines().map(String::from).collect(),
+                    scroll: 0,
+                    choices: Vec::new(),
+                })
+            }
+            // Whole responses aren't saved with the session
+            None => {
+                self.error_message = Some(
+                    "The response wasn't cut, or was cut before the app was last closed".into(),
+                )
+            }
+        }
+    }
+
+    // Show the word and character counts of the exchange at the cursor, or the last one, in the status line
+    fn show_stats(&mut self) {
+        let chatlog = self.session.get_chatlog();
+        let Some(index) = self
+            .entry_at_cursor()
+            .filter(|&index| index < chatlog.len())
+            .or_else(|| chatlog.len().checked_sub(1))
+        else {
+            self.error_message = Some("There is no response yet".into());
+            return;
+        };
+        let entry = &chatlog[index];
+        self.error_message = Some(
+            format!(
+                "Message {}: {}. Response: {}",
+                index + 1,
+                TextStats::of(&entry.message).describe(&self.locale),
+                TextStats::of(&entry.response).describe(&self.locale)
+            )
+            .into(),
+        );
+    }
+
+    // The response of the exchange at the cursor, or the last one if the cursor is past it
+    fn response_at_cursor(&self) -> Option<String> {
+        let chatlog = self.session.get_chatlog();
+        let index = self
+            .entry_at_cursor()
+            .filter(|&index| index < chatlog.len())
+            .or_else(|| chatlog.len().checked_sub(1));
+        index.map(|index| chatlog[index].response.clone())
+    }
+
+    // Copy the response at the cursor to the clipboard. With `code`, only the last code block in it is copied.
+    fn copy_at_cursor(&mut self, code: bool) {
+        let Some(response) = self.response_at_cursor() else {
+            self.error_message = Some("There is no response to copy yet".into());
+            return;
+        };
+        let (text, what) = if code {
+            match code_blocks(&response).pop() {
+                Some(block) => (block, "the code block"),
+                None => {
+                    self.error_message = Some("There is no code block in this response".into());
+                    return;
+                }
+            }
+        } else {
+            (response, "the response")
+        };
+        self.error_message = Some(match self.clipboard.copy(&text) {
+            Ok(target) => format!("Copied {} to {}", what, target).into(),
+            Err(err) => format!("Failed to copy {}: {}", what, err).into(),
+        });
+    }
+
+    // Save a code block of the response at the cursor to a file, asking before replacing one. Without a path, the
+    // command is put back in the input box with a suggested path to edit.
+    fn extract_code_at_cursor(&mut self, number: Option<usize>, path: Option<String>) {
+        let Some(response) = self.response_at_cursor() else {
+            self.error_message = Some("There is no response to save code from yet".into());
+            return;
+        };
+        let blocks = fenced_blocks(&response);
+        let number = number.unwrap_or(blocks.len());
+        let Some(block) = number.checked_sub(1).and_then(|index| blocks.get(index)) else {
+            self.error_message = Some(
+                match blocks.len() {
+                    0 => String::from("There is no code block in this response"),
+                    1 => String::from("This response has only 1 code block"),
+                    count => format!("This response has {} code blocks", count),
+                }
+                .into(),
+            );
+            return;
+        };
+        match path {
+            None => {
+                self.input
+                    .set_text(&format!("/extract {} {}", number, suggest_path(block)));
+                self.error_message =
+                    Some("Edit the path and press Enter to save the code block".into());
+            }
+            Some(path) if Path::new(&path).exists() => {
+                self.question = Some(Question {
+                    text: format!(
+                        "{} already exists. Press y to replace it, any other key to keep it",
+                        path
+                    ),
+                    choices: vec![('y', UiEvent::SaveCode(path, block.code.clone()))],
+                    restore_input: None,
+                });
+            }
+            Some(path) => self.save_code(&path, &block.code),
+        }
+    }
+
+    fn save_code(&mut self, path: &str, code: &str) {
+        self.error_message = Some(
+            match save_code(path, code) {
+                Ok(()) => format!("Saved the code block to {}", path),
+                Err(err) => format!("Error saving the code block to {}: {}", path, err),
+            }
+            .into(),
+        );
+    }
+
+    // Show what was sent to the API for the exchange at the cursor
+    fn inspect_entry_at_cursor(&mut self) {
+        let chatlog = self.session.get_chatlog();
+        if let Some(index) = self
+            .entry_at_cursor()
+            .filter(|&index| index < chatlog.len())
+        {
+            self.popup = Some(Popup::inspect_entry(chatlog, index, &self.locale));
+        }
+    }
+
+    // Move the chat log cursor to the message of an exchange
+    fn jump_to_entry(&mut self, index: usize) {
+        let target = self
+            .message_area
+            .lines()
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.starts_with("You: "))
+            .nth(index)
+            .map(|(row, _)| row);
+        if let Some(target) = target {
+            self.message_area
+                .move_cursor(CursorMove::Jump(target as u16, 0));
+        }
+    }
+
+    // Move the chat log cursor to the previous or next user message
+    fn jump_to_user_message(&mut self, forward: bool) {
+        let (row, _) = self.message_area.cursor();
+        let lines = self.message_area.lines();
+        let is_user_message = |i: &usize| lines[*i].starts_with("You: ");
+        let target = if forward {
+            (row + 1..lines.len()).find(is_user_message)
+        } else {
+            (0..row).rev().find(is_user_message)
+        };
+        if let Some(target) = target {
+            self.message_area
+                .move_cursor(CursorMove::Jump(target as u16, 0));
+        }
+    }
+
+    // Clear the message area and add all the entries in the chatlog, with the tool steps of the given exchanges
+    // expanded
+    fn create_message_area_from_session(
+        chatlog: &[ChatLogEntry],
+        expanded_steps: &BTreeSet<usize>,
+    ) -> TextArea<'a> {
+        let mut message_area = TextArea::default();
+        message_area.set_block(Block::default().borders(Borders::ALL).title("Chat Log"));
+        message_area.set_style(Style::default().fg(Color::White));
+        message_area.set_alignment(Alignment::Left);
+        message_area.set_cursor_style(Style::default().fg(Color::Black));
+
+        for (index, entry) in chatlog.iter().enumerate() {
+            let expanded = expanded_steps.contains(&index);
+            ChatTermApp::add_chatlog_entry(&mut message_area, entry, 80, expanded);
+        }
+        message_area
+    }
+
+    // Redraw the message area after the chat log was changed
+    fn refresh_message_area(&mut self) {
+        self.message_area = ChatTermApp::create_message_area_from_session(
+            self.session.get_chatlog(),
+            &self.expanded_steps,
+        );
+        // The answer being received goes below the chat log as it is now
+        if let Some(request) = &mut self.request {
+            request.chat_log = self.message_area.clone();
+            self.show_answer_so_far();
+        }
+    }
+
+    // Expand or collapse the tool steps of the exchange at the cursor, keeping the cursor where it was
+    fn toggle_steps_at_cursor(&mut self) {
+        let Some(index) = self.entry_at_cursor() else {
+            return;
+        };
+        if !self.expanded_steps.remove(&index) {
+            self.expanded_steps.insert(index);
+        }
+        let (row, _) = self.message_area.cursor();
+        self.refresh_message_area();
+        self.message_area
+            .move_cursor(CursorMove::Jump(row as u16, 0));
+    }
+
+    // Return to a checkpoint, optionally saving the conversation so far as a fork first
+    fn rollback(&mut self, name: &str, fork: bool) {
+        let result = if fork {
+            self.session.fork(name).map(|saved| {
+                saved.map(|filename| {
+                    format!(
+                        "Saved the conversation to {} and continued from checkpoint {}",
+                        filename, name
+                    )
+                })
+            })
+        } else {
+            Ok(self
+                .session
+                .rollback(name)
+                .then(|| format!("Rolled back to checkpoint {}", name)))
+        };
+        self.error_message = Some(match result {
+            Ok(Some(message)) => {
+                self.refresh_message_area();
+                message.into()
+            }
+            Ok(None) => format!("No checkpoint named {}", name).into(),
+            Err(err) => format!("Error: {:?}", err).into(),
+        });
+    }
+
+    // Replace the contents of the session and redraw the message area
+    fn restore_session(&mut self, file: SessionFile) {
+        self.expanded_steps.clear();
+        self.message_area =
+            ChatTermApp::create_message_area_from_session(&file.entries, &self.expanded_steps);
+        if let Err(err) = self.session.restore(file) {
+            self.error_message = Some(err.into());
+        }
+    }
+
+    // Continue with the session left behind by a crash, saving it to the file it was going to be saved to
+    fn recover_session(&mut self, autosaved: AutosavedSession) {
+        self.restore_session(autosaved.file);
+        self.session.set_path(&autosaved.name);
+        // The session is autosaved by this run from now on, in place of the file it was read from
+        if let Some(autosave) = &mut self.autosave {
+            autosave.mark_changed();
+            if autosave.flush(&self.session).is_ok() {
+                std::fs::remove_file(&autosaved.source).ok();
+            }
+        }
+        if self.error_message.is_none() {
+            self.error_message = Some(
+                format!(
+                    "Recovered the session autosaved at {}, press ^S to save it to {}",
+                    self.locale.time_with_seconds(&autosaved.saved),
+                    autosaved.name
+                )
+                .into(),
+            );
+        }
+    }
+
+    // Send a summary of the last saved session with a persona along with the initial prompt, to carry on from it
+    fn continue_previous(&mut self, persona: &str) {
+        let previous = self
+            .sessions
+            .list()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|session| session.persona.as_deref() == Some(persona))
+            .max_by_key(|session| session.modified);
+        let Some(previous) = previous else {
+            return;
+        };
+        self.error_message =
+            Some(format!("Summarizing the last session with {}...", persona).into());
+        self.draw();
+        let result = self
+            .sessions
+            .load(&previous.path)
+            .map_err(|err| format!("Error: {}", err))
+            .and_then(|(file, _)| {
+                self.session
+                    .continue_from(&file)
+                    .map_err(|err| err.to_string())
+            });
+        self.error_message = Some(match result {
+            Ok(()) => format!("Continuing from {}", previous.path).into(),
+            Err(err) => format!("Couldn't summarize the last session: {}", err).into(),
+        });
+    }
+
+    // Note that the chat log or the metadata of the session changed, so that it is autosaved again and the page
+    // being presented shows it
+    fn session_changed(&mut self) {
+        if let Some(autosave) = &mut self.autosave {
+            autosave.mark_changed();
+        }
+        if let Some(presentation) = self.presentation.as_ref().filter(|p| !p.is_finished()) {
+            presentation.set_page(self.session.export(ExportFormat::Html));
+        }
+    }
+
+    // Write the session to the autosave file if it changed since the last time and the interval has passed
+    fn autosave_if_due(&mut self) {
+        let Some(autosave) = &mut self.autosave else {
+            return;
+        };
+        if let Err(err) = autosave.save_if_due(&self.session) {
+            self.error_message = Some(format!("Error autosaving: {}", err).into());
+        }
+    }
+
+    // Save the current session and switch to a saved one, falling back to its backup if it is corrupted
+    fn open_session(&mut self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let switched = self.sessions.switch(&mut self.session, path);
+        self.expanded_steps.clear();
+        self.refresh_message_area();
+        let from_backup = switched?;
+        self.error_message = Some(if from_backup {
+            format!(
+                "{} is corrupted, loaded its most recent backup instead",
+                path
+            )
+            .into()
+        } else {
+            format!("Opened {}", path).into()
+        });
+        Ok(())
     }
 
-    // Add a new entry to the message area
-    fn add_line_wrapped(text_area: &mut TextArea, line: &str, width: usize) {
-        let wrap_width = if width > 6 { width - 5 } else { width };
-        let wrapped_lines = textwrap::wrap(line, wrap_width);
-        for (ctr, line) in wrapped_lines.into_iter().enumerate() {
-            if ctr > 0 {
-                // Prefix with five spaces to indicate a continuation of the previous line
-                text_area.insert_str("     ");
+    // Show the start screen and return the entry picked by the user, or None if they quit
+    fn pick_start_entry(&mut self) -> Result<Option<StartEntry>, Box<dyn std::error::Error>> {
+        let mut entries = self.start_entries()?;
+        let mut state = ListState::default();
+        state.select(Some(0));
+        loop {
+            let items: Vec<ListItem> = entries
+                .iter()
+                .map(|entry| ListItem::new(entry.to_string()))
+                .collect();
+            self.term.draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+                    .split(f.size());
+                let list = List::new(items)
+                    .block(Block::default().borders(Borders::ALL).title("Start"))
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+                f.render_stateful_widget(list, chunks[0], &mut state);
+                f.render_widget(
+                    Paragraph::new(Spans::from(vec![
+                        Span::styled("Up/Down", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to select, "),
+                        Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to open, "),
+                        Span::styled("f", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to toggle favorite, "),
+                        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to quit"),
+                    ])),
+                    chunks[1],
+                );
+            })?;
+
+            let selected = state.selected().unwrap_or(0);
+            match crossterm::event::read()?.into() {
+                Input { key: Key::Esc, .. } => return Ok(None),
+                Input { key: Key::Up, .. } => state.select(Some(selected.saturating_sub(1))),
+                Input { key: Key::Down, .. } => {
+                    state.select(Some((selected + 1).min(entries.len() - 1)))
+                }
+                Input {
+                    key: Key::Char('f'),
+                    ..
+                } => {
+                    if let StartEntry::Session(session) = &entries[selected] {
+                        self.sessions
+                            .set_favorite(&session.path, !session.favorite)?;
+                        entries = self.start_entries()?;
+                    }
+                }
+                Input {
+                    key: Key::Enter, ..
+                } => return Ok(Some(entries.swap_remove(selected))),
+                _ => {}
             }
-            text_area.insert_str(line);
-            text_area.insert_newline();
         }
     }
-    fn add_chatlog_entry(message_area: &mut TextArea, entry: &ChatLogEntry, width: usize) {
-        // Add both message and response to message_area after wrapping them to width
-        let message = format!("You: {}", entry.message);
-        ChatTermApp::add_line_wrapped(message_area, &message, width);
-        let message = format!("Bot: {}", entry.response);
-        ChatTermApp::add_line_wrapped(message_area, &message, width);
-    }
 
-    // Clear the message area and add all the entries in the chatlog
-    fn create_message_area_from_session(chatlog: &[ChatLogEntry]) -> TextArea<'a> {
-        let mut message_area = TextArea::default();
-        message_area.set_block(Block::default().borders(Borders::ALL).title("Chat Log"));
-        message_area.set_style(Style::default().fg(Color::White));
-        message_area.set_alignment(Alignment::Left);
-        message_area.set_cursor_style(Style::default().fg(Color::Black));
+    // Entries listed on the start screen, starting with the session left behind by a crash if there is one
+    fn start_entries(&self) -> io::Result<Vec<StartEntry>> {
+        let mut entries: Vec<StartEntry> = self
+            .autosave
+            .as_ref()
+            .and_then(Autosave::recover)
+            .map(StartEntry::Recover)
+            .into_iter()
+            .collect();
+        entries.push(StartEntry::NewConversation);
+        entries.extend(
+            self.session
+                .config()
+                .personas
+                .iter()
+                .cloned()
+                .map(StartEntry::Persona),
+        );
+        entries.extend(
+            self.sessions
+                .list()?
+                .into_iter()
+                .take(NUM_RECENT_SESSIONS)
+                .map(StartEntry::Session),
+        );
+        Ok(entries)
+    }
 
-        for entry in chatlog.iter() {
-            ChatTermApp::add_chatlog_entry(&mut message_area, entry, 80);
+    // Start recording a macro, or stop and ask which key to bind it to
+    fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            None => self.recording = Some(Vec::new()),
+            Some(keys) if keys.is_empty() => {
+                self.error_message = Some("Discarded the macro, no keys were recorded".into());
+            }
+            Some(keys) => {
+                self.question = Some(Question {
+                    text: format!(
+                        "Recorded {} keys. Press a digit to bind them to Alt and the digit, any other key to \
+                        discard them",
+                        keys.len()
+                    ),
+                    choices: ('0'..='9')
+                        .map(|digit| (digit, UiEvent::BindMacro(digit, keys.clone())))
+                        .collect(),
+                    restore_input: None,
+                });
+            }
         }
-        message_area
     }
 
-    fn update_ui(&mut self) -> Option<UiEvent> {
-        let input_height = self.input.height();
+    // Draw the chat log, input box, status line and any popup
+    fn draw(&mut self) {
+        let input_height = if self.read_only {
+            0
+        } else {
+            self.input.height()
+        };
+        let error_height = if self.error.is_some() { 2 } else { 0 };
+        let palette_entries = match &self.palette {
+            Some(palette) => self.palette_entries(palette),
+            None => Vec::new(),
+        };
+        let indicator = self.profile_indicator().unwrap_or_default();
+        let quota = if self.out_of_quota {
+            format!(" Out of quota, {} queued ", self.queued.len())
+        } else {
+            String::new()
+        };
+        // The chat log may have changed since the last search, e.g. with an answer arriving
+        if let Some(search) = &mut self.search {
+            search.find(self.message_area.lines());
+        }
+        let timestamps: Vec<String> = self
+            .session
+            .get_chatlog()
+            .iter()
+            .map(|entry| {
+                entry
+                    .timestamp
+                    .map(|timestamp| timestamp_label(&timestamp, &self.locale))
+                    .unwrap_or_default()
+            })
+            .collect();
+        // The file the session is saved to, so that it is clear where ^S goes
+        let file_name = self.session.file_name();
+        let file_name = Path::new(&file_name)
+            .file_name()
+            .map_or(file_name.clone(), |name| name.to_string_lossy().to_string());
+        self.message_area.set_block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Chat Log - {}", file_name)),
+        );
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints(
                 [
                     Constraint::Min(1),
+                    Constraint::Length(error_height),
                     Constraint::Length(input_height),
                     Constraint::Length(1),
                     Constraint::Length(1),
@@ -168,50 +3557,659 @@ impl<'a> ChatTermApp<'a> {
             .draw(|f| {
                 let chunks = layout.split(f.size());
 
-                f.render_widget(self.message_area.widget(), chunks[0]);
+                let mut chat_area = chunks[0];
+                if let Some(outline) = &mut self.outline {
+                    let panes = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Length(OUTLINE_WIDTH), Constraint::Min(1)].as_ref())
+                        .split(chat_area);
+                    let chatlog = self.session.get_chatlog();
+                    // The chat log may have been cleared since the outline was opened
+                    if outline.state.selected().unwrap_or(0) >= chatlog.len() {
+                        outline.state.select(chatlog.len().checked_sub(1));
+                    }
+                    let items: Vec<ListItem> = chatlog
+                        .iter()
+                        .enumerate()
+                        .map(|(index, entry)| {
+                            ListItem::new(format!("{}. {}", index + 1, outline_title(entry)))
+                        })
+                        .collect();
+                    f.render_stateful_widget(
+                        List::new(items)
+                            .block(Block::default().borders(Borders::ALL).title("Outline"))
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+                        panes[0],
+                        &mut outline.state,
+                    );
+                    chat_area = panes[1];
+                }
+
+                if self.show_metadata {
+                    let panes = Layout::default()
+                        .direction(Direction::Horizontal)
+                        .constraints([Constraint::Min(1), Constraint::Length(45)].as_ref())
+                        .split(chat_area);
+                    render_message_area(
+                        f,
+                        &mut self.message_area,
+                        panes[0],
+                        self.role_colors,
+                        &timestamps,
+                        self.search.as_ref(),
+                    );
+                    let text: Vec<Spans> = response_metadata_lines(self.session.last_response())
+                        .into_iter()
+                        .map(Spans::from)
+                        .collect();
+                    f.render_widget(
+                        Paragraph::new(text)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Last response"),
+                            )
+                            .wrap(Wrap { trim: false }),
+                        panes[1],
+                    );
+                } else {
+                    render_message_area(
+                        f,
+                        &mut self.message_area,
+                        chat_area,
+                        self.role_colors,
+                        &timestamps,
+                        self.search.as_ref(),
+                    );
+                }
 
                 // Render status line
                 let slot = format!("[{}/{}]", self.current + 1, 10);
+                let mut cost = match self.session.cost() {
+                    Some(cost) => self.locale.dollars(cost, 4),
+                    None => String::from("$?"),
+                };
+                let tokens = self.session.usage().tokens();
+                if tokens > 0 {
+                    cost = format!("{} tokens | {}", self.locale.number(tokens), cost);
+                }
+                let (top_row, _) = scroll_position(&mut self.message_area);
+                let height = chunks[0].height.saturating_sub(2) as usize;
+                if let Some(position) =
+                    scroll_indicator(top_row, height, self.message_area.lines().len())
+                {
+                    cost = format!("{} | {}", position, cost);
+                }
+                let today = self.usage.today();
+                let threshold = self.session.config().daily_cost_threshold;
+                if threshold.is_some_and(|threshold| today.cost >= threshold) && today.cost > 0.0 {
+                    cost = format!(
+                        "{} | today {} | week {}",
+                        cost,
+                        self.locale.dollars(today.cost, 2),
+                        self.locale.dollars(self.usage.this_week().cost, 2)
+                    );
+                }
                 let status_chunks = Layout::default()
                     .direction(Direction::Horizontal)
                     .constraints(
                         [
                             Constraint::Length(slot.len() as u16),
+                            Constraint::Length(textwrap::core::display_width(&indicator) as u16),
+                            Constraint::Length(quota.len() as u16),
                             Constraint::Min(1),
-                            Constraint::Length(10u16),
+                            Constraint::Length(cost.len() as u16 + 1),
                         ]
                         .as_ref(),
                     )
-                    .split(chunks[2]);
+                    .split(chunks[3]);
                 let status_style = Style::default().add_modifier(Modifier::REVERSED);
                 f.render_widget(Paragraph::new(slot).style(status_style), status_chunks[0]);
-                f.render_widget(Paragraph::new("").style(status_style), status_chunks[1]);
-                f.render_widget(Paragraph::new("0").style(status_style), status_chunks[2]);
+                f.render_widget(
+                    Paragraph::new(indicator.as_str()).style(
+                        Style::default()
+                            .fg(Color::Black)
+                            .bg(Color::Cyan)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    status_chunks[1],
+                );
+                f.render_widget(
+                    Paragraph::new(quota.as_str()).style(
+                        Style::default()
+                            .fg(Color::White)
+                            .bg(Color::Red)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    status_chunks[2],
+                );
+                let status = match &self.read_aloud {
+                    Some(read_aloud) => {
+                        let (current, total) = read_aloud.progress();
+                        let state = if read_aloud.is_paused() {
+                            "paused, F5 to resume"
+                        } else {
+                            "F5 to pause"
+                        };
+                        format!(
+                            "Reading aloud {}/{} ({}, F6 to stop)",
+                            current, total, state
+                        )
+                    }
+                    None if self.recording.is_some() => {
+                        String::from("Recording a macro (F7 to stop)")
+                    }
+                    None if self.request.is_some() => {
+                        let request = self.request.as_ref().unwrap();
+                        let elapsed = request.started.elapsed();
+                        let frame = (elapsed.as_millis() / REQUEST_REFRESH.as_millis()) as usize;
+                        let spinner = SPINNER[frame % SPINNER.len()];
+                        match &request.retry {
+                            // Counting down to the next attempt, then counting the attempt
+                            Some((retry, failed)) if failed.elapsed() < retry.wait => format!(
+                                "{} {}, attempt {} of {} in {}s",
+                                spinner,
+                                preview(&retry.reason, 40),
+                                retry.attempt + 1,
+                                retry.max_attempts,
+                                (retry.wait - failed.elapsed()).as_secs() + 1
+                            ),
+                            Some((retry, _)) => format!(
+                                "{} Thinking... {}s (attempt {} of {})",
+                                spinner,
+                                elapsed.as_secs(),
+                                retry.attempt + 1,
+                                retry.max_attempts
+                            ),
+                            None => format!("{} Thinking... {}s", spinner, elapsed.as_secs()),
+                        }
+                    }
+                    None if self.running_command.is_some() => {
+                        let running = self.running_command.as_ref().unwrap();
+                        let elapsed = running.started.elapsed();
+                        let frame = (elapsed.as_millis() / REQUEST_REFRESH.as_millis()) as usize;
+                        format!(
+                            "{} Running {}... {}s",
+                            SPINNER[frame % SPINNER.len()],
+                            preview(&running.command, 40),
+                            elapsed.as_secs()
+                        )
+                    }
+                    None => match self.session.ab_variants() {
+                        Some([a, b]) => format!("A/B mode (A: {}, B: {})", a, b),
+                        None if self.session.low_bandwidth() => {
+                            format!("Low bandwidth | {}", self.session.sampling())
+                        }
+                        None => self.session.sampling().to_string(),
+                    },
+                };
+                f.render_widget(Paragraph::new(status).style(status_style), status_chunks[3]);
+                f.render_widget(
+                    Paragraph::new(cost)
+                        .alignment(Alignment::Right)
+                        .style(status_style),
+                    status_chunks[4],
+                );
+
+                if !self.read_only {
+                    f.render_widget(self.input.textarea.widget(), chunks[2]);
+                }
 
-                f.render_widget(self.input.textarea.widget(), chunks[1]);
+                // The last failed request, with what to do about it, between the chat log and the input box
+                if let Some(error) = &self.error {
+                    let text = vec![
+                        Spans::from(vec![
+                            Span::styled(
+                                format!("✗ {}: ", error.title()),
+                                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                            ),
+                            Span::styled(error.message.as_str(), Style::default().fg(Color::Red)),
+                        ]),
+                        Spans::from(Span::styled(
+                            format!("  {}", error.action()),
+                            Style::default().fg(Color::DarkGray),
+                        )),
+                    ];
+                    f.render_widget(Paragraph::new(text), chunks[1]);
+                }
+
+                // Offered paths go above the input box, starting under the word they complete
+                if let Some(completion) = &mut self.completion {
+                    let input = chunks[2];
+                    let width = completion
+                        .candidates
+                        .iter()
+                        .map(|path| textwrap::core::display_width(path) as u16 + 2)
+                        .chain([20])
+                        .max()
+                        .unwrap_or_default()
+                        .min(input.width);
+                    let height = (completion.candidates.len() as u16 + 2).min(input.y);
+                    let x = (input.x + 1 + completion.word.start as u16)
+                        .min(input.x + input.width - width);
+                    let area = Rect::new(x, input.y - height, width, height);
+                    let items: Vec<ListItem> = completion
+                        .candidates
+                        .iter()
+                        .map(|path| ListItem::new(path.as_str()))
+                        .collect();
+                    f.render_widget(Clear, area);
+                    f.render_stateful_widget(
+                        List::new(items)
+                            .block(Block::default().borders(Borders::ALL).title("Tab to complete"))
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+                        area,
+                        &mut completion.state,
+                    );
+                }
 
                 // Render message at bottom
-                let message = if let Some(message) = self.error_message.take() {
-                    Spans::from(Span::raw(message))
+                let message = if let Some(question) = &self.question {
+                    Spans::from(Span::styled(
+                        question.text.as_str(),
+                        Style::default()
+                            .fg(Color::Yellow)
+                            .add_modifier(Modifier::BOLD),
+                    ))
+                } else if let Some(search) = &self.search {
+                    Spans::from(Span::raw(search.status()))
+                } else if let Some(message) = &self.error_message {
+                    Spans::from(Span::raw(message.as_ref()))
+                } else if self.outline.is_some() {
+                    Spans::from(vec![
+                        Span::raw("Press "),
+                        Span::styled("Up/Down", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to go to an exchange, "),
+                        Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to stay there, "),
+                        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to go back "),
+                    ])
+                } else if self.read_only {
+                    Spans::from(vec![
+                        Span::raw("Read-only. Press "),
+                        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to quit, "),
+                        Span::styled("Up/Down", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to scroll, "),
+                        Span::styled("[ ]", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to jump between your messages, "),
+                        Span::styled("F3", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to inspect what was sent "),
+                    ])
                 } else {
                     Spans::from(vec![
                         Span::raw("Press "),
                         Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
                         Span::raw(" to quit, "),
+                        Span::styled("^P", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" for commands, "),
                         Span::styled("^S", Style::default().add_modifier(Modifier::BOLD)),
-                        Span::raw(" to save session "),
+                        Span::raw(" to save session, "),
+                        Span::styled("[ ]", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to jump between your messages, "),
+                        Span::styled("F3", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to inspect what was sent "),
                     ])
                 };
-                f.render_widget(Paragraph::new(message), chunks[3]);
+                f.render_widget(Paragraph::new(message), chunks[4]);
+
+                if let Some(palette) = &mut self.palette {
+                    let area = centered_rect(70, 60, f.size());
+                    let items: Vec<ListItem> = palette_entries
+                        .iter()
+                        .map(|entry| ListItem::new(entry.label.as_str()))
+                        .collect();
+                    let block = Block::default()
+                        .borders(Borders::ALL)
+                        .title("Commands (Enter to pick, Tab to favorite a prompt, Esc to close)");
+                    let inner = block.inner(area);
+                    let parts = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([Constraint::Length(1), Constraint::Min(1)].as_ref())
+                        .split(inner);
+                    f.render_widget(Clear, area);
+                    f.render_widget(block, area);
+                    f.render_widget(Paragraph::new(format!("> {}", palette.query)), parts[0]);
+                    f.render_stateful_widget(
+                        List::new(items)
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+                        parts[1],
+                        &mut palette.state,
+                    );
+                }
+
+                if let Some(picker) = &mut self.session_picker {
+                    let area = centered_rect(80, 60, f.size());
+                    let items: Vec<ListItem> = picker
+                        .sessions
+                        .iter()
+                        .map(|session| ListItem::new(session.to_string()))
+                        .collect();
+                    f.render_widget(Clear, area);
+                    f.render_stateful_widget(
+                        List::new(items)
+                            .block(Block::default().borders(Borders::ALL).title(
+                                "Sessions (Enter to open, n for a new session, d to delete, Esc to close)",
+                            ))
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+                        area,
+                        &mut picker.state,
+                    );
+                }
+
+                if let Some(picker) = &mut self.profile_picker {
+                    let area = centered_rect(60, 40, f.size());
+                    let current = self.session.profile();
+                    let items: Vec<ListItem> = picker
+                        .profiles
+                        .iter()
+                        .map(|(name, label)| {
+                            if name.as_deref() == current {
+                                ListItem::new(format!("{} (current)", label))
+                            } else {
+                                ListItem::new(label.as_str())
+                            }
+                        })
+                        .collect();
+                    f.render_widget(Clear, area);
+                    f.render_stateful_widget(
+                        List::new(items)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Profiles (Enter to switch, Esc to close)"),
+                            )
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+                        area,
+                        &mut picker.state,
+                    );
+                }
+
+                if let Some(picker) = &mut self.model_picker {
+                    let area = centered_rect(50, 60, f.size());
+                    let current = self.session.model();
+                    let items: Vec<ListItem> = picker
+                        .models
+                        .iter()
+                        .map(|model| {
+                            if model == current {
+                                ListItem::new(format!("{} (current)", model))
+                            } else {
+                                ListItem::new(model.as_str())
+                            }
+                        })
+                        .collect();
+                    f.render_widget(Clear, area);
+                    f.render_stateful_widget(
+                        List::new(items)
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Models (Enter to pick, Esc to close)"),
+                            )
+                            .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+                        area,
+                        &mut picker.state,
+                    );
+                }
+
+                if let Some(popup) = &self.popup {
+                    let area = centered_rect(80, 60, f.size());
+                    let text: Vec<Spans> = popup
+                        .lines
+                        .iter()
+                        .map(|line| Spans::from(line.as_str()))
+                        .collect();
+                    f.render_widget(Clear, area);
+                    f.render_widget(
+                        Paragraph::new(text)
+                            .block(Block::default().borders(Borders::ALL).title(format!(
+                                "{} (arrow keys to scroll, any other key to close)",
+                                popup.title
+                            )))
+                            .wrap(Wrap { trim: false })
+                            .scroll((popup.scroll, 0)),
+                        area,
+                    );
+                }
             })
             .ok();
-        match crossterm::event::read().ok().map(Into::into) {
+    }
+
+    fn update_ui(&mut self) -> Option<UiEvent> {
+        if self.read_aloud.as_ref().is_some_and(ReadAloud::is_finished) {
+            self.read_aloud = None;
+        }
+        self.poll_request();
+        self.poll_command();
+        self.autosave_if_due();
+        self.draw();
+        // Redraw periodically while waiting for an answer or a command or reading aloud instead of waiting for the
+        // next event, and wake up in time for the next autosave
+        let refresh = if self.request.is_some() || self.running_command.is_some() {
+            Some(REQUEST_REFRESH)
+        } else if self.read_aloud.is_some() {
+            Some(READ_ALOUD_REFRESH)
+        } else {
+            None
+        };
+        let refresh = match self.autosave.as_ref().and_then(Autosave::due_in) {
+            Some(due) => Some(refresh.map_or(due, |refresh| refresh.min(due))),
+            None => refresh,
+        };
+        // Replay the keys of a macro one at a time, waiting for any answer that a key sent
+        let replayed = match self.request {
+            None => self.replaying.pop_front(),
+            Some(_) => None,
+        };
+        let input = match replayed {
+            Some(input) => Some(input),
+            None => {
+                if let Some(refresh) = refresh {
+                    if !crossterm::event::poll(refresh).unwrap_or(false) {
+                        return None;
+                    }
+                }
+                let input = crossterm::event::read().ok().map(key_input);
+                if let (Some(recording), Some(input)) = (&mut self.recording, &input) {
+                    if !is_macro_control(input) {
+                        recording.push(input.clone());
+                    }
+                }
+                input
+            }
+        };
+        // Messages stay until the next input, since the screen may be redrawn before it is read
+        if let Some(message) = self.error_message.take() {
+            self.log_status(message.into_owned());
+        }
+        if let Some(popup) = &mut self.popup {
+            match input {
+                Some(Input {
+                    key: Key::Up | Key::MouseScrollUp,
+                    ..
+                }) => popup.scroll = popup.scroll.saturating_sub(1),
+                Some(Input {
+                    key: Key::Down | Key::MouseScrollDown,
+                    ..
+                }) => popup.scroll = popup.scroll.saturating_add(1),
+                Some(Input {
+                    key: Key::PageUp, ..
+                }) => popup.scroll = popup.scroll.saturating_sub(10),
+                Some(Input {
+                    key: Key::PageDown, ..
+                }) => popup.scroll = popup.scroll.saturating_add(10),
+                Some(Input { key: Key::Null, .. }) => {}
+                Some(Input {
+                    key: Key::Char(c),
+                    ctrl: false,
+                    alt: false,
+                }) if popup.choices.iter().any(|(key, _)| *key == c) => {
+                    return self.popup.take().and_then(|popup| {
+                        popup
+                            .choices
+                            .into_iter()
+                            .find(|(key, _)| *key == c)
+                            .map(|(_, event)| event)
+                    });
+                }
+                _ => self.popup = None,
+            }
+            return None;
+        }
+        if self.palette.is_some() {
+            return self.palette_input(input?);
+        }
+        if self.session_picker.is_some() {
+            return self.session_picker_input(input?);
+        }
+        if self.model_picker.is_some() {
+            return self.model_picker_input(input?);
+        }
+        if self.profile_picker.is_some() {
+            return self.profile_picker_input(input?);
+        }
+        if self.outline.is_some() {
+            return self.outline_input(input?);
+        }
+        if let (Some(search), Some(key)) = (&self.search, &input) {
+            if search.editing || is_search_key(key) {
+                return self.search_input(key.clone());
+            }
+            // Any other key stops the search and does what it normally does
+            if !matches!(
+                key.key,
+                Key::Null | Key::MouseScrollDown | Key::MouseScrollUp
+            ) {
+                self.search = None;
+            }
+        }
+        if let Some(question) = self.question.take() {
+            match input {
+                Some(Input {
+                    key: Key::Null | Key::MouseScrollDown | Key::MouseScrollUp,
+                    ..
+                })
+                | None => self.question = Some(question),
+                Some(Input {
+                    key: Key::Char(c),
+                    ctrl: false,
+                    alt: false,
+                }) if question.choices.iter().any(|(key, _)| *key == c) => {
+                    return question
+                        .choices
+                        .into_iter()
+                        .find(|(key, _)| *key == c)
+                        .map(|(_, event)| event);
+                }
+                Some(_) => {
+                    if let Some(text) = question.restore_input {
+                        self.input.set_text(&text);
+                    }
+                }
+            }
+            return None;
+        }
+        match input {
+            // Esc and Ctrl+C stop waiting for an answer rather than quitting
+            Some(
+                Input { key: Key::Esc, .. }
+                | Input {
+                    key: Key::Char('c'),
+                    ctrl: true,
+                    alt: false,
+                },
+            ) if self.request.is_some() => {
+                self.cancel_request();
+                None
+            }
             Some(Input { key: Key::Esc, .. }) => Some(UiEvent::Quit),
+            Some(Input { key: Key::F(2), .. }) => {
+                self.toggle_outline();
+                None
+            }
+            Some(Input { key: Key::F(3), .. }) => {
+                self.inspect_entry_at_cursor();
+                None
+            }
+            Some(Input { key: Key::F(4), .. }) => {
+                self.show_metadata = !self.show_metadata;
+                None
+            }
+            Some(Input { key: Key::F(5), .. }) => {
+                self.toggle_read_aloud();
+                None
+            }
+            Some(Input { key: Key::F(6), .. }) => {
+                // Dropping the playback stops it
+                self.read_aloud = None;
+                None
+            }
+            Some(Input { key: Key::F(7), .. }) if !self.read_only => {
+                self.toggle_recording();
+                None
+            }
+            Some(Input {
+                key: Key::Char(digit @ '0'..='9'),
+                ctrl: false,
+                alt: true,
+            }) if !self.read_only => {
+                match self.macros.get(&digit) {
+                    Some(keys) => self.replaying.extend(keys.iter().cloned()),
+                    None => {
+                        self.error_message = Some(format!("No macro bound to Alt+{}", digit).into())
+                    }
+                }
+                None
+            }
             Some(Input {
                 key: Key::Char('s'),
                 ctrl: true,
                 alt: false,
-            }) => Some(UiEvent::SaveSession),
+            }) if !self.read_only => Some(UiEvent::SaveSession),
+            Some(Input {
+                key: Key::Char('f'),
+                ctrl: true,
+                alt: false,
+            }) => {
+                self.open_search();
+                None
+            }
+            Some(Input {
+                key: Key::Char('/'),
+                ctrl: false,
+                alt: false,
+            }) if self.read_only => {
+                self.open_search();
+                None
+            }
+            Some(Input {
+                key: Key::Char('p'),
+                ctrl: true,
+                alt: false,
+            }) if !self.read_only => {
+                self.palette = Some(Palette::open(&self.sessions));
+                None
+            }
+            Some(Input {
+                key: Key::Char('c'),
+                ctrl: false,
+                alt: true,
+            }) => Some(UiEvent::Copy(false)),
+            Some(Input {
+                key: Key::Char('r'),
+                ctrl: true,
+                alt: false,
+            }) if !self.read_only => Some(UiEvent::Regenerate),
+            Some(Input { key: Key::F(9), .. }) => {
+                self.toggle_steps_at_cursor();
+                None
+            }
+            Some(Input { key: Key::F(8), .. }) if !self.read_only => {
+                self.show_sessions();
+                None
+            }
             // Pass through mousescroll events to the message area
             Some(Input {
                 key: Key::MouseScrollDown,
@@ -233,8 +4231,81 @@ impl<'a> ChatTermApp<'a> {
                 });
                 None
             }
-            Some(input) => self.input.input(input).and_then(|message_str| {
-                if !message_str.is_empty() {
+            // Scroll the chat log by pages, or by half pages with Ctrl+U and Ctrl+D
+            Some(Input {
+                key: Key::PageUp, ..
+            }) => {
+                self.message_area.scroll(Scrolling::PageUp);
+                None
+            }
+            Some(Input {
+                key: Key::PageDown, ..
+            }) => {
+                self.message_area.scroll(Scrolling::PageDown);
+                None
+            }
+            Some(Input {
+                key: Key::Char('u'),
+                ctrl: true,
+                alt: false,
+            }) => {
+                self.message_area.scroll(Scrolling::HalfPageUp);
+                None
+            }
+            Some(Input {
+                key: Key::Char('d'),
+                ctrl: true,
+                alt: false,
+            }) => {
+                self.message_area.scroll(Scrolling::HalfPageDown);
+                None
+            }
+            // Go to the start or the end of the chat log while the input box is empty
+            Some(Input { key: Key::Home, .. }) if self.input.textarea.is_empty() => {
+                self.message_area.move_cursor(CursorMove::Top);
+                None
+            }
+            Some(Input { key: Key::End, .. }) if self.input.textarea.is_empty() => {
+                self.message_area.move_cursor(CursorMove::Bottom);
+                None
+            }
+            // Jump between user messages with [ and ] while the input box is empty
+            Some(Input {
+                key: Key::Char(c @ ('[' | ']')),
+                ctrl: false,
+                alt: false,
+            }) if self.input.textarea.is_empty() => {
+                self.jump_to_user_message(c == ']');
+                None
+            }
+            // Only keys that move around the chat log are used in read-only mode
+            Some(Input {
+                key: key @ (Key::Up | Key::Down | Key::Home | Key::End),
+                ..
+            }) if self.read_only => {
+                self.message_area.input(Input {
+                    key,
+                    ..Default::default()
+                });
+                None
+            }
+            Some(_) if self.read_only => None,
+            Some(input) if self.completion_input(&input) => None,
+            Some(input) => {
+                let message = self.input.input(input);
+                self.update_completion();
+                message
+            }
+            .and_then(|message_str| {
+                if message_str.starts_with('/') {
+                    match parse_command(&message_str) {
+                        Ok(event) => Some(event),
+                        Err(err) => {
+                            self.error_message = Some(err.into());
+                            None
+                        }
+                    }
+                } else if !message_str.is_empty() || !self.input.attachments.is_empty() {
                     Some(UiEvent::SendMessage(message_str))
                 } else {
                     None
@@ -248,58 +4319,379 @@ impl<'a> ChatTermApp<'a> {
 impl<'a> Drop for ChatTermApp<'a> {
     fn drop(&mut self) {
         self.term.show_cursor().unwrap();
-        if !is_raw_mode_enabled().unwrap() {
-            return;
-        }
-        disable_raw_mode().unwrap();
-        crossterm::execute!(
-            self.term.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        )
-        .unwrap();
+        restore_terminal(self.term.backend_mut()).unwrap();
+    }
+}
+
+// Put the terminal back the way it was before the app started, unless it already is
+fn restore_terminal(out: &mut impl Write) -> io::Result<()> {
+    if !is_raw_mode_enabled()? {
+        return Ok(());
+    }
+    disable_raw_mode()?;
+    let _ = crossterm::execute!(out, PopKeyboardEnhancementFlags);
+    crossterm::execute!(out, LeaveAlternateScreen, DisableMouseCapture)
+}
+
+// Restores the terminal and exits if quitting takes longer than the timeout, e.g. because a file can't be written
+// or a thread doesn't stop. Quitting is done once it is dropped.
+struct Watchdog {
+    _done: mpsc::Sender<()>,
+}
+
+impl Watchdog {
+    fn start(timeout: Duration) -> Self {
+        let (done, finished) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            if finished.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+            let mut stdout = io::stdout();
+            crossterm::execute!(stdout, Show).ok();
+            restore_terminal(&mut stdout).ok();
+            eprintln!(
+                "Quitting took longer than {} seconds, exiting without finishing",
+                timeout.as_secs()
+            );
+            std::process::exit(1);
+        });
+        Self { _done: done }
     }
 }
 
 pub fn run(
     client: ChatGPTClient,
-    session_file: Option<String>,
+    // Session file given on the command line, along with its path
+    session_file: Option<(String, SessionFile)>,
+    read_only: bool,
+    events: EventLog,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Load session from file if given and pass it to new_session
-    let chatlog = if let Some(filename) = session_file {
-        ChatGPTSession::load_chatlog(&filename)?
-    } else {
-        Vec::new()
-    };
+    // Loading the tokenizer takes a moment, so it is done in the background before the first message is sent
+    let model = client.config.openai_model.clone();
+    thread::spawn(move || count_tokens(&model, ""));
+    let mut session = client.new_session(Vec::new(), 2000);
+    session.set_event_log(events);
 
-    let session = client.new_session(chatlog, 2000);
+    let usage = UsageStore::load(UsageStore::default_path()?)?;
+    let history = PromptHistory::load(PromptHistory::default_path()?)?;
+    let mut app = ChatTermApp::new(session, usage, history)?;
+    app.read_only = read_only;
+    let autosave = &app.session.config().autosave;
+    if autosave.enabled && !read_only {
+        app.autosave = Some(Autosave::new(&Autosave::default_dir()?, autosave));
+    }
+    if app.session.config().memory {
+        let store = MemoryStore::load(MemoryStore::default_path()?)?;
+        app.session.set_memories(store.facts());
+        app.memory = Some(store);
+    }
 
-    // TODO: Separate threads for input events, UI updates, and chatbot responses
-    let mut app = ChatTermApp::new(session)?;
+    // Show the start screen unless a session was given on the command line
+    match session_file {
+        Some((path, file)) => {
+            app.restore_session(file);
+            app.session.set_path(&path);
+        }
+        None => match app.pick_start_entry()? {
+            None => return Ok(()),
+            Some(StartEntry::Recover(autosaved)) => app.recover_session(autosaved),
+            Some(StartEntry::NewConversation) => {}
+            Some(StartEntry::Persona(persona)) => {
+                app.session.set_persona(&persona);
+                if persona.continue_previous {
+                    app.continue_previous(&persona.name);
+                }
+            }
+            Some(StartEntry::Session(session)) => app.open_session(&session.path)?,
+        },
+    }
     loop {
         if let Some(ui_event) = app.update_ui() {
+            // The session can't change while a message is being sent
+            if app.request.is_some()
+                && !matches!(
+                    ui_event,
+                    UiEvent::Quit
+                        | UiEvent::BindMacro(..)
+                        | UiEvent::Help
+                        | UiEvent::ShowLog
+                        | UiEvent::ShowUsage
+                        | UiEvent::ShowFullResponse
+                        | UiEvent::ShowStats
+                        | UiEvent::ShowOutline
+                        | UiEvent::ShowQueue
+                        | UiEvent::ShowMemories
+                        | UiEvent::Present
+                        | UiEvent::StopPresenting
+                        | UiEvent::ApproveTool
+                        | UiEvent::Copy(_)
+                        | UiEvent::ExtractCode(..)
+                        | UiEvent::SaveCode(..)
+                        | UiEvent::AttachFile(_)
+                        | UiEvent::RunCommand(_)
+                )
+            {
+                if let UiEvent::SendMessage(message_str) = &ui_event {
+                    app.input.set_text(message_str);
+                }
+                app.error_message = Some("Wait for the answer to arrive first".into());
+                continue;
+            }
+            let changes_session = ui_event.changes_session();
             match ui_event {
-                UiEvent::SendMessage(message_str) => match app.session.send_message(&message_str) {
-                    Ok(entry) => {
-                        let width = app.term.get_frame().size().width as usize - 4;
-                        ChatTermApp::add_chatlog_entry(&mut app.message_area, &entry, width);
-                    }
-                    Err(err) => {
-                        app.input.set_error(Some(format!("Error: {:?}", err)));
+                UiEvent::SendMessage(message_str) => {
+                    let attachments = app.input.take_attachments();
+                    app.remember_attached_files(&attachments);
+                    let message_str = attach(&message_str, &attachments);
+                    let message_str = app.normalize_message(message_str);
+                    app.check_and_send(&message_str, 0)
+                }
+                UiEvent::ContinueSending(message_str, next) => {
+                    app.check_and_send(&message_str, next)
+                }
+                UiEvent::PinAndContinueSending(entries, message_str, next) => {
+                    app.session.pin_entries(&entries);
+                    app.check_and_send(&message_str, next);
+                }
+                UiEvent::SummarizeAndContinueSending(entries, message_str, next) => {
+                    match app.session.summarize_entries(&entries) {
+                        Ok(()) => app.check_and_send(&message_str, next),
+                        Err(err) => {
+                            app.report_error(&err);
+                            app.input.set_text(&message_str);
+                        }
                     }
-                },
-                UiEvent::SaveSession => match app.session.save_chatlog() {
-                    Ok(filename) => {
-                        app.error_message = Some(format!("Saved session to {}", filename).into());
+                }
+                UiEvent::RephraseAndRetry(message_str) => {
+                    match app.session.rephrase(&message_str) {
+                        Ok(rephrased) => app.check_and_send(&rephrased, 0),
+                        Err(err) => {
+                            app.report_error(&err);
+                            app.input.set_text(&message_str);
+                        }
                     }
-                    Err(err) => {
+                }
+                UiEvent::SaveSession => app.save_session(),
+                UiEvent::Checkpoint(name) => {
+                    app.session.add_checkpoint(&name);
+                    app.error_message = Some(format!("Added checkpoint {}", name).into());
+                }
+                UiEvent::Rollback(name) => app.rollback(&name, false),
+                UiEvent::Fork(name) => app.rollback(&name, true),
+                UiEvent::AbMode(argument) => app.set_ab_mode(&argument),
+                UiEvent::ToggleLowBandwidth => {
+                    let on = !app.session.low_bandwidth();
+                    app.session.set_low_bandwidth(on);
+                    app.error_message = Some(
+                        if on {
+                            "Low-bandwidth mode on: short answers without streaming and more retries"
+                        } else {
+                            "Low-bandwidth mode off"
+                        }
+                        .into(),
+                    );
+                }
+                UiEvent::Copy(code) => app.copy_at_cursor(code),
+                UiEvent::ExtractCode(number, path) => app.extract_code_at_cursor(number, path),
+                UiEvent::SaveCode(path, code) => app.save_code(&path, &code),
+                UiEvent::Export(format, path) => {
+                    app.error_message = Some(
+                        match std::fs::write(&path, app.session.export(format)) {
+                            Ok(()) => format!("Exported the conversation to {}", path),
+                            Err(err) => format!("Error exporting to {}: {}", path, err),
+                        }
+                        .into(),
+                    );
+                }
+                UiEvent::AttachFile(path) => app.attach_file(&path),
+                UiEvent::RunCommand(command) => app.attach_command_output(&command),
+                UiEvent::SetSystemPrompt(prompt) => {
+                    app.session.set_system_prompt(&prompt);
+                    app.error_message = Some("Set the system prompt of this session".into());
+                }
+                UiEvent::SetTitle(title) => {
+                    app.session.set_title(&title);
+                    app.error_message = Some(format!("Set title to {}", title).into());
+                }
+                UiEvent::Rate(rating) => app.annotate_at_cursor(Some(rating), None),
+                UiEvent::Note(note) => app.annotate_at_cursor(None, Some(note)),
+                UiEvent::AddTag(tag) => {
+                    app.session.set_tag(&tag, true);
+                    app.error_message = Some(format!("Tagged session with #{}", tag).into());
+                }
+                UiEvent::RemoveTag(tag) => {
+                    app.session.set_tag(&tag, false);
+                    app.error_message = Some(format!("Removed tag #{}", tag).into());
+                }
+                UiEvent::OpenSession(path) => {
+                    if let Err(err) = app.open_session(&path) {
                         app.error_message = Some(format!("Error: {:?}", err).into());
                     }
+                }
+                UiEvent::ShowSessions => app.show_sessions(),
+                UiEvent::ShowModels => app.show_models(),
+                UiEvent::Regenerate => app.regenerate(),
+                UiEvent::ShowQueue => app.popup = Some(Popup::queue(&app.queued)),
+                UiEvent::SendQueue => {
+                    if app.queued.is_empty() {
+                        app.error_message = Some("No messages are queued".into());
+                    } else {
+                        app.send_queue();
+                    }
+                }
+                UiEvent::ClearQueue => {
+                    app.error_message =
+                        Some(format!("Dropped {} queued messages", app.queued.len()).into());
+                    app.queued.clear();
+                }
+                UiEvent::Remember(Some(fact)) => app.remember(&fact),
+                UiEvent::Remember(None) => app.extract_memories(),
+                UiEvent::ReviewMemories(facts) => app.review_memories(facts),
+                UiEvent::ApproveMemory(facts) => {
+                    app.remember(&facts[0]);
+                    app.review_memories(facts[1..].to_vec());
+                }
+                UiEvent::ShowMemories => {
+                    let locale = app.locale;
+                    if let Some(store) = app.memory_store() {
+                        let popup = Popup::memories(store.memories(), &locale);
+                        app.popup = Some(popup);
+                    }
+                }
+                UiEvent::ForgetMemory(number) => app.forget_memory(number),
+                UiEvent::ClearMemories => app.clear_memories(),
+                UiEvent::Present => app.present(),
+                UiEvent::StopPresenting => {
+                    app.error_message = Some(match app.presentation.take() {
+                        Some(presentation) if !presentation.is_finished() => {
+                            "Stopped presenting the conversation".into()
+                        }
+                        _ => "The conversation isn't being presented".into(),
+                    });
+                }
+                UiEvent::ShowProfiles => app.show_profiles(),
+                UiEvent::SetProfile(name) => app.set_profile(name.as_deref()),
+                UiEvent::SetBackend(argument) => app.set_backend(&argument),
+                UiEvent::NewSession => match app.sessions.create(&mut app.session) {
+                    Ok(()) => {
+                        app.expanded_steps.clear();
+                        app.refresh_message_area();
+                        app.error_message = Some("Started a new session".into());
+                    }
+                    Err(err) => app.error_message = Some(format!("Error: {:?}", err).into()),
                 },
+                UiEvent::DeleteSession(path, wipe) => {
+                    match app.sessions.delete(&app.session, &path, wipe) {
+                        Ok(()) if wipe => {
+                            app.error_message = Some(format!("Wiped and deleted {}", path).into())
+                        }
+                        Ok(()) => app.error_message = Some(format!("Deleted {}", path).into()),
+                        Err(err) => app.error_message = Some(format!("Error: {}", err).into()),
+                    }
+                    // Go back to the list, without the deleted session
+                    app.show_sessions();
+                }
+                UiEvent::BindMacro(digit, keys) => {
+                    app.macros.insert(digit, keys);
+                    app.error_message = Some(format!("Bound the macro to Alt+{}", digit).into());
+                }
+                UiEvent::Help => app.popup = Some(Popup::help()),
+                UiEvent::ReviewEdit(edit) => app.popup = Some(Popup::edit(&edit)),
+                UiEvent::ApplyEdit(edit) => app.apply_edit(&edit),
+                UiEvent::ShowLog => {
+                    app.popup = Some(Popup::status_log(&app.status_log, &app.locale))
+                }
+                UiEvent::ShowUsage => {
+                    let popup = Popup::usage(
+                        app.session.usage(),
+                        app.session.cost(),
+                        &app.usage,
+                        &app.locale,
+                    );
+                    app.popup = Some(popup);
+                }
+                UiEvent::ShowFullResponse => app.show_full_response(),
+                UiEvent::ShowStats => app.show_stats(),
+                UiEvent::ShowOutline => {
+                    if app.outline.is_none() {
+                        app.toggle_outline();
+                    }
+                }
+                UiEvent::GenerateHeadings => app.generate_headings(),
+                UiEvent::SaveSessionAs(name) => {
+                    app.session.set_name(&name);
+                    match app.session.save_chatlog() {
+                        Ok(filename) => {
+                            app.error_message =
+                                Some(format!("Saved session to {}", filename).into());
+                        }
+                        Err(err) => {
+                            app.error_message = Some(format!("Error: {:?}", err).into());
+                        }
+                    }
+                }
+                UiEvent::Reset => {
+                    app.session.reset();
+                    app.expanded_steps.clear();
+                    app.refresh_message_area();
+                    app.error_message = Some("Started a new conversation".into());
+                }
+                UiEvent::SetModel(model) => {
+                    app.session.set_model(&model);
+                    app.error_message = Some(format!("Sending messages to {}", model).into());
+                }
+                UiEvent::SetSampling(name, value) => {
+                    app.error_message = Some(
+                        match app.session.set_sampling(&name, &value) {
+                            Ok(()) if value == "default" => {
+                                format!("{} is left to the API's default", name)
+                            }
+                            Ok(()) => format!("Set {} to {}", name, value),
+                            Err(err) => err,
+                        }
+                        .into(),
+                    );
+                }
+                UiEvent::Agent(goal) => app.start_agent(&goal),
+                UiEvent::ApproveTool => {
+                    if let Some(approval) = app
+                        .request
+                        .as_mut()
+                        .and_then(|request| request.approval.take())
+                    {
+                        approval.send(true).ok();
+                    }
+                }
                 UiEvent::Quit => break,
             }
+            if changes_session {
+                app.session_changed();
+            }
         }
     }
 
+    app.shutdown();
+    let mut saved = None;
+    if app.session.config().save_on_quit && !app.read_only && !app.session.get_chatlog().is_empty()
+    {
+        if let Some(name) = app.session.suggested_name() {
+            app.session.set_name(&name);
+        }
+        if app.session.metadata().title.is_none() {
+            if let Some(title) = app.session.generated_title() {
+                app.session.set_title(&title);
+            }
+        }
+        saved = Some(app.session.save_chatlog()?);
+    }
+    // Quitting is a clean exit, so there is nothing to recover at the next start
+    if let Some(autosave) = &app.autosave {
+        autosave.remove()?;
+    }
+    // Restore the terminal before telling where the session went
+    drop(app);
+    if let Some(filename) = saved {
+        println!("Saved session to {}", filename);
+    }
     Ok(())
 }
@@ -0,0 +1,97 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::fence::{detect_language, fence_code};
+use crate::sandbox::{run_sandboxed, SandboxPolicy};
+
+// Files longer than this are attached in part
+const MAX_ATTACHMENT_BYTES: u64 = 64 * 1024;
+
+// Commands run with /run are killed after this many seconds, and only the start of their output is kept
+const COMMAND_TIMEOUT_SECS: u64 = 120;
+const MAX_COMMAND_OUTPUT_BYTES: usize = 16 * 1024;
+
+/// Text sent along with the next message, such as the contents of a file
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    // What the text is, e.g. "File `src/main.rs`"
+    pub title: String,
+    pub text: String,
+    // Tag of the fenced block the text is sent in
+    pub language: Option<String>,
+    // File the text was read from, if it was read whole, so that new versions of it can be applied
+    pub path: Option<String>,
+}
+
+impl Attachment {
+    /// The contents of a text file, up to the size limit
+    pub fn file(path: &str) -> Result<Self, String> {
+        let error = |err: std::io::Error| format!("{}: {}", path, err);
+        let file = std::fs::File::open(path).map_err(error)?;
+        let size = file.metadata().map_err(error)?.len();
+        let mut contents = Vec::new();
+        file.take(MAX_ATTACHMENT_BYTES)
+            .read_to_end(&mut contents)
+            .map_err(error)?;
+        if contents.contains(&0) {
+            return Err(format!("{} is not a text file", path));
+        }
+        let text = String::from_utf8_lossy(&contents).into_owned();
+        let mut title = format!("File `{}`", path);
+        if size > MAX_ATTACHMENT_BYTES {
+            title.push_str(&format!(
+                " (the first {} of {} bytes)",
+                MAX_ATTACHMENT_BYTES, size
+            ));
+        }
+        // The extension makes a better tag than a guess, e.g. `rs` or `toml`
+        let language = Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .or_else(|| detect_language(&text).map(String::from));
+        Ok(Self {
+            title,
+            text,
+            language,
+            path: (size <= MAX_ATTACHMENT_BYTES).then(|| path.to_string()),
+        })
+    }
+
+    /// What a command printed on stdout and stderr, run with `sh` in the current directory. Unlike tools, commands
+    /// typed by the user are not sandboxed.
+    pub fn command_output(command: &str) -> Result<Self, String> {
+        let policy = SandboxPolicy {
+            temp_dir: false,
+            allow_network: true,
+            timeout_secs: COMMAND_TIMEOUT_SECS,
+            max_output_bytes: MAX_COMMAND_OUTPUT_BYTES,
+        };
+        // The shell reads the command from stdin, so the command itself gets no input. Grouping it makes the shell
+        // read all of it before running any of it, and lets stderr be interleaved with stdout.
+        let script = format!("{{ {}\n}} </dev/null 2>&1\n", command);
+        let output = run_sandboxed("sh -s", &script, &policy).map_err(|err| err.to_string())?;
+        Ok(Self {
+            title: format!("Output of `{}`", command),
+            text: output,
+            language: None,
+            path: None,
+        })
+    }
+
+    /// The attachment as markdown, a title followed by a fenced block
+    pub fn to_markdown(&self) -> String {
+        format!(
+            "{}:\n{}",
+            self.title,
+            fence_code(&self.text, self.language.as_deref())
+        )
+    }
+}
+
+/// A message followed by its attachments, which may also be sent without a message
+pub fn attach(message: &str, attachments: &[Attachment]) -> String {
+    let mut parts = vec![message.to_string()];
+    parts.extend(attachments.iter().map(Attachment::to_markdown));
+    parts.retain(|part| !part.is_empty());
+    parts.join("\n\n")
+}
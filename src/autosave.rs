@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::api::{ChatGPTSession, SessionFile};
+
+/// Saving the session to a well-known file as it goes, so that it can be recovered after a crash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveConfig {
+    pub enabled: bool,
+    // Shortest time between autosaves, 0 to save after every exchange
+    pub interval_secs: u64,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_secs: 10,
+        }
+    }
+}
+
+/// A session left behind by a run of the app that didn't exit cleanly
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AutosavedSession {
+    // Name of the file the session is saved to, so that saving it again goes to the same file
+    pub name: String,
+    pub saved: DateTime<Local>,
+    pub file: SessionFile,
+    // The autosave file it was read from, to be removed once the session is recovered
+    #[serde(skip)]
+    pub source: PathBuf,
+}
+
+// The process that wrote an autosave file, from its name
+fn writer_pid(path: &Path) -> Option<u32> {
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix("autosave-")?
+        .parse()
+        .ok()
+}
+
+// Whether a process is still running, in which case its autosave file is in use rather than left behind. Other
+// platforms can't tell, so files of running instances may be offered there too.
+fn is_running(pid: u32) -> bool {
+    #[cfg(unix)]
+    {
+        // Signal 0 only checks that the process exists; EPERM means it does but belongs to someone else
+        let exists = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+        exists || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        false
+    }
+}
+
+/// Writes the session to an autosave file of its own once it has changed, no more often than the configured
+/// interval. Each running instance of the app has its own file, named after its process ID, which is removed when
+/// it exits cleanly, so finding one of another process that is no longer running means that it crashed.
+pub struct Autosave {
+    path: PathBuf,
+    interval: Duration,
+    last_saved: Option<Instant>,
+    pending: bool,
+}
+
+impl Autosave {
+    /// Default directory of the autosave files, the one of the config file
+    pub fn default_dir() -> Result<PathBuf, Box<dyn Error>> {
+        let config_path = confy::get_configuration_file_path("chatgpt-term", None)?;
+        Ok(config_path.with_file_name(""))
+    }
+
+    /// Autosave to a file of this process in the given directory
+    pub fn new(dir: &Path, config: &AutosaveConfig) -> Self {
+        Self {
+            path: dir.join(format!("autosave-{}.json", std::process::id())),
+            interval: Duration::from_secs(config.interval_secs),
+            last_saved: None,
+            pending: false,
+        }
+    }
+
+    /// The most recent session left behind by a run that didn't exit cleanly, if any, including the autosave.json
+    /// of older versions. Files that can't be read are ignored.
+    pub fn recover(&self) -> Option<AutosavedSession> {
+        let dir = self.path.parent()?;
+        std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("");
+                let left_behind = match writer_pid(path) {
+                    Some(pid) => !is_running(pid),
+                    None => name == "autosave.json",
+                };
+                *path != self.path && left_behind && name.ends_with(".json")
+            })
+            .filter_map(|path| {
+                let json = std::fs::read_to_string(&path).ok()?;
+                let autosaved: AutosavedSession = serde_json::from_str(&json).ok()?;
+                Some(AutosavedSession {
+                    source: path,
+                    ..autosaved
+                })
+            })
+            .max_by_key(|autosaved| autosaved.saved)
+    }
+
+    /// Note that the session changed and needs to be saved
+    pub fn mark_changed(&mut self) {
+        self.pending = true;
+    }
+
+    /// How long until the changes are due to be saved, or None if there are none
+    pub fn due_in(&self) -> Option<Duration> {
+        if !self.pending {
+            return None;
+        }
+        Some(match self.last_saved {
+            Some(saved) => self.interval.saturating_sub(saved.elapsed()),
+            None => Duration::ZERO,
+        })
+    }
+
+    /// Save the session if it changed and the interval has passed since the last autosave
+    pub fn save_if_due(&mut self, session: &ChatGPTSession) -> io::Result<()> {
+        if self.due_in() != Some(Duration::ZERO) {
+            return Ok(());
+        }
+        self.save(session)
+    }
+
+    /// Save the changes not saved yet right away, whether or not the interval has passed
+    pub fn flush(&mut self, session: &ChatGPTSession) -> io::Result<()> {
+        if !self.pending {
+            return Ok(());
+        }
+        self.save(session)
+    }
+
+    fn save(&mut self, session: &ChatGPTSession) -> io::Result<()> {
+        let autosaved = AutosavedSession {
+            name: session.file_name(),
+            saved: Local::now(),
+            file: session.session_file(),
+            source: self.path.clone(),
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Written aside and moved into place, so that a crash while saving doesn't leave half a file
+        let temporary = self.path.with_extension("json.tmp");
+        std::fs::write(&temporary, serde_json::to_string(&autosaved)?)?;
+        std::fs::rename(&temporary, &self.path)?;
+        self.last_saved = Some(Instant::now());
+        self.pending = false;
+        Ok(())
+    }
+
+    /// Remove the autosave file when the app exits cleanly
+    pub fn remove(&self) -> io::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::anthropic::Anthropic;
+use crate::api::{ChatGPTClient, ChatLogEntry, Message, ResponseMetadata};
+use crate::errors::ChatGptError;
+use crate::ollama::Ollama;
+use crate::sampling::SamplingParams;
+use crate::tools::Tool;
+use crate::ChatTermConfig;
+
+/// The next message of a conversation to ask a backend for. The model and the address of the server are in the
+/// config of the client making the request.
+pub struct ChatRequest<'a> {
+    // The system message first, if any
+    pub messages: &'a [Message],
+    pub sampling: &'a SamplingParams,
+    // Tools the model may ask to run, which backends without function calling leave out
+    pub tools: &'a [Tool],
+}
+
+/// A provider of chat models, turning requests into calls to its API and its answers into chat log entries. The
+/// client passed along sends the HTTP requests, holds the config and says whether the user cancelled. Failed
+/// requests are tried again by the client, so backends make a single attempt.
+pub trait ChatBackend: Send + Sync {
+    /// Ask for the whole answer at once
+    fn send(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError>;
+
+    /// Ask for the answer, passing each piece of it to `on_token` as it is generated
+    fn stream(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError>;
+
+    /// Names of the chat models that can be used, sorted
+    fn list_models(&self, client: &ChatGPTClient) -> Result<Vec<String>, ChatGptError>;
+}
+
+/// The chat completions API of OpenAI, or of another server with a compatible API such as Azure
+pub struct OpenAi;
+
+impl ChatBackend for OpenAi {
+    fn send(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        client.send_request_once(
+            request.messages.to_vec(),
+            request.sampling,
+            request.tools,
+            None,
+        )
+    }
+
+    fn stream(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        client.send_request_once(
+            request.messages.to_vec(),
+            request.sampling,
+            request.tools,
+            Some(on_token),
+        )
+    }
+
+    fn list_models(&self, client: &ChatGPTClient) -> Result<Vec<String>, ChatGptError> {
+        client.list_openai_models()
+    }
+}
+
+/// Which backend messages are sent to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    // OpenAI, Azure or another server with a compatible API, at api_base_url
+    #[default]
+    OpenAi,
+    // Claude models of Anthropic, as set in the [anthropic] table
+    Anthropic,
+    // Local models served by Ollama, as set in the [ollama] table
+    Ollama,
+}
+
+impl BackendKind {
+    /// The backend making the requests
+    pub fn backend(self) -> &'static dyn ChatBackend {
+        match self {
+            BackendKind::OpenAi => &OpenAi,
+            BackendKind::Anthropic => &Anthropic,
+            BackendKind::Ollama => &Ollama,
+        }
+    }
+
+    /// Model used unless another one is chosen, from the config of the backend
+    pub fn default_model(self, config: &ChatTermConfig) -> &str {
+        match self {
+            BackendKind::OpenAi => &config.openai_model,
+            BackendKind::Anthropic => &config.anthropic.model,
+            BackendKind::Ollama => &config.ollama.model,
+        }
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "openai" => Ok(BackendKind::OpenAi),
+            "anthropic" | "claude" => Ok(BackendKind::Anthropic),
+            "ollama" => Ok(BackendKind::Ollama),
+            _ => Err(format!(
+                "Unknown backend {}, use openai, anthropic or ollama",
+                name
+            )),
+        }
+    }
+}
+
+impl Display for BackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BackendKind::OpenAi => "openai",
+            BackendKind::Anthropic => "anthropic",
+            BackendKind::Ollama => "ollama",
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Persona;
+
+/// Version of the persona bundle format written by this build
+pub const BUNDLE_FORMAT_VERSION: u64 = 1;
+
+/// A persona saved to a file of its own so that it can be shared
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersonaBundle {
+    pub format_version: u64,
+    pub persona: Persona,
+}
+
+/// Write a persona to a bundle file
+pub fn export_persona(persona: &Persona, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let bundle = PersonaBundle {
+        format_version: BUNDLE_FORMAT_VERSION,
+        persona: persona.clone(),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&bundle)?)?;
+    Ok(())
+}
+
+/// Read the persona from a bundle file
+pub fn read_persona_bundle(path: &str) -> Result<Persona, Box<dyn std::error::Error>> {
+    let bundle: PersonaBundle = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    if bundle.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(format!(
+            "{} was written by a newer version (bundle format {})",
+            path, bundle.format_version
+        )
+        .into());
+    }
+    Ok(bundle.persona)
+}
+
+/// First name not used by any of the personas, adding a number to the given one if needed
+pub fn unique_persona_name(personas: &[Persona], name: &str) -> String {
+    let is_taken = |candidate: &str| personas.iter().any(|persona| persona.name == candidate);
+    if !is_taken(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|number| format!("{} ({})", name, number))
+        .find(|candidate| !is_taken(candidate))
+        .unwrap()
+}
+
+/// Add an imported persona to a list of personas. A persona with the same name is replaced if `overwrite` is set,
+/// otherwise the imported one is renamed. Returns the name it was added under.
+pub fn add_persona(personas: &mut Vec<Persona>, mut persona: Persona, overwrite: bool) -> String {
+    if overwrite {
+        personas.retain(|existing| existing.name != persona.name);
+    } else {
+        persona.name = unique_persona_name(personas, &persona.name);
+    }
+    let name = persona.name.clone();
+    personas.push(persona);
+    name
+}
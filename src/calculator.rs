@@ -0,0 +1,281 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::tools::Tool;
+
+/// Name of the built-in tool the model calls to compute things
+pub const CALCULATOR_TOOL: &str = "calculate";
+
+// Whole numbers from here on can't all be represented, so they are not written out in full
+const EXACT_INTEGER_LIMIT: f64 = 9007199254740992.0;
+
+// Deepest nesting of parentheses, signs and powers evaluated, well within the stack of any thread
+const MAX_DEPTH: usize = 256;
+
+type Function = fn(f64) -> f64;
+
+// Functions of one argument the evaluator knows
+const FUNCTIONS: &[(&str, Function)] = &[
+    ("sqrt", f64::sqrt),
+    ("cbrt", f64::cbrt),
+    ("abs", f64::abs),
+    ("exp", f64::exp),
+    ("ln", f64::ln),
+    ("log10", f64::log10),
+    ("log2", f64::log2),
+    ("sin", f64::sin),
+    ("cos", f64::cos),
+    ("tan", f64::tan),
+    ("asin", f64::asin),
+    ("acos", f64::acos),
+    ("atan", f64::atan),
+    ("floor", f64::floor),
+    ("ceil", f64::ceil),
+    ("round", f64::round),
+];
+
+/// Settings of the built-in calculator tool, which is only offered to the model when they are in the config
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalculatorConfig {
+    // Run Python code in the tool sandbox instead of evaluating arithmetic expressions, which allows for more than
+    // arithmetic but needs python3
+    pub python: bool,
+    // Run Python code without asking first. The built-in evaluator can't do any harm and never asks.
+    pub auto_approve: bool,
+}
+
+impl CalculatorConfig {
+    /// The tool offered to the model
+    pub fn tool(&self) -> Tool {
+        let (description, parameters) = if self.python {
+            (
+                "Run Python 3 code and return what it prints. Use it to compute any numeric result exactly instead \
+                of working it out yourself, and print the result.",
+                json!({
+                    "type": "object",
+                    "properties": {"code": {"type": "string", "description": "Python code to run"}},
+                    "required": ["code"]
+                }),
+            )
+        } else {
+            (
+                "Evaluate an arithmetic expression. Use it to compute any numeric result exactly instead of working \
+                it out yourself. Supports numbers, + - * / % ^, parentheses, the constants pi and e and the \
+                functions sqrt, cbrt, abs, exp, ln, log10, log2, sin, cos, tan, asin, acos, atan, floor, ceil, \
+                round, min and max.",
+                json!({
+                    "type": "object",
+                    "properties": {"expression": {"type": "string", "description": "Expression to evaluate"}},
+                    "required": ["expression"]
+                }),
+            )
+        };
+        Tool {
+            name: CALCULATOR_TOOL.to_string(),
+            description: description.to_string(),
+            command: String::new(),
+            auto_approve: !self.python || self.auto_approve,
+            parameters,
+        }
+    }
+}
+
+// Recursive descent parser evaluating an expression as it goes
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    // How deeply the unary currently being evaluated is nested
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    // Consume the next character if it is the given one, after any whitespace
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&expected).is_some()
+    }
+
+    // sum = product (("+" | "-") product)*
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    // product = unary (("*" | "/" | "%") unary)*
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            self.skip_whitespace();
+            let mut lookahead = self.chars.clone();
+            match lookahead.next() {
+                // ** is a power, handled further down
+                Some('*') if lookahead.next() != Some('*') => {
+                    self.chars.next();
+                    value *= self.unary()?;
+                }
+                Some('/') => {
+                    self.chars.next();
+                    let divisor = self.unary()?;
+                    if divisor == 0.0 {
+                        return Err(String::from("division by zero"));
+                    }
+                    value /= divisor;
+                }
+                Some('%') => {
+                    self.chars.next();
+                    value %= self.unary()?;
+                }
+                _ => return Ok(value),
+            }
+        }
+    }
+
+    // unary = "-" unary | "+" unary | power. Every nested expression goes through here, so this is where the depth
+    // is limited.
+    fn unary(&mut self) -> Result<f64, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(String::from("the expression is nested too deeply"));
+        }
+        let value = if self.eat('-') {
+            self.unary().map(|value| -value)
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    // power = atom (("^" | "**") unary)?, which is right associative and binds tighter than a minus in front
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.atom()?;
+        self.skip_whitespace();
+        let mut lookahead = self.chars.clone();
+        let operator = match (lookahead.next(), lookahead.next()) {
+            (Some('^'), _) => 1,
+            (Some('*'), Some('*')) => 2,
+            _ => return Ok(base),
+        };
+        for _ in 0..operator {
+            self.chars.next();
+        }
+        Ok(base.powf(self.unary()?))
+    }
+
+    // atom = number | constant | function "(" arguments ")" | "(" sum ")"
+    fn atom(&mut self) -> Result<f64, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some(c) if c.is_ascii_digit() || *c == '.' => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.name(),
+            Some('(') => {
+                self.chars.next();
+                let value = self.sum()?;
+                if !self.eat(')') {
+                    return Err(String::from("missing closing parenthesis"));
+                }
+                Ok(value)
+            }
+            Some(c) => Err(format!("unexpected '{}'", c)),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let mut text = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_digit() || *c == '.' || *c == '_' || *c == 'e' || *c == 'E')
+        {
+            // Allow a sign in the exponent, as in 1e-3
+            if c == 'e' || c == 'E' {
+                text.push(c);
+                text.extend(self.chars.next_if(|c| *c == '-' || *c == '+'));
+            } else if c != '_' {
+                text.push(c);
+            }
+        }
+        text.parse().map_err(|_| format!("invalid number {}", text))
+    }
+
+    fn name(&mut self) -> Result<f64, String> {
+        let mut name = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphanumeric()) {
+            name.push(c);
+        }
+        match name.as_str() {
+            "pi" => return Ok(std::f64::consts::PI),
+            "e" => return Ok(std::f64::consts::E),
+            _ => {}
+        }
+        if !self.eat('(') {
+            return Err(format!("unknown name {}", name));
+        }
+        let mut arguments = vec![self.sum()?];
+        while self.eat(',') {
+            arguments.push(self.sum()?);
+        }
+        if !self.eat(')') {
+            return Err(format!(
+                "missing closing parenthesis after the arguments of {}",
+                name
+            ));
+        }
+        let function = FUNCTIONS.iter().find(|(known, _)| *known == name);
+        match (name.as_str(), function, arguments.as_slice()) {
+            (_, Some((_, function)), [argument]) => Ok(function(*argument)),
+            ("min", _, [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, &b| a.min(b))),
+            ("max", _, [first, rest @ ..]) => Ok(rest.iter().fold(*first, |a, &b| a.max(b))),
+            ("pow", _, [base, exponent]) => Ok(base.powf(*exponent)),
+            (_, Some(_), _) => Err(format!("{} takes one argument", name)),
+            _ => Err(format!("unknown function {}", name)),
+        }
+    }
+}
+
+/// Evaluate an arithmetic expression
+pub fn evaluate(expression: &str) -> Result<f64, String> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+        depth: 0,
+    };
+    let value = parser.sum()?;
+    parser.skip_whitespace();
+    if let Some(c) = parser.chars.next() {
+        return Err(format!("unexpected '{}'", c));
+    }
+    if !value.is_finite() {
+        return Err(String::from("the result is not a finite number"));
+    }
+    Ok(value)
+}
+
+/// A result written in full if it is a whole number that is represented exactly. Other results are rounded to the
+/// 15 significant digits that floating point gets right, so that 0.1 + 0.2 is 0.3, and numbers too large to be exact
+/// or too small to write out are written in scientific notation.
+pub fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < EXACT_INTEGER_LIMIT {
+        return format!("{}", value as i64);
+    }
+    let scientific = value.abs() >= EXACT_INTEGER_LIMIT || value.abs() < 1e-6;
+    let value: f64 = format!("{:.14e}", value).parse().unwrap_or(value);
+    if scientific {
+        format!("{:e}", value)
+    } else {
+        format!("{}", value)
+    }
+}
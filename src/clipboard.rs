@@ -0,0 +1,30 @@
+use std::io::Write;
+
+use base64::Engine;
+
+/// Copies text to the system clipboard. Where there is none, such as over SSH, the text is handed to the terminal
+/// with the OSC 52 escape sequence, which most terminals put on the clipboard of the machine they run on.
+#[derive(Default)]
+pub struct Clipboard {
+    // Kept open, since on X11 the copied text is only available while the clipboard that copied it exists
+    system: Option<arboard::Clipboard>,
+}
+
+impl Clipboard {
+    /// Copy the text, returning where it went
+    pub fn copy(&mut self, text: &str) -> Result<&'static str, Box<dyn std::error::Error>> {
+        if self.system.is_none() {
+            self.system = arboard::Clipboard::new().ok();
+        }
+        if let Some(system) = &mut self.system {
+            if system.set_text(text).is_ok() {
+                return Ok("the clipboard");
+            }
+        }
+        let encoded = base64::engine::general_purpose::STANDARD.encode(text);
+        let mut stdout = std::io::stdout();
+        write!(stdout, "\x1b]52;c;{}\x07", encoded)?;
+        stdout.flush()?;
+        Ok("the terminal's clipboard")
+    }
+}
@@ -0,0 +1,345 @@
+use crate::api::Rating;
+use crate::app::UiEvent;
+use crate::export::ExportFormat;
+
+/// A command typed in the input box, starting with a slash
+pub struct SlashCommand {
+    pub name: &'static str,
+    // Arguments, shown in usage messages and by /help
+    pub arguments: &'static str,
+    pub description: &'static str,
+    // The event the command stands for given its argument, or None if the argument is not valid
+    parse: fn(&str) -> Option<UiEvent>,
+}
+
+impl SlashCommand {
+    /// How to use the command, e.g. `/title <title>`
+    pub fn usage(&self) -> String {
+        if self.arguments.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.arguments)
+        }
+    }
+}
+
+// Commands that need an argument
+fn required(argument: &str, event: fn(String) -> UiEvent) -> Option<UiEvent> {
+    (!argument.is_empty()).then(|| event(argument.to_string()))
+}
+
+// Commands that take no argument
+fn no_argument(argument: &str, event: UiEvent) -> Option<UiEvent> {
+    argument.is_empty().then_some(event)
+}
+
+/// Commands available in the input box, in the order they are listed by /help. New commands are added here.
+pub const COMMANDS: &[SlashCommand] = &[
+    SlashCommand {
+        name: "help",
+        arguments: "",
+        description: "List the available commands",
+        parse: |argument| no_argument(argument, UiEvent::Help),
+    },
+    SlashCommand {
+        name: "save",
+        arguments: "[name]",
+        description: "Save the session, under a new name if given",
+        parse: |argument| {
+            Some(match argument {
+                "" => UiEvent::SaveSession,
+                name => UiEvent::SaveSessionAs(name.to_string()),
+            })
+        },
+    },
+    SlashCommand {
+        name: "reset",
+        arguments: "",
+        description: "Start a new conversation",
+        parse: |argument| no_argument(argument, UiEvent::Reset),
+    },
+    SlashCommand {
+        name: "regen",
+        arguments: "",
+        description: "Send the last message again for another answer in place of the last one",
+        parse: |argument| no_argument(argument, UiEvent::Regenerate),
+    },
+    SlashCommand {
+        name: "model",
+        arguments: "<model>",
+        description: "Send the following messages to another model",
+        parse: |argument| required(argument, UiEvent::SetModel),
+    },
+    SlashCommand {
+        name: "backend",
+        arguments: "<openai|anthropic|ollama> [model]",
+        description: "Send the following messages to another backend",
+        parse: |argument| required(argument, UiEvent::SetBackend),
+    },
+    SlashCommand {
+        name: "models",
+        arguments: "",
+        description: "List the models the API key can use to pick one",
+        parse: |argument| no_argument(argument, UiEvent::ShowModels),
+    },
+    SlashCommand {
+        name: "profile",
+        arguments: "[name|main]",
+        description: "Switch to another account or server from the config, or list them",
+        parse: |argument| {
+            Some(match argument {
+                "" => UiEvent::ShowProfiles,
+                "main" => UiEvent::SetProfile(None),
+                name => UiEvent::SetProfile(Some(name.to_string())),
+            })
+        },
+    },
+    SlashCommand {
+        name: "set",
+        arguments: "<parameter> <value|default>",
+        description:
+            "Change temperature, top_p, presence_penalty, frequency_penalty or max_response_tokens",
+        parse: |argument| {
+            let (name, value) = argument.split_once(' ')?;
+            Some(UiEvent::SetSampling(
+                name.to_string(),
+                value.trim().to_string(),
+            ))
+        },
+    },
+    SlashCommand {
+        name: "sessions",
+        arguments: "",
+        description: "List the saved sessions to open, create or delete them",
+        parse: |argument| no_argument(argument, UiEvent::ShowSessions),
+    },
+    SlashCommand {
+        name: "agent",
+        arguments: "<goal>",
+        description:
+            "Have the model work towards a goal, running tools step by step (experimental)",
+        parse: |argument| required(argument, UiEvent::Agent),
+    },
+    SlashCommand {
+        name: "copy",
+        arguments: "[code]",
+        description: "Copy the response at the cursor, or its last code block, to the clipboard",
+        parse: |argument| match argument {
+            "" => Some(UiEvent::Copy(false)),
+            "code" => Some(UiEvent::Copy(true)),
+            _ => None,
+        },
+    },
+    SlashCommand {
+        name: "extract",
+        arguments: "[number] [path]",
+        description: "Save a code block of the response at the cursor, the last by default, to a file",
+        parse: |argument| {
+            let (first, rest) = argument.split_once(' ').unwrap_or((argument, ""));
+            let path = |path: &str| (!path.is_empty()).then(|| path.to_string());
+            Some(match first.parse::<usize>() {
+                Ok(number) => UiEvent::ExtractCode(Some(number), path(rest.trim())),
+                Err(_) => UiEvent::ExtractCode(None, path(argument)),
+            })
+        },
+    },
+    SlashCommand {
+        name: "file",
+        arguments: "<path>",
+        description: "Send the contents of a file along with the next message",
+        parse: |argument| required(argument, UiEvent::AttachFile),
+    },
+    SlashCommand {
+        name: "run",
+        arguments: "<command>",
+        description: "Run a command and send its output along with the next message",
+        parse: |argument| required(argument, UiEvent::RunCommand),
+    },
+    SlashCommand {
+        name: "export",
+        arguments: "md|html <path>",
+        description: "Save the conversation as a Markdown or HTML document to share",
+        parse: |argument| {
+            let (format, path) = argument.split_once(' ')?;
+            let path = path.trim();
+            let format = ExportFormat::parse(format)?;
+            (!path.is_empty()).then(|| UiEvent::Export(format, path.to_string()))
+        },
+    },
+    SlashCommand {
+        name: "checkpoint",
+        arguments: "<name>",
+        description: "Mark the current point in the conversation",
+        parse: |argument| required(argument, UiEvent::Checkpoint),
+    },
+    SlashCommand {
+        name: "rollback",
+        arguments: "<name>",
+        description: "Return to a checkpoint",
+        parse: |argument| required(argument, UiEvent::Rollback),
+    },
+    SlashCommand {
+        name: "fork",
+        arguments: "<name>",
+        description: "Save the conversation and continue from a checkpoint as a new session",
+        parse: |argument| required(argument, UiEvent::Fork),
+    },
+    SlashCommand {
+        name: "ab",
+        arguments: "<prompt> | temperature <a> <b> | off",
+        description: "Send each message with two variants to compare them",
+        parse: |argument| required(argument, UiEvent::AbMode),
+    },
+    SlashCommand {
+        name: "lowbw",
+        arguments: "",
+        description: "Turn low-bandwidth mode on or off, for flaky connections",
+        parse: |argument| no_argument(argument, UiEvent::ToggleLowBandwidth),
+    },
+    SlashCommand {
+        name: "system",
+        arguments: "<prompt>",
+        description: "Use another system prompt for this session",
+        parse: |argument| required(argument, UiEvent::SetSystemPrompt),
+    },
+    SlashCommand {
+        name: "title",
+        arguments: "<title>",
+        description: "Set the title of the session",
+        parse: |argument| required(argument, UiEvent::SetTitle),
+    },
+    SlashCommand {
+        name: "rate",
+        arguments: "up|down|clear",
+        description: "Rate the exchange at the cursor, e.g. to track which prompts worked well",
+        parse: |argument| match argument {
+            "up" | "+" => Some(UiEvent::Rate(Some(Rating::Up))),
+            "down" | "-" => Some(UiEvent::Rate(Some(Rating::Down))),
+            "clear" => Some(UiEvent::Rate(None)),
+            _ => None,
+        },
+    },
+    SlashCommand {
+        name: "note",
+        arguments: "[text]",
+        description: "Attach a private note to the exchange at the cursor, or remove it",
+        parse: |argument| {
+            Some(UiEvent::Note(
+                (!argument.is_empty()).then(|| argument.to_string()),
+            ))
+        },
+    },
+    SlashCommand {
+        name: "tag",
+        arguments: "<tag>",
+        description: "Tag the session",
+        parse: |argument| required(argument, UiEvent::AddTag),
+    },
+    SlashCommand {
+        name: "untag",
+        arguments: "<tag>",
+        description: "Remove a tag from the session",
+        parse: |argument| required(argument, UiEvent::RemoveTag),
+    },
+    SlashCommand {
+        name: "outline",
+        arguments: "[headings]",
+        description: "Show a line for each exchange to jump between them, or have the model write headings",
+        parse: |argument| match argument {
+            "" => Some(UiEvent::ShowOutline),
+            "headings" => Some(UiEvent::GenerateHeadings),
+            _ => None,
+        },
+    },
+    SlashCommand {
+        name: "queue",
+        arguments: "[send|clear]",
+        description: "List the messages held back while out of quota, try sending them again or drop them",
+        parse: |argument| match argument {
+            "" => Some(UiEvent::ShowQueue),
+            "send" => Some(UiEvent::SendQueue),
+            "clear" => Some(UiEvent::ClearQueue),
+            _ => None,
+        },
+    },
+    SlashCommand {
+        name: "remember",
+        arguments: "[fact]",
+        description: "Remember a fact about you for every session, or pick some out of the chat",
+        parse: |argument| {
+            Some(UiEvent::Remember(
+                (!argument.is_empty()).then(|| argument.to_string()),
+            ))
+        },
+    },
+    SlashCommand {
+        name: "memories",
+        arguments: "[forget <number>|clear]",
+        description: "List the facts remembered about you, forget one of them or all of them",
+        parse: |argument| match argument.split_once(' ') {
+            Some(("forget", number)) => number.trim().parse().ok().map(UiEvent::ForgetMemory),
+            _ => match argument {
+                "" => Some(UiEvent::ShowMemories),
+                "clear" => Some(UiEvent::ClearMemories),
+                _ => None,
+            },
+        },
+    },
+    SlashCommand {
+        name: "present",
+        arguments: "[stop]",
+        description: "Show the conversation as a web page on localhost, e.g. to share the screen",
+        parse: |argument| match argument {
+            "" => Some(UiEvent::Present),
+            "stop" => Some(UiEvent::StopPresenting),
+            _ => None,
+        },
+    },
+    SlashCommand {
+        name: "usage",
+        arguments: "",
+        description: "Show the tokens used and their estimated cost, in this session and all time",
+        parse: |argument| no_argument(argument, UiEvent::ShowUsage),
+    },
+    SlashCommand {
+        name: "full",
+        arguments: "",
+        description: "Show the whole of a response that was cut to max_response_chars",
+        parse: |argument| no_argument(argument, UiEvent::ShowFullResponse),
+    },
+    SlashCommand {
+        name: "stats",
+        arguments: "",
+        description: "Show the word and character counts of the exchange at the cursor",
+        parse: |argument| no_argument(argument, UiEvent::ShowStats),
+    },
+    SlashCommand {
+        name: "log",
+        arguments: "",
+        description: "List the recent status and error messages",
+        parse: |argument| no_argument(argument, UiEvent::ShowLog),
+    },
+    SlashCommand {
+        name: "quit",
+        arguments: "",
+        description: "Quit the app",
+        parse: |argument| no_argument(argument, UiEvent::Quit),
+    },
+];
+
+/// Parse a line starting with a slash into the event of the command it stands for. A line starting with two slashes
+/// is a message starting with one, such as a path.
+pub fn parse_command(line: &str) -> Result<UiEvent, String> {
+    let line = line.strip_prefix('/').unwrap_or(line);
+    if line.starts_with('/') {
+        return Ok(UiEvent::SendMessage(line.to_string()));
+    }
+    let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let Some(command) = COMMANDS.iter().find(|command| command.name == name) else {
+        return Err(format!(
+            "Unknown command /{}, type /help to list commands or // to send a message starting with /",
+            name
+        ));
+    };
+    (command.parse)(argument.trim()).ok_or_else(|| format!("Usage: {}", command.usage()))
+}
@@ -0,0 +1,128 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Most paths offered at once
+pub const MAX_CANDIDATES: usize = 8;
+
+// Characters around a path in text that are not part of it, e.g. in `src/main.rs`, or "(see src/lib.rs)"
+const PATH_DELIMITERS: &[char] = &[
+    '`', '\'', '"', '(', ')', '[', ']', '{', '}', '<', '>', ',', ';', ':',
+];
+
+// Commands whose argument is a path
+const PATH_COMMANDS: &[&str] = &["/file ", "/export md ", "/export html "];
+
+/// The word at the cursor if it is to be completed as a path, as a range of characters of the cursor's line. That
+/// is the argument of a command taking a path, or a word that looks like one, such as `src/ma` or `./`.
+pub fn path_word(lines: &[String], (row, col): (usize, usize)) -> Option<Range<usize>> {
+    let chars: Vec<char> = lines.get(row)?.chars().collect();
+    let col = col.min(chars.len());
+    let mut start = chars[..col]
+        .iter()
+        .rposition(|c| c.is_whitespace())
+        .map_or(0, |index| index + 1);
+    let end = chars[col..]
+        .iter()
+        .position(|c| c.is_whitespace())
+        .map_or(chars.len(), |index| col + index);
+    while start < end && PATH_DELIMITERS.contains(&chars[start]) {
+        start += 1;
+    }
+    let word: String = chars[start..end].iter().collect();
+    let argument = row == 0
+        && PATH_COMMANDS
+            .iter()
+            .any(|command| lines[0].starts_with(command) && start >= command.len());
+    // A command is not a path, though an absolute path at the start of a message is
+    let command = row == 0 && start == 0 && word.starts_with('/') && word.matches('/').count() == 1;
+    (argument || (word.contains('/') && !command && !word.contains("://"))).then_some(start..end)
+}
+
+/// Paths in text that exist, relative to the current directory, in the order they first appear
+pub fn mentioned_paths(text: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word
+            .trim_matches(PATH_DELIMITERS)
+            .trim_end_matches(['.', '!', '?']);
+        let looks_like_path = word.contains('/')
+            || word
+                .rsplit_once('.')
+                .is_some_and(|(name, extension)| !name.is_empty() && !extension.is_empty());
+        if looks_like_path
+            && !word.contains("://")
+            && !paths.iter().any(|path| path == word)
+            && Path::new(word).exists()
+        {
+            paths.push(word.to_string());
+        }
+    }
+    paths
+}
+
+// How well a query matches a candidate if all its characters appear in it in order, ignoring case. Lower is better:
+// candidates starting with the query come first, then those where the characters are closest together.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<(bool, usize, usize)> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    let mut gaps = 0;
+    let mut next = 0;
+    for q in query.iter() {
+        let found = candidate[next..].iter().position(|c| c == q)?;
+        // The first character may be anywhere without counting as a gap
+        if next > 0 {
+            gaps += found;
+        }
+        next += found + 1;
+    }
+    Some((!candidate.starts_with(&query), gaps, candidate.len()))
+}
+
+// The directory a typed path refers to, expanding a leading `~`
+fn expand_dir(dir: &str) -> PathBuf {
+    match dir.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(rest),
+        None if dir.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(dir),
+    }
+}
+
+/// Paths completing a partly typed one, best first. Paths mentioned in the conversation that match come first,
+/// followed by the entries of the directory typed so far that match the rest of the word, e.g. `src/apr` matches
+/// `src/app.rs`. Directories end with a slash, so that they can be completed further.
+pub fn complete_path(word: &str, mentioned: &[String]) -> Vec<String> {
+    let (dir, query) = match word.rfind('/') {
+        Some(index) => word.split_at(index + 1),
+        None => ("", word),
+    };
+    let mut from_conversation: Vec<_> = mentioned
+        .iter()
+        .filter_map(|path| Some((fuzzy_score(word, path)?, path.clone())))
+        .collect();
+    from_conversation.sort();
+    let mut entries: Vec<_> = std::fs::read_dir(expand_dir(dir))
+        .map(|entries| entries.filter_map(|entry| entry.ok()).collect())
+        .unwrap_or_else(|_| Vec::new())
+        .into_iter()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Hidden files are only offered once their dot is typed
+            if name.starts_with('.') && !query.starts_with('.') {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some((
+                fuzzy_score(query, &name)?,
+                format!("{}{}{}", dir, name, slash),
+            ))
+        })
+        .collect();
+    entries.sort();
+    let mut candidates: Vec<String> = Vec::new();
+    for (_, path) in from_conversation.into_iter().chain(entries) {
+        if path != word && !candidates.contains(&path) && candidates.len() < MAX_CANDIDATES {
+            candidates.push(path);
+        }
+    }
+    candidates
+}
@@ -0,0 +1,67 @@
+use crate::ChatTermConfig;
+
+/// Environment variable with the API key, which takes precedence over the config file and the keychain
+pub const API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+// Where the API key is kept in the keychain of the OS
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "chatgpt-term";
+#[cfg(feature = "keyring")]
+const KEYRING_USER: &str = "openai_api_key";
+
+/// The API key to use: from the environment, the keychain if `use_keyring` is set, or the config file. None if
+/// there is none yet.
+pub fn api_key(config: &ChatTermConfig) -> Result<Option<String>, String> {
+    if let Some(key) = std::env::var(API_KEY_ENV)
+        .ok()
+        .filter(|key| !key.trim().is_empty())
+    {
+        return Ok(Some(key.trim().to_string()));
+    }
+    if config.use_keyring {
+        return read_keyring();
+    }
+    Ok((!config.openai_api_key.is_empty()).then(|| config.openai_api_key.clone()))
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .map_err(|err| format!("Error opening the keychain: {}", err))
+}
+
+// The API key stored in the keychain, if any
+#[cfg(feature = "keyring")]
+fn read_keyring() -> Result<Option<String>, String> {
+    match keyring_entry()?.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) => Err(format!("Error reading the API key from the keychain: {}", err)),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_keyring() -> Result<Option<String>, String> {
+    Err(no_keyring())
+}
+
+/// Store the API key in the keychain
+#[cfg(feature = "keyring")]
+pub fn store_in_keyring(key: &str) -> Result<(), String> {
+    keyring_entry()?
+        .set_password(key)
+        .map_err(|err| format!("Error storing the API key in the keychain: {}", err))
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn store_in_keyring(_key: &str) -> Result<(), String> {
+    Err(no_keyring())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn no_keyring() -> String {
+    String::from(
+        "use_keyring is set, but this build has no keychain support. Build with `--features keyring`, or set \
+        the key in OPENAI_API_KEY.",
+    )
+}
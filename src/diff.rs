@@ -0,0 +1,173 @@
+use crate::markdown::code_blocks;
+
+// Unchanged lines shown around each change in a unified diff
+const CONTEXT_LINES: usize = 3;
+// Share of lines a code block has to have in common with a file to be taken for a new version of it
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// A line of a diff between two texts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line by line diff of two texts, based on their longest common subsequence of lines
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    diff.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+    diff
+}
+
+/// Changes from one version of a file to another in the unified format of `diff -u`, empty if there are none
+pub fn unified_diff(path: &str, old: &str, new: &str) -> Vec<String> {
+    let diff = diff_lines(old, new);
+    let changed: Vec<usize> = (0..diff.len())
+        .filter(|&index| !matches!(diff[index], DiffLine::Same(_)))
+        .collect();
+    let Some(&first) = changed.first() else {
+        return Vec::new();
+    };
+    // Changes at most twice the context apart go in the same hunk
+    let mut hunks: Vec<(usize, usize)> = vec![(first, first + 1)];
+    for &index in &changed[1..] {
+        match hunks.last_mut() {
+            Some((_, end)) if index - *end <= 2 * CONTEXT_LINES => *end = index + 1,
+            _ => hunks.push((index, index + 1)),
+        }
+    }
+
+    let mut lines = vec![format!("--- a/{}", path), format!("+++ b/{}", path)];
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES).min(diff.len());
+        // Line numbers where the hunk starts in each version, counting from 1
+        let old_start = 1 + diff[..start]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Added(_)))
+            .count();
+        let new_start = 1 + diff[..start]
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Removed(_)))
+            .count();
+        let hunk = &diff[start..end];
+        let old_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Added(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|line| !matches!(line, DiffLine::Removed(_)))
+            .count();
+        lines.push(format!(
+            "@@ -{},{} +{},{} @@",
+            old_start, old_count, new_start, new_count
+        ));
+        lines.extend(hunk.iter().map(|line| match line {
+            DiffLine::Same(line) => format!(" {}", line),
+            DiffLine::Removed(line) => format!("-{}", line),
+            DiffLine::Added(line) => format!("+{}", line),
+        }));
+    }
+    lines
+}
+
+// Share of the lines of the longer text that both texts have in common, in order
+fn similarity(old: &str, new: &str) -> f64 {
+    let diff = diff_lines(old, new);
+    let same = diff
+        .iter()
+        .filter(|line| matches!(line, DiffLine::Same(_)))
+        .count();
+    let longest = old.lines().count().max(new.lines().count());
+    if longest == 0 {
+        0.0
+    } else {
+        same as f64 / longest as f64
+    }
+}
+
+/// A new version of a file given by the model
+#[derive(Debug, Clone)]
+pub struct FileEdit {
+    pub path: String,
+    // Contents of the file when it was sent to the model, which it should still have for the edit to be applied
+    pub original: String,
+    pub text: String,
+}
+
+impl FileEdit {
+    /// The code block of a response that is most likely a new version of one of the given files, as their paths
+    /// and contents. Blocks that are the same as the file, or only share a small part of it, are not edits.
+    pub fn find(response: &str, files: &[(String, String)]) -> Option<Self> {
+        let mut best: Option<(f64, Self)> = None;
+        for block in code_blocks(response) {
+            for (path, original) in files {
+                if block.trim_end() == original.trim_end() {
+                    continue;
+                }
+                let score = similarity(original, &block);
+                if score >= MIN_SIMILARITY && best.as_ref().is_none_or(|(best, _)| score > *best) {
+                    best = Some((
+                        score,
+                        Self {
+                            path: path.clone(),
+                            original: original.clone(),
+                            text: block.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+        best.map(|(_, edit)| edit)
+    }
+
+    /// Write the new version to the file, unless the file was changed since it was sent, returning what was written
+    pub fn apply(&self) -> Result<String, String> {
+        let current =
+            std::fs::read_to_string(&self.path).map_err(|err| format!("{}: {}", self.path, err))?;
+        if current != self.original {
+            return Err(format!(
+                "{} was changed since it was attached, so the edit was not applied",
+                self.path
+            ));
+        }
+        let mut text = self.text.clone();
+        // Code blocks don't tell whether the file ends with a newline, so that is kept as it was
+        if self.original.ends_with('\n') && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        std::fs::write(&self.path, &text).map_err(|err| format!("{}: {}", self.path, err))?;
+        Ok(text)
+    }
+}
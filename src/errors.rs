@@ -0,0 +1,165 @@
+use std::fmt::Display;
+use std::time::Duration;
+
+use crate::api::ApiErrorKind;
+
+/// An error while talking to the API, which callers can match on to decide what to do
+#[derive(Debug)]
+pub enum ChatGptError {
+    // The server could not be reached, or the connection broke
+    Network(String),
+    // The API answered with an error
+    Api {
+        status: u16,
+        code: Option<String>,
+        message: String,
+        // How long the server asked to wait before trying again
+        retry_after: Option<Duration>,
+    },
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    // The response could not be read, e.g. from a server that is not OpenAI compatible
+    InvalidResponse(String),
+    Io(std::io::Error),
+    // Something in the config keeps the request from being made, such as a malformed API key
+    Config(String),
+    // The user cancelled the request before the answer arrived
+    Cancelled,
+}
+
+impl ChatGptError {
+    /// The kind of error, as reported in events and by exit codes
+    pub fn kind(&self) -> ApiErrorKind {
+        match self {
+            ChatGptError::Network(_) => ApiErrorKind::Network,
+            ChatGptError::Api { status, code, .. } => match code.as_deref() {
+                Some("content_filter") => ApiErrorKind::ContentFilter,
+                Some("insufficient_quota") => ApiErrorKind::Quota,
+                _ if matches!(status, 401 | 403) => ApiErrorKind::Auth,
+                _ => ApiErrorKind::Other,
+            },
+            ChatGptError::RateLimited { .. } => ApiErrorKind::RateLimit,
+            ChatGptError::InvalidResponse(_) => ApiErrorKind::Parse,
+            ChatGptError::Io(_) | ChatGptError::Config(_) | ChatGptError::Cancelled => {
+                ApiErrorKind::Other
+            }
+        }
+    }
+
+    /// Whether the same request may succeed when tried again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ChatGptError::Network(_) | ChatGptError::RateLimited { .. } => true,
+            ChatGptError::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// How long the server asked to wait before trying again
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ChatGptError::Api { retry_after, .. }
+            | ChatGptError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl Display for ChatGptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatGptError::Network(message)
+            | ChatGptError::Api { message, .. }
+            | ChatGptError::RateLimited { message, .. }
+            | ChatGptError::InvalidResponse(message)
+            | ChatGptError::Config(message) => write!(f, "{}", message),
+            ChatGptError::Io(err) => write!(f, "{}", err),
+            ChatGptError::Cancelled => write!(f, "The request was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ChatGptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChatGptError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ChatGptError {
+    fn from(err: std::io::Error) -> Self {
+        ChatGptError::Io(err)
+    }
+}
+
+impl From<reqwest::Error> for ChatGptError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_decode() {
+            ChatGptError::InvalidResponse(err.to_string())
+        } else {
+            ChatGptError::Network(err.to_string())
+        }
+    }
+}
+
+impl From<serde_json::Error> for ChatGptError {
+    fn from(err: serde_json::Error) -> Self {
+        ChatGptError::InvalidResponse(err.to_string())
+    }
+}
+
+/// A failed request described for the user: what kind of failure it was, what happened and what to do about it
+#[derive(Debug, Clone)]
+pub struct ErrorReport {
+    pub kind: ApiErrorKind,
+    pub message: String,
+}
+
+impl ErrorReport {
+    pub fn new(err: &ChatGptError) -> Self {
+        Self {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+
+    /// What went wrong, in a few words
+    pub fn title(&self) -> &'static str {
+        match self.kind {
+            ApiErrorKind::Auth => "API key rejected",
+            ApiErrorKind::RateLimit => "Rate limited",
+            ApiErrorKind::Quota => "Out of quota",
+            ApiErrorKind::Network => "Network error",
+            ApiErrorKind::Parse => "Unexpected response",
+            ApiErrorKind::ContentFilter => "Blocked by the content filter",
+            ApiErrorKind::Other => "API error",
+        }
+    }
+
+    /// What the user can do about it
+    pub fn action(&self) -> &'static str {
+        match self.kind {
+            ApiErrorKind::Auth => {
+                "Check OPENAI_API_KEY or openai_api_key in the config file, or run with --reconfigure to enter it again"
+            }
+            ApiErrorKind::RateLimit => {
+                "Wait a minute before sending the message again, or raise max_attempts in [retry]"
+            }
+            ApiErrorKind::Quota => {
+                "Check the plan and billing details of the account, or switch to another account with /profile"
+            }
+            ApiErrorKind::Network => {
+                "Check the internet connection, and api_base_url in the config file if it is set"
+            }
+            ApiErrorKind::Parse => {
+                "The server may not be OpenAI compatible, check api_base_url in the config file"
+            }
+            ApiErrorKind::ContentFilter => "Rephrase the message and send it again",
+            ApiErrorKind::Other => "Send the message again, or try another model with /model",
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::api::ApiErrorKind;
+
+/// Something that happened while talking to the API, reported to programs observing the app
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    RequestStarted {
+        model: &'a str,
+        streaming: bool,
+    },
+    // A piece of a streamed answer
+    Token {
+        text: &'a str,
+    },
+    Completed {
+        prompt_tokens: u32,
+        completion_tokens: u32,
+        finish_reason: Option<&'a str>,
+    },
+    Error {
+        kind: Option<ApiErrorKind>,
+        message: &'a str,
+    },
+    // Estimated costs in US dollars, if the price of the model is known
+    Cost {
+        request: Option<f64>,
+        session: Option<f64>,
+        today: f64,
+    },
+}
+
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    time: DateTime<Local>,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Writes events as JSON lines to a file or file descriptor, if one was given
+#[derive(Clone, Default)]
+pub struct EventLog {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl EventLog {
+    /// Append events to a file, creating it if needed
+    pub fn open_file(path: &str) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    /// Write events to a file descriptor inherited from the parent process
+    #[cfg(unix)]
+    pub fn open_fd(fd: u32) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(format!("/dev/fd/{}", fd))?;
+        Ok(Self {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    /// File descriptors are only inherited this way on Unix, so other platforms have to use a file
+    #[cfg(not(unix))]
+    pub fn open_fd(_fd: u32) -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "--event-fd is only supported on Unix, use --event-file instead",
+        ))
+    }
+
+    /// Write an event. Failing to write is not an error for the app, so it is ignored.
+    pub fn emit(&self, event: Event) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let record = EventRecord {
+            time: Local::now(),
+            event,
+        };
+        if let Ok(json) = serde_json::to_string(&record) {
+            let mut file = file.lock().unwrap();
+            writeln!(file, "{}", json).ok();
+            file.flush().ok();
+        }
+    }
+}
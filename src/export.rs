@@ -0,0 +1,90 @@
+use chrono::Local;
+use pulldown_cmark::{escape::escape_html, html::push_html, Event, Options, Parser};
+
+use crate::api::ChatLogEntry;
+use crate::locale::Locale;
+use crate::search::cited_results;
+use crate::stats::TextStats;
+
+// Keeps exported pages readable without any other files
+const HTML_STYLE: &str =
+    "body { max-width: 50em; margin: 2em auto; padding: 0 1em; font-family: sans-serif; \
+    line-height: 1.5; }
+h2 { border-bottom: 1px solid #ddd; }
+pre { background: #f5f5f5; padding: 0.75em; overflow-x: auto; }
+code { font-family: monospace; }";
+
+/// Document formats a session can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    /// The format with the given name or file extension, e.g. `md` or `html`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "html" | "htm" => Some(ExportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// The chat log as a markdown document, with a heading for each message. Messages are markdown already, so code
+/// blocks are kept as they are.
+pub fn to_markdown(title: &str, model: &str, entries: &[ChatLogEntry], locale: &Locale) -> String {
+    let mut document = format!(
+        "# {}\n\n_Exported on {} from a conversation with {}_\n",
+        title,
+        locale.date_time(&Local::now()),
+        model
+    );
+    for entry in entries {
+        document.push_str("\n## You\n\n");
+        if let Some(timestamp) = &entry.timestamp {
+            document.push_str(&format!("_{}_\n\n", locale.date_time(timestamp)));
+        }
+        document.push_str(&format!("{}\n", entry.message.trim_end()));
+        for call in &entry.tool_calls {
+            document.push_str(&format!("\n_Ran the tool `{}`_\n", call.name));
+        }
+        document.push_str(&format!("\n## Bot\n\n{}\n", entry.response.trim_end()));
+        for (number, result) in cited_results(&entry.response, &entry.tool_calls) {
+            document.push_str(&format!(
+                "\n[{}] [{}]({})\n",
+                number, result.title, result.url
+            ));
+        }
+        document.push_str(&format!(
+            "\n_{}_\n",
+            TextStats::of(&entry.response).describe(locale)
+        ));
+        if let Some(annotation) = entry.annotation() {
+            document.push_str(&format!("\n_Note: {}_\n", annotation));
+        }
+    }
+    document
+}
+
+/// The chat log as a web page that can be opened without anything else. HTML written in the messages and responses
+/// is shown as text rather than run, since the page may be opened in a browser.
+pub fn to_html(title: &str, model: &str, entries: &[ChatLogEntry], locale: &Locale) -> String {
+    let markdown = to_markdown(title, model, entries, locale);
+    let mut body = String::new();
+    push_html(
+        &mut body,
+        Parser::new_ext(&markdown, Options::ENABLE_TABLES).map(|event| match event {
+            Event::Html(html) => Event::Text(html),
+            event => event,
+        }),
+    );
+    let mut escaped_title = String::new();
+    escape_html(&mut escaped_title, title).ok();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n\
+        </head>\n<body>\n{}</body>\n</html>\n",
+        escaped_title, HTML_STYLE, body
+    )
+}
@@ -0,0 +1,115 @@
+// Text with fewer lines than this is not worth wrapping in a fence
+const MIN_CODE_LINES: usize = 3;
+
+// Words and symbols typical of each language, checked in order so that the first language wins ties
+const LANGUAGE_MARKERS: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "fn ", "let mut ", "impl ", "pub ", "use std", "&self", "-> ", "println!", "::",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "def ", "import ", "self.", "elif ", "print(", "None", "__init__", "):\n",
+        ],
+    ),
+    ("go", &["func ", "package ", ":= ", "fmt.", "err != nil"]),
+    (
+        "typescript",
+        &["interface ", ": string", ": number", "export ", "=> "],
+    ),
+    (
+        "javascript",
+        &[
+            "function ",
+            "const ",
+            "=> ",
+            "console.log",
+            "require(",
+            "document.",
+        ],
+    ),
+    (
+        "java",
+        &[
+            "public class",
+            "System.out",
+            "private ",
+            "public static void",
+            "import java",
+        ],
+    ),
+    (
+        "cpp",
+        &["#include", "std::", "cout", "template<", "nullptr"],
+    ),
+    ("c", &["#include", "printf(", "int main", "malloc(", "NULL"]),
+    (
+        "bash",
+        &["#!/bin/", "echo ", "fi\n", "$(", "done\n", "then\n"],
+    ),
+    (
+        "sql",
+        &[
+            "SELECT ",
+            "FROM ",
+            "WHERE ",
+            "INSERT INTO",
+            "CREATE TABLE",
+            "JOIN ",
+        ],
+    ),
+    ("html", &["<div", "</", "<html", "<p>", "class=\""]),
+];
+
+/// Whether a message looks like pasted code that is not fenced yet, judging by how many lines end like
+/// statements or are indented, and how dense brackets and operators are
+pub fn looks_like_code(text: &str) -> bool {
+    if text.contains("```") {
+        return false;
+    }
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    if lines.len() < MIN_CODE_LINES {
+        return false;
+    }
+    let code_lines = lines
+        .iter()
+        .filter(|line| {
+            line.starts_with([' ', '\t'])
+                || line.trim_end().ends_with(['{', '}', ';', ')', ':', ','])
+        })
+        .count();
+    let symbols = text.chars().filter(|c| "{}[]();=<>".contains(*c)).count();
+    let non_whitespace = text.chars().filter(|c| !c.is_whitespace()).count();
+    code_lines * 10 >= lines.len() * 6 && symbols * 25 >= non_whitespace
+}
+
+/// Best guess at the language of some code, for the tag of a fenced block
+pub fn detect_language(code: &str) -> Option<&'static str> {
+    if serde_json::from_str::<serde_json::Value>(code).is_ok() {
+        return Some("json");
+    }
+    let mut best = None;
+    let mut best_score = 1;
+    for (language, markers) in LANGUAGE_MARKERS {
+        let score = markers
+            .iter()
+            .filter(|marker| code.contains(*marker))
+            .count();
+        if score > best_score {
+            best = Some(*language);
+            best_score = score;
+        }
+    }
+    best
+}
+
+/// Wrap code in a fenced block, tagged with its language if known
+pub fn fence_code(code: &str, language: Option<&str>) -> String {
+    format!("```{}\n{}\n```", language.unwrap_or(""), code.trim_end())
+}
@@ -0,0 +1,157 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::tools::Tool;
+
+/// Name of the built-in tool the model calls to read a file
+pub const READ_FILE_TOOL: &str = "read_file";
+/// Name of the built-in tool the model calls to list a directory
+pub const LIST_DIR_TOOL: &str = "list_dir";
+
+// Directories with more entries than this are listed in part
+const MAX_DIR_ENTRIES: usize = 500;
+
+fn default_max_file_bytes() -> usize {
+    64 * 1024
+}
+
+/// Settings of the built-in tools that read files, which are only offered to the model when they are in the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilesConfig {
+    // Directories the model can read from, along with everything below them. Relative paths given by the model are
+    // taken from the first one.
+    pub directories: Vec<PathBuf>,
+    // Files are cut off after this many bytes
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: usize,
+    // Read without asking first
+    #[serde(default)]
+    pub auto_approve: bool,
+}
+
+impl FilesConfig {
+    /// The tools offered to the model
+    pub fn tools(&self) -> Vec<Tool> {
+        let roots: Vec<String> = self
+            .directories
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect();
+        let parameters = |description: &str| {
+            json!({
+                "type": "object",
+                "properties": {"path": {"type": "string", "description": description}},
+                "required": ["path"]
+            })
+        };
+        vec![
+            Tool {
+                name: LIST_DIR_TOOL.to_string(),
+                description: format!(
+                    "List the files and directories in a directory. Only these directories and what is below them \
+                    can be read: {}",
+                    roots.join(", ")
+                ),
+                command: String::new(),
+                auto_approve: self.auto_approve,
+                parameters: parameters("Path of the directory, relative paths are taken from the first directory"),
+            },
+            Tool {
+                name: READ_FILE_TOOL.to_string(),
+                description: format!(
+                    "Read a text file. Only files in these directories and below them can be read: {}",
+                    roots.join(", ")
+                ),
+                command: String::new(),
+                auto_approve: self.auto_approve,
+                parameters: parameters("Path of the file, relative paths are taken from the first directory"),
+            },
+        ]
+    }
+
+    // The path the model asked for, if it is within one of the directories once links and `..` are resolved
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let first = self
+            .directories
+            .first()
+            .ok_or("no directories are configured")?;
+        let path = first.join(path);
+        let allowed = |path: &Path| {
+            self.directories
+                .iter()
+                .filter_map(|dir| dir.canonicalize().ok())
+                .any(|dir| path.starts_with(dir))
+        };
+        let outside = |path: &Path| {
+            format!(
+                "{} is outside of the directories that can be read",
+                path.display()
+            )
+        };
+        match path.canonicalize() {
+            Ok(resolved) if allowed(&resolved) => Ok(resolved),
+            Ok(resolved) => Err(outside(&resolved)),
+            // Whether a missing path would be outside is judged by the closest existing directory above it, so that
+            // errors don't tell which files exist elsewhere
+            Err(err) => match path.ancestors().find_map(|dir| dir.canonicalize().ok()) {
+                Some(dir) if allowed(&dir) => Err(format!("{}: {}", path.display(), err)),
+                _ => Err(outside(&path)),
+            },
+        }
+    }
+
+    /// List a directory, directories first and marked with a slash
+    pub fn list_dir(&self, path: &str) -> Result<String, String> {
+        let path = self.resolve(path)?;
+        let mut entries: Vec<(bool, String)> = std::fs::read_dir(&path)
+            .map_err(|err| format!("{}: {}", path.display(), err))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                // Links to directories count as directories
+                let is_dir = entry.path().is_dir();
+                (!is_dir, entry.file_name().to_string_lossy().to_string())
+            })
+            .collect();
+        entries.sort();
+        let total = entries.len();
+        let mut lines: Vec<String> = entries
+            .into_iter()
+            .take(MAX_DIR_ENTRIES)
+            .map(|(is_file, name)| if is_file { name } else { format!("{}/", name) })
+            .collect();
+        if total > MAX_DIR_ENTRIES {
+            lines.push(format!("({} more entries)", total - MAX_DIR_ENTRIES));
+        }
+        if lines.is_empty() {
+            return Ok(format!("{} is empty", path.display()));
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Read a text file, up to the size limit
+    pub fn read_file(&self, path: &str) -> Result<String, String> {
+        let path = self.resolve(path)?;
+        let error = |err: std::io::Error| format!("{}: {}", path.display(), err);
+        let file = std::fs::File::open(&path).map_err(error)?;
+        let size = file.metadata().map_err(error)?.len();
+        let mut contents = Vec::new();
+        file.take(self.max_file_bytes as u64)
+            .read_to_end(&mut contents)
+            .map_err(error)?;
+        if contents.contains(&0) {
+            return Err(format!("{} is not a text file", path.display()));
+        }
+        let mut text = String::from_utf8_lossy(&contents).into_owned();
+        if size > contents.len() as u64 {
+            text.push_str(&format!(
+                "\n(file truncated to {} of {} bytes)",
+                contents.len(),
+                size
+            ));
+        }
+        Ok(text)
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+/// Filters shipped with the application
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinFilter {
+    // Remove markdown formatting such as headings, emphasis, inline code and code fences
+    StripMarkdown,
+    // Replace typographic quotes with ASCII ones
+    AsciiQuotes,
+    // Remove boilerplate such as "As an AI language model, ..."
+    TrimBoilerplate,
+}
+
+/// A post-processing step applied to responses before they are shown and saved
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ResponseFilter {
+    Builtin {
+        builtin: BuiltinFilter,
+    },
+    // Replace all matches of a regular expression. The replacement can refer to groups as `$1` or `${name}`.
+    Regex {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+    },
+    // Command that gets the response on stdin and prints the filtered response
+    Command {
+        command: String,
+    },
+}
+
+fn strip_markdown(text: &str) -> String {
+    let fences = Regex::new(r"(?m)^\s*```.*\n?").unwrap();
+    let headings = Regex::new(r"(?m)^#{1,6}\s+").unwrap();
+    let emphasis = Regex::new(r"\*\*(.+?)\*\*|__(.+?)__").unwrap();
+    let inline_code = Regex::new(r"`([^`\n]+)`").unwrap();
+    let links = Regex::new(r"\[([^\]\n]+)\]\(([^)\s]+)\)").unwrap();
+    let text = fences.replace_all(text, "");
+    let text = headings.replace_all(&text, "");
+    let text = emphasis.replace_all(&text, "$1$2");
+    let text = inline_code.replace_all(&text, "$1");
+    links.replace_all(&text, "$1 ($2)").into_owned()
+}
+
+fn ascii_quotes(text: &str) -> String {
+    text.replace(['\u{2018}', '\u{2019}'], "'")
+        .replace(['\u{201c}', '\u{201d}'], "\"")
+}
+
+fn trim_boilerplate(text: &str) -> String {
+    // The clause is dropped and the rest of the sentence capitalized
+    let disclaimer =
+        Regex::new(r"(?i)\bas an ai(?: language model)?(?: [^,.\n]*)?,\s*(\w)").unwrap();
+    disclaimer
+        .replace_all(text, |caps: &Captures| caps[1].to_uppercase())
+        .into_owned()
+}
+
+// Pipe text through a command, split on whitespace like the text-to-speech command
+fn run_command(command: &str, text: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut args = command.split_whitespace();
+    let program = args.next().ok_or("Empty response filter command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Write from another thread so that a command printing a lot before reading all its input can't block
+    let mut stdin = child.stdin.take().unwrap();
+    let input = text.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    // A command may exit without reading all of its input, which is not an error
+    writer.join().ok();
+    if !output.status.success() {
+        return Err(format!(
+            "Response filter `{}` failed with {}",
+            command, output.status
+        )
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?.trim_end().to_string())
+}
+
+impl ResponseFilter {
+    /// Apply the filter to a response
+    pub fn apply(&self, text: &str) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(match self {
+            ResponseFilter::Builtin { builtin } => match builtin {
+                BuiltinFilter::StripMarkdown => strip_markdown(text),
+                BuiltinFilter::AsciiQuotes => ascii_quotes(text),
+                BuiltinFilter::TrimBoilerplate => trim_boilerplate(text),
+            },
+            ResponseFilter::Regex {
+                pattern,
+                replacement,
+            } => Regex::new(pattern)?
+                .replace_all(text, replacement.as_str())
+                .into_owned(),
+            ResponseFilter::Command { command } => run_command(command, text)?,
+        })
+    }
+}
+
+/// Apply filters to a response in order
+pub fn apply_filters(
+    filters: &[ResponseFilter],
+    text: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    filters
+        .iter()
+        .try_fold(text.to_string(), |text, filter| filter.apply(&text))
+}
@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// A prompt sent in any session, counted each time it is sent again
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PromptRecord {
+    pub text: String,
+    pub count: u32,
+    pub last_used: DateTime<Local>,
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+/// Prompts sent across all sessions, persisted next to the config file
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PromptHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    prompts: Vec<PromptRecord>,
+}
+
+impl PromptHistory {
+    /// Default location of the prompt history, in the same directory as the config file
+    pub fn default_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let config_path = confy::get_configuration_file_path("chatgpt-term", None)?;
+        Ok(config_path.with_file_name("prompt_history.json"))
+    }
+
+    /// Load the prompt history, starting an empty one if it doesn't exist yet
+    pub fn load(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut history: PromptHistory = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => PromptHistory::default(),
+            Err(err) => return Err(err.into()),
+        };
+        history.path = path;
+        Ok(history)
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self)?)
+    }
+
+    /// Add a sent prompt, or count it again if it was sent before, and save the history
+    pub fn record(&mut self, text: &str) -> std::io::Result<()> {
+        let text = text.trim();
+        match self.prompts.iter_mut().find(|prompt| prompt.text == text) {
+            Some(prompt) => {
+                prompt.count += 1;
+                prompt.last_used = Local::now();
+            }
+            None => self.prompts.push(PromptRecord {
+                text: text.to_string(),
+                count: 1,
+                last_used: Local::now(),
+                favorite: false,
+            }),
+        }
+        self.save()
+    }
+
+    /// Mark or unmark a prompt as a favorite and save the history
+    pub fn toggle_favorite(&mut self, text: &str) -> std::io::Result<()> {
+        if let Some(prompt) = self.prompts.iter_mut().find(|prompt| prompt.text == text) {
+            prompt.favorite = !prompt.favorite;
+        }
+        self.save()
+    }
+
+    /// Prompts with favorites first, then the most frequently and recently used ones
+    pub fn ranked(&self) -> Vec<&PromptRecord> {
+        let mut prompts: Vec<&PromptRecord> = self.prompts.iter().collect();
+        prompts.sort_by(|a, b| {
+            b.favorite
+                .cmp(&a.favorite)
+                .then(b.count.cmp(&a.count))
+                .then(b.last_used.cmp(&a.last_used))
+        });
+        prompts
+    }
+}
@@ -1,11 +1,275 @@
+//! A ChatGPT client with sessions that are saved to files, and the terminal UI of `chatgpt-term` built on it.
+//!
+//! The library can be used on its own to send messages from another program, with default features turned off
+//! to leave out the terminal UI and its dependencies:
+//!
+//! ```toml
+//! chatgpt-term = { git = "https://github.com/thomasantony/chatgpt-term", default-features = false }
+//! ```
+//!
+//! - [`ChatTermConfig`] holds the API key, model, prompts and everything else of the config file.
+//! - [`api::ChatGPTClient`] makes requests to the API. Requests go over HTTP unless another
+//!   [`transport::Transport`] is given, e.g. to answer them in tests.
+//! - [`api::ChatGPTSession`] is a conversation: it keeps the chat log, decides what fits in the context and sends
+//!   messages.
+//! - [`api::SessionFile`] is a conversation saved to a file, which a session can be restored from.
+//!
+//! ```no_run
+//! use chatgpt_term::api::{ChatGPTClient, SessionFile};
+//! use chatgpt_term::ChatTermConfig;
+//!
+//! let config = ChatTermConfig {
+//!     openai_api_key: std::env::var("OPENAI_API_KEY")?,
+//!     ..Default::default()
+//! };
+//! let mut session = ChatGPTClient::new(config).new_session(Vec::new(), 2000);
+//! let entry = session.send_message("What is the capital of France?")?;
+//! println!("{}", entry.response);
+//!
+//! // Print the answer to the next message as it is generated, then save the conversation
+//! let mut print = |token: &str| print!("{}", token);
+//! session.send_message_streaming("And of Germany?", Some(&mut print))?;
+//! session.set_path("geography.json");
+//! session.save_chatlog()?;
+//!
+//! // Carry on later
+//! let mut session = ChatGPTClient::new(ChatTermConfig::default()).new_session(Vec::new(), 2000);
+//! session.restore(SessionFile::load("geography.json")?)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::agent::AgentConfig;
+use crate::anthropic::AnthropicConfig;
+use crate::autosave::AutosaveConfig;
+use crate::backend::BackendKind;
+use crate::calculator::CalculatorConfig;
+use crate::files::FilesConfig;
+use crate::filters::ResponseFilter;
+use crate::network::{LowBandwidthConfig, NetworkConfig};
+use crate::ollama::OllamaConfig;
+use crate::pricing::ModelPrice;
+use crate::retry::RetryConfig;
+use crate::sampling::SamplingParams;
+use crate::sandbox::SandboxPolicy;
+use crate::search::WebSearchConfig;
+use crate::tools::Tool;
+
+// A named initial prompt that can be used to start new conversations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Persona {
+    pub name: String,
+    pub prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    // Model used instead of `openai_model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // Token limit of the context sent with each message
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    // Example exchanges sent ahead of the conversation to show the kind of answers expected
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Example>,
+    // Start new conversations with a summary of the last saved session with this persona, to carry on from it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub continue_previous: bool,
+}
+
+// Another account or server to send messages to, switched to with /profile
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    // Address of the API, OpenAI's if not set, as for `api_base_url`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_base_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub azure_api_version: Option<String>,
+    // Environment variable holding the API key, which keeps it out of the config file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    // The API key itself, if not given in an environment variable. The main key is used if neither is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai_api_key: Option<String>,
+    // Model used instead of `openai_model`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub openai_model: Option<String>,
+    // Used instead of `extra_headers` and `query_params`, as for `api_base_url`
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub extra_headers: HashMap<String, String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub query_params: HashMap<String, String>,
+}
+
+// Key that sends the message in the input box. With any key other than Enter, Enter inserts a new line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SendKey {
+    #[default]
+    #[serde(rename = "enter")]
+    Enter,
+    #[serde(rename = "ctrl+enter")]
+    CtrlEnter,
+    #[serde(rename = "alt+enter")]
+    AltEnter,
+}
+
+// Colors of the messages in the chat log by who wrote them: a name such as "cyan" or "light_green", a number of
+// the 256-color palette, or "#rrggbb"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatColors {
+    pub user: String,
+    pub assistant: String,
+}
+
+impl Default for ChatColors {
+    fn default() -> Self {
+        Self {
+            user: String::from("cyan"),
+            assistant: String::from("white"),
+        }
+    }
+}
+
+// A few-shot example exchange
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Example {
+    pub user: String,
+    pub assistant: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatTermConfig {
     pub openai_api_key: String,
     pub openai_model: String,
     pub initial_prompt: String,
     pub max_tokens: u32,
+    // Text-to-speech command used to read responses aloud, e.g. "espeak" or "say"
+    pub tts_command: Option<String>,
+    // Today's and this week's spend are shown in the status bar once today's spend reaches this amount (in US
+    // dollars), never if not set
+    pub daily_cost_threshold: Option<f64>,
+    // Ask for confirmation before sending requests with a higher estimated cost (in US dollars)
+    pub confirm_cost_threshold: Option<f64>,
+    #[serde(default)]
+    pub send_key: SendKey,
+    // Warn about likely mistakes in a message, such as unclosed code fences, before sending it
+    #[serde(default)]
+    pub lint_prompts: bool,
+    // Offer to wrap messages that look like pasted code in a fenced block tagged with the language
+    #[serde(default)]
+    pub offer_code_fences: bool,
+    // Collapse redundant whitespace and blank lines in large messages before sending them
+    #[serde(default)]
+    pub normalize_whitespace: bool,
+    // Condense exchanges that no longer fit in the context into a summary without asking, rather than asking
+    // whether to pin, summarize or drop them
+    #[serde(default)]
+    pub auto_summarize: bool,
+    // Cheaper model that writes the summaries, the model of the session if not set
+    pub summary_model: Option<String>,
+    // Tell the model the current date, time and time zone in a system message sent with each request
+    #[serde(default)]
+    pub send_current_time: bool,
+    // Remember facts about the user that they approved, in memories.json, and tell every session about them
+    #[serde(default)]
+    pub memory: bool,
+    // Most tools the model can run to answer one message, 5 if not set
+    pub max_tool_iterations: Option<u32>,
+    // Address of an OpenAI compatible API to use instead of OpenAI's, such as a proxy, a local server or an Azure
+    // OpenAI resource, e.g. "http://localhost:8080/v1" or "https://my-resource.openai.azure.com"
+    pub api_base_url: Option<String>,
+    // API version of an Azure OpenAI resource, 2023-05-15 if not set
+    pub azure_api_version: Option<String>,
+    // Where messages are sent: "openai" for the API at api_base_url, "anthropic" or "ollama" as set in their tables
+    #[serde(default)]
+    pub backend: BackendKind,
+    // Save the session when quitting, titled after the first message if it has no title
+    #[serde(default)]
+    pub save_on_quit: bool,
+    // Directory sessions are saved to and listed from, the data directory of the app if not set
+    pub sessions_dir: Option<String>,
+    // Keep the API key in the keychain of the OS instead of this file, which needs the keyring feature
+    #[serde(default)]
+    pub use_keyring: bool,
+    // Name given to {{user_name}} in prompts, the login name if not set
+    pub user_name: Option<String>,
+    // Locale numbers, dates and times are written for, e.g. "de_DE", taken from LANG and LC_ALL if not set
+    pub locale: Option<String>,
+    // Longer responses are cut to this many characters in the chat log and the session file, so that a runaway
+    // answer can't slow everything down. /full shows the whole of it until the app is closed.
+    pub max_response_chars: Option<usize>,
+    // Longest time quitting may take, in seconds, before the app restores the terminal and exits anyway, 5 if not
+    // set
+    pub shutdown_timeout_secs: Option<u64>,
+    // The fields below are written as TOML tables, which have to come after all plain values
+
+    // Colors of your messages and of the responses in the chat log
+    #[serde(default)]
+    pub chat_colors: ChatColors,
+    // Personas offered as shortcuts on the start screen
+    #[serde(default)]
+    pub personas: Vec<Persona>,
+    // Other accounts or servers to switch between, shown along with the model in the status bar
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    // Values of {{name}} variables in the initial prompt and the prompts of personas
+    #[serde(default)]
+    pub prompt_variables: HashMap<String, String>,
+    // Headers and query parameters added to every request to the API, e.g. for a gateway in front of it. Values
+    // can take environment variables as {{env:NAME}}.
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub query_params: HashMap<String, String>,
+    // Prices per 1000 tokens, overriding or adding to the built-in price table
+    #[serde(default)]
+    pub model_prices: HashMap<String, ModelPrice>,
+    // Post-processing applied to responses before they are shown and saved, in order
+    #[serde(default)]
+    pub response_filters: Vec<ResponseFilter>,
+    // Commands the model can ask to run while answering
+    #[serde(default)]
+    pub tools: Vec<Tool>,
+    // Restrictions applied to the commands run as tools
+    #[serde(default)]
+    pub tool_sandbox: SandboxPolicy,
+    // Limits and checkpoints of agent mode
+    #[serde(default)]
+    pub agent: AgentConfig,
+    // Retrying requests that fail with rate limits and server or network errors
+    #[serde(default)]
+    pub retry: RetryConfig,
+    // Proxy and certificates of the connections to the API and the web search provider
+    #[serde(default)]
+    pub network: NetworkConfig,
+    // Smaller answers and more retries for flaky connections, toggled with /lowbw
+    #[serde(default)]
+    pub low_bandwidth: LowBandwidthConfig,
+    // API key and model of Anthropic, for the anthropic backend
+    #[serde(default)]
+    pub anthropic: AnthropicConfig,
+    // Address and model of an Ollama server, for the ollama backend
+    #[serde(default)]
+    pub ollama: OllamaConfig,
+    // Saving the session as it goes, to recover it after a crash
+    #[serde(default)]
+    pub autosave: AutosaveConfig,
+    // Temperature and the other parameters of how answers are generated, left to the API if not set
+    #[serde(default)]
+    pub sampling: SamplingParams,
+    // Search engine of the built-in web search tool, which is offered to the model when set
+    pub web_search: Option<WebSearchConfig>,
+    // The built-in calculator tool is offered to the model when set
+    pub calculator: Option<CalculatorConfig>,
+    // Directories the built-in tools for listing and reading files can look in, offered to the model when set
+    pub files: Option<FilesConfig>,
 }
 // Implement default trait for Config with "gpt-3.5-turbo" as the default model
 impl Default for ChatTermConfig {
@@ -17,8 +281,137 @@ impl Default for ChatTermConfig {
                 "You are Assistant, a very enthusiastic chatbot. You are chatting with a user.",
             ),
             max_tokens: 2000,
+            tts_command: None,
+            daily_cost_threshold: None,
+            confirm_cost_threshold: None,
+            send_key: SendKey::Enter,
+            lint_prompts: false,
+            offer_code_fences: false,
+            normalize_whitespace: false,
+            auto_summarize: false,
+            summary_model: None,
+            send_current_time: false,
+            memory: false,
+            max_tool_iterations: None,
+            api_base_url: None,
+            azure_api_version: None,
+            backend: BackendKind::OpenAi,
+            save_on_quit: false,
+            sessions_dir: None,
+            use_keyring: false,
+            user_name: None,
+            locale: None,
+            max_response_chars: None,
+            shutdown_timeout_secs: None,
+            chat_colors: ChatColors::default(),
+            personas: Vec::new(),
+            profiles: Vec::new(),
+            prompt_variables: HashMap::new(),
+            extra_headers: HashMap::new(),
+            query_params: HashMap::new(),
+            model_prices: HashMap::new(),
+            response_filters: Vec::new(),
+            tools: Vec::new(),
+            tool_sandbox: SandboxPolicy::default(),
+            agent: AgentConfig::default(),
+            retry: RetryConfig::default(),
+            network: NetworkConfig::default(),
+            low_bandwidth: LowBandwidthConfig::default(),
+            anthropic: AnthropicConfig::default(),
+            ollama: OllamaConfig::default(),
+            autosave: AutosaveConfig::default(),
+            sampling: SamplingParams::default(),
+            web_search: None,
+            calculator: None,
+            files: None,
+        }
+    }
+}
+impl ChatTermConfig {
+    /// Directory sessions are saved to and listed from: `sessions_dir`, or else the data directory of the app, such
+    /// as ~/.local/share/chatgpt-term/sessions, or the current directory if the OS has none
+    pub fn sessions_dir(&self) -> PathBuf {
+        match &self.sessions_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => ProjectDirs::from("rs", "", "chatgpt-term")
+                .map(|dirs| dirs.data_dir().join("sessions"))
+                .unwrap_or_else(|| PathBuf::from(".")),
         }
     }
+
+    /// The config with the address, API key, model, headers and query parameters of a profile in place of the main
+    /// ones
+    pub fn with_profile(&self, name: &str) -> Result<ChatTermConfig, String> {
+        let profile = self
+            .profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .ok_or_else(|| format!("There is no profile named {} in the config", name))?;
+        let mut config = self.clone();
+        config.api_base_url = profile.api_base_url.clone();
+        config.azure_api_version = profile.azure_api_version.clone();
+        config.extra_headers = profile.extra_headers.clone();
+        config.query_params = profile.query_params.clone();
+        if let Some(variable) = &profile.api_key_env {
+            config.openai_api_key = std::env::var(variable)
+                .ok()
+                .filter(|key| !key.trim().is_empty())
+                .ok_or_else(|| format!("Set {} to the API key of the {} profile", variable, name))?;
+        } else if let Some(key) = &profile.openai_api_key {
+            config.openai_api_key = key.clone();
+        }
+        if let Some(model) = &profile.openai_model {
+            config.openai_model = model.clone();
+        }
+        Ok(config)
+    }
 }
+
+pub mod agent;
+pub mod analyze;
+pub mod anthropic;
 pub mod api;
+#[cfg(feature = "tui")]
 pub mod app;
+pub mod attachments;
+pub mod autosave;
+pub mod backend;
+pub mod bundle;
+pub mod calculator;
+#[cfg(feature = "tui")]
+pub mod clipboard;
+#[cfg(feature = "tui")]
+pub mod commands;
+pub mod completion;
+pub mod credentials;
+pub mod diff;
+pub mod errors;
+pub mod events;
+pub mod export;
+pub mod fence;
+pub mod files;
+pub mod filters;
+pub mod history;
+pub mod lint;
+pub mod locale;
+pub mod markdown;
+pub mod memory;
+pub mod network;
+pub mod ollama;
+pub mod present;
+pub mod pricing;
+pub mod replay;
+pub mod retry;
+pub mod sampling;
+pub mod sandbox;
+pub mod search;
+pub mod sessions;
+pub mod snippets;
+pub mod stats;
+pub mod tokens;
+pub mod tools;
+pub mod transport;
+pub mod tts;
+pub mod usage;
+pub mod variables;
+pub mod whitespace;
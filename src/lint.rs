@@ -0,0 +1,32 @@
+// Lines longer than this are likely a paste that lost its line breaks
+const MAX_LINE_CHARS: usize = 2000;
+
+/// Problems with a prompt that are likely mistakes, e.g. from pasting text into the terminal
+pub fn lint_prompt(text: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if let Some(line) = text
+        .lines()
+        .find(|line| line.chars().count() > MAX_LINE_CHARS)
+    {
+        warnings.push(format!(
+            "a single line is {} characters long",
+            line.chars().count()
+        ));
+    }
+    let num_fences = text
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count();
+    if num_fences % 2 == 1 {
+        warnings.push(String::from("a code fence (```) is not closed"));
+    }
+    // Tabs and line breaks are fine, other control characters usually come from terminal escape sequences
+    let num_control = text
+        .chars()
+        .filter(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'))
+        .count();
+    if num_control > 0 {
+        warnings.push(format!("it contains {} control character(s)", num_control));
+    }
+    warnings
+}
@@ -0,0 +1,171 @@
+use chrono::{DateTime, TimeZone};
+
+use crate::ChatTermConfig;
+
+/// How numbers, dates and times are written for the user's locale
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    // Put between groups of three digits, None to not group them
+    pub thousands_separator: Option<char>,
+    pub decimal_mark: char,
+    // chrono formats of dates and times
+    pub date_format: &'static str,
+    pub time_format: &'static str,
+}
+
+// Plain numbers, ISO dates and a 24-hour clock, for the C locale and locales that aren't known
+const DEFAULT_LOCALE: Locale = Locale {
+    thousands_separator: None,
+    decimal_mark: '.',
+    date_format: "%Y-%m-%d",
+    time_format: "%H:%M",
+};
+
+impl Default for Locale {
+    fn default() -> Self {
+        DEFAULT_LOCALE
+    }
+}
+
+impl Locale {
+    /// The locale with a name like `en_US.UTF-8`, `de_DE` or `fr`
+    pub fn from_name(name: &str) -> Self {
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let (language, country) = name.split_once(['_', '-']).unwrap_or((name, ""));
+        // Non-breaking space, so that numbers are never split across lines
+        const SPACE: char = '\u{a0}';
+        let (thousands_separator, decimal_mark) = match (language, country) {
+            ("de" | "it", "CH") => (Some('\''), '.'),
+            (
+                "de" | "nl" | "es" | "it" | "pt" | "id" | "da" | "tr" | "el" | "ro" | "hr" | "sl",
+                _,
+            ) => (Some('.'), ','),
+            (
+                "fr" | "ru" | "uk" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "hu"
+                | "bg",
+                _,
+            ) => (Some(SPACE), ','),
+            ("en" | "ja" | "zh" | "ko" | "he" | "th", _) => (Some(','), '.'),
+            _ => return DEFAULT_LOCALE,
+        };
+        let date_format = match (language, country) {
+            ("en", "US" | "PH") => "%m/%d/%Y",
+            ("en", "CA") | ("ja" | "zh" | "ko" | "hu", _) => "%Y-%m-%d",
+            (
+                "de" | "ru" | "uk" | "pl" | "cs" | "sk" | "fi" | "nb" | "nn" | "no" | "tr" | "ro"
+                | "hr",
+                _,
+            )
+            | ("bg" | "sl", _) => "%d.%m.%Y",
+            ("nl" | "da", _) => "%d-%m-%Y",
+            ("sv", _) => "%Y-%m-%d",
+            _ => "%d/%m/%Y",
+        };
+        // English outside of Britain and Ireland, and Korean, mostly use a 12-hour clock
+        let time_format = match (language, country) {
+            ("en", "GB" | "IE") => "%H:%M",
+            ("en" | "ko", _) => "%-I:%M %p",
+            _ => "%H:%M",
+        };
+        Self {
+            thousands_separator,
+            decimal_mark,
+            date_format,
+            time_format,
+        }
+    }
+
+    /// The locale of the environment, from `LC_ALL`, `LC_NUMERIC` or `LANG` like other programs
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|variable| std::env::var(variable).ok())
+            .find(|name| !name.is_empty())
+            .map(|name| Self::from_name(&name))
+            .unwrap_or_default()
+    }
+
+    /// The locale set in the config, or else the one of the environment
+    pub fn from_config(config: &ChatTermConfig) -> Self {
+        match &config.locale {
+            Some(name) => Self::from_name(name),
+            None => Self::from_env(),
+        }
+    }
+
+    // Digits of a whole number grouped in threes
+    fn group(&self, digits: &str) -> String {
+        let Some(separator) = self.thousands_separator else {
+            return digits.to_string();
+        };
+        let mut grouped = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    /// A whole number, e.g. `12,345`
+    pub fn number(&self, value: u64) -> String {
+        self.group(&value.to_string())
+    }
+
+    /// A number with the given number of decimals, e.g. `1.234,50`
+    pub fn decimal(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (whole, fraction) = formatted.split_once('.').unwrap_or((&formatted, ""));
+        let sign = if value < 0.0 && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            "-"
+        } else {
+            ""
+        };
+        if fraction.is_empty() {
+            format!("{}{}", sign, self.group(whole))
+        } else {
+            format!(
+                "{}{}{}{}",
+                sign,
+                self.group(whole),
+                self.decimal_mark,
+                fraction
+            )
+        }
+    }
+
+    /// An amount in US dollars with the given number of decimals, e.g. `$1,234.50`
+    pub fn dollars(&self, value: f64, decimals: usize) -> String {
+        format!("${}", self.decimal(value, decimals))
+    }
+
+    /// The time of day, e.g. `14:05` or `2:05 PM`
+    pub fn time<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        time.format(self.time_format).to_string()
+    }
+
+    /// The time of day with seconds, e.g. `14:05:09` or `2:05:09 PM`
+    pub fn time_with_seconds<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        time.format(&self.time_format.replace("%M", "%M:%S"))
+            .to_string()
+    }
+
+    /// A date and time, e.g. `05.03.2024 14:05`
+    pub fn date_time<Tz: TimeZone>(&self, time: &DateTime<Tz>) -> String
+    where
+        Tz::Offset: std::fmt::Display,
+    {
+        format!(
+            "{} {}",
+            time.format(self.date_format),
+            time.format(self.time_format)
+        )
+    }
+}
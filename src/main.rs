@@ -1,10 +1,25 @@
 // Import the library from lib.rs
 use chatgpt_term::{
-    api::{ChatGPTClient, ChatLogEntry},
+    api::{
+        backup_path, dedup_trailing_entries, verify_chatlog, ApiErrorKind, ChatGPTClient,
+        ChatGPTSession, ChatLogEntry, ChatlogIntegrity, SessionFile,
+    },
+    backend::BackendKind,
+    bundle::{add_persona, export_persona, read_persona_bundle},
+    credentials,
+    errors::ChatGptError,
+    events::{Event, EventLog},
+    memory::MemoryStore,
+    sessions::SessionManager,
+    tools,
+    transport::MockTransport,
+    usage::UsageStore,
+    whitespace::normalize_whitespace,
     ChatTermConfig,
 };
 use gumdrop::Options;
-use std::io::Write;
+use std::io::{IsTerminal, Read, Write};
+use std::sync::Arc;
 
 const MIN_MAX_TOKENS: u32 = 1000;
 const MAX_MAX_TOKENS: u32 = 4096;
@@ -19,7 +34,7 @@ fn prompt_yes_no(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
         stdout.flush()?;
         input.clear();
         stdin.read_line(&mut input)?;
-        input = format!("{}", input.trim().to_lowercase());
+        input = input.trim().to_lowercase();
     }
     Ok(input)
 }
@@ -69,13 +84,139 @@ struct Args {
     session: Option<String>,
     #[options(help = "reconfigure the application")]
     reconfigure: bool,
+    #[options(
+        short = "R",
+        help = "only view the session, without the input box and without saving"
+    )]
+    read_only: bool,
+    #[options(no_short, help = "don't save the session when quitting")]
+    no_save: bool,
+    #[options(
+        meta = "TEXT",
+        help = "send a single message without the terminal UI and print the answer"
+    )]
+    prompt: Option<String>,
+    #[options(
+        no_short,
+        meta = "FD",
+        help = "write JSON events about requests to this file descriptor"
+    )]
+    event_fd: Option<u32>,
+    #[options(
+        no_short,
+        meta = "FILE",
+        help = "append JSON events about requests to this file"
+    )]
+    event_file: Option<String>,
+    #[options(command)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Options)]
+enum Command {
+    #[options(help = "send a single message and print the answer")]
+    Ask(AskArgs),
+    #[options(
+        help = "send the messages of a dialogue script from stdin in order, within one session"
+    )]
+    Script(ScriptArgs),
+    #[options(
+        help = "send the messages of a saved session again and report how the answers changed"
+    )]
+    Replay(ReplayArgs),
+    #[options(help = "work with the saved sessions")]
+    Sessions(SessionsArgs),
+    #[options(help = "write a persona from the config to a file that can be shared")]
+    ExportPersona(ExportPersonaArgs),
+    #[options(help = "add a persona from a shared file to the config")]
+    ImportPersona(ImportPersonaArgs),
+}
+
+#[derive(Debug, Options)]
+struct AskArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(free, help = "message to send, read from stdin if not given")]
+    message: Vec<String>,
+    #[options(help = "session file to continue, the exchange is saved to it")]
+    session: Option<String>,
+    #[options(no_short, help = "print the answer only once it is complete")]
+    no_stream: bool,
+}
+
+#[derive(Debug, Options)]
+struct ScriptArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(help = "session file to continue, the exchanges are saved to it")]
+    session: Option<String>,
+    #[options(no_short, help = "print each answer only once it is complete")]
+    no_stream: bool,
+}
+
+#[derive(Debug, Options)]
+struct ReplayArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(free, required, help = "session file to replay")]
+    session: String,
+    #[options(help = "model to send the messages to instead of the configured one")]
+    model: Option<String>,
+    #[options(help = "file to write the report to instead of printing it")]
+    output: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct SessionsArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(command)]
+    command: Option<SessionsCommand>,
+}
+
+#[derive(Debug, Options)]
+enum SessionsCommand {
+    #[options(
+        help = "have the model suggest improvements to the personas from the ratings and notes of their sessions"
+    )]
+    Analyze(AnalyzeArgs),
+}
+
+#[derive(Debug, Options)]
+struct AnalyzeArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(help = "file to write the report to instead of printing it")]
+    output: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct ExportPersonaArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(free, required, help = "name of the persona")]
+    name: String,
+    #[options(help = "file to write the persona to (default: <name>.persona.json)")]
+    output: Option<String>,
+}
+
+#[derive(Debug, Options)]
+struct ImportPersonaArgs {
+    #[options(help = "print help message")]
+    help: bool,
+    #[options(free, required, help = "persona file to import")]
+    file: String,
+    #[options(help = "replace a persona with the same name instead of renaming the imported one")]
+    overwrite: bool,
 }
 
 fn configure() -> Result<ChatTermConfig, Box<dyn std::error::Error>> {
     // Prompt the user to get the OpenAI API key and save it to the config file
     let api_key = prompt_non_empty("Enter OpenAI API Key: ")?;
-    let mut config = ChatTermConfig::default();
-    config.openai_api_key = api_key;
+    let mut config = ChatTermConfig {
+        openai_api_key: api_key,
+        ..Default::default()
+    };
 
     // Display current initial prompt and ask user if they want to change it
     println!("Initial prompt:\n\n{}\n", config.initial_prompt);
@@ -91,26 +232,506 @@ fn configure() -> Result<ChatTermConfig, Box<dyn std::error::Error>> {
 
     Ok(config)
 }
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+// Check a session file before loading it and offer to fall back to its backup if it is corrupted
+fn resolve_session_file(path: String) -> Result<String, Box<dyn std::error::Error>> {
+    if verify_chatlog(&path)? != ChatlogIntegrity::Corrupted {
+        return Ok(path);
+    }
+    println!("Warning: session file {} is truncated or corrupted", path);
+    let backup = backup_path(&path);
+    if std::path::Path::new(&backup).exists()
+        && verify_chatlog(&backup)? == ChatlogIntegrity::Intact
+        && prompt_yes_no("Load the most recent intact backup instead? (y/n): ")? == "y"
+    {
+        return Ok(backup);
+    }
+    Ok(path)
+}
+
+// Start a session for a non-interactive command, continuing a session file if it exists
+fn open_session(
+    config: ChatTermConfig,
+    path: Option<&String>,
+    events: EventLog,
+) -> Result<ChatGPTSession, Box<dyn std::error::Error>> {
+    let memory = config.memory;
+    let mut session = ChatGPTClient::new(config).new_session(Vec::new(), 2000);
+    session.set_event_log(events);
+    if memory {
+        session.set_memories(MemoryStore::load(MemoryStore::default_path()?)?.facts());
+    }
+    if let Some(path) = path {
+        if std::path::Path::new(path).exists() {
+            session.restore(SessionFile::load(&resolve_session_file(path.clone())?)?)?;
+        }
+    }
+    Ok(session)
+}
+
+// Send a message and print the answer, streaming it as it is generated unless disabled, and record the usage
+fn send_and_print(
+    session: &mut ChatGPTSession,
+    usage: &mut UsageStore,
+    message: &str,
+    stream: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let normalized = if session.config().normalize_whitespace {
+        normalize_whitespace(message, session.model())
+    } else {
+        None
+    };
+    if let Some(normalized) = &normalized {
+        eprintln!(
+            "Collapsed whitespace, saving {} characters (~{} tokens)",
+            normalized.saved_chars, normalized.saved_tokens
+        );
+    }
+    let message = normalized
+        .as_ref()
+        .map_or(message, |normalized| normalized.text.as_str());
+
+    // Filters apply to the whole response, so it can't be printed as it is generated
+    let entry = if stream && session.config().response_filters.is_empty() {
+        let mut stdout = std::io::stdout();
+        let mut print_token = |token: &str| {
+            print!("{}", token);
+            stdout.flush().ok();
+        };
+        let entry = session.send_message_streaming(message, Some(&mut print_token))?;
+        println!();
+        entry
+    } else {
+        let entry = session.send_message(message)?;
+        println!("{}", entry.response);
+        entry
+    };
+
+    let (prompt_tokens, completion_tokens) = entry.total_tokens();
+    let cost = session.entry_cost(&entry);
+    usage.record(prompt_tokens, completion_tokens, cost)?;
+    session.emit(Event::Cost {
+        request: cost,
+        session: session.cost(),
+        today: usage.today().cost,
+    });
+    Ok(())
+}
+
+// Save the session of a non-interactive command if a session file was given, then fail if the last answer was
+// blocked by the content filter
+fn finish_exchange(
+    session: &ChatGPTSession,
+    path: Option<&String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(path) = path {
+        session.save_chatlog_to_path(path)?;
+    }
+    let finish_reason = session
+        .last_response()
+        .and_then(|metadata| metadata.finish_reason.as_deref());
+    if finish_reason == Some("content_filter") {
+        return Err(ChatGptError::Api {
+            status: 200,
+            code: Some("content_filter".to_string()),
+            message: "The answer was cut short by the content filter".to_string(),
+            retry_after: None,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+// Send a single message and print the answer
+fn ask(
+    config: ChatTermConfig,
+    args: AskArgs,
+    events: EventLog,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message = if args.message.is_empty() {
+        let mut message = String::new();
+        std::io::stdin().read_to_string(&mut message)?;
+        message
+    } else {
+        args.message.join(" ")
+    };
+    let message = message.trim();
+    if message.is_empty() {
+        return Err("No message to send".into());
+    }
+
+    let mut session = open_session(config, args.session.as_ref(), events)?;
+    let mut usage = UsageStore::load(UsageStore::default_path()?)?;
+    send_and_print(&mut session, &mut usage, message, !args.no_stream)?;
+    finish_exchange(&session, args.session.as_ref())
+}
+
+// Messages of a dialogue script: one per line, or one per document if the script separates documents with `---`
+// lines like YAML, so that messages can span several lines
+fn script_messages(script: &str) -> Vec<String> {
+    let messages: Vec<String> = if script.lines().any(|line| line.trim_end() == "---") {
+        script
+            .split('\n')
+            .collect::<Vec<_>>()
+            .split(|line| line.trim_end() == "---")
+            .map(|lines| lines.join("\n"))
+            .collect()
+    } else {
+        script.lines().map(String::from).collect()
+    };
+    messages
+        .into_iter()
+        .map(|message| message.trim().to_string())
+        .filter(|message| !message.is_empty())
+        .collect()
+}
+
+// Send the messages of a dialogue script from stdin in order within one session, printing the conversation
+fn script(
+    config: ChatTermConfig,
+    args: ScriptArgs,
+    events: EventLog,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let messages = script_messages(&input);
+    if messages.is_empty() {
+        return Err("No messages to send".into());
+    }
+
+    let mut session = open_session(config, args.session.as_ref(), events)?;
+    let mut usage = UsageStore::load(UsageStore::default_path()?)?;
+    for message in messages {
+        println!("You: {}", message);
+        print!("Bot: ");
+        std::io::stdout().flush()?;
+        if let Err(err) = send_and_print(&mut session, &mut usage, &message, !args.no_stream) {
+            println!();
+            return Err(err);
+        }
+        println!();
+        finish_exchange(&session, args.session.as_ref())?;
+    }
+    Ok(())
+}
+
+// Load a session file, offering to drop repeated trailing exchanges. A file that doesn't exist is an empty session.
+fn load_session_file(path: String) -> Result<SessionFile, Box<dyn std::error::Error>> {
+    if !std::path::Path::new(&path).exists() {
+        return Ok(SessionFile::default());
+    }
+    let path = resolve_session_file(path)?;
+    let mut file = SessionFile::load(&path)?;
+
+    let mut deduped = file.entries.clone();
+    let num_duplicates = dedup_trailing_entries(&mut deduped);
+    if num_duplicates > 0 {
+        println!(
+            "Session file {} ends with {} repeated exchange(s)",
+            path, num_duplicates
+        );
+        if prompt_yes_no("Remove the duplicates? (y/n): ")? == "y" {
+            file.entries = deduped;
+        }
+    }
+    Ok(file)
+}
+
+// Messages of the conversation the e2e command has with the mock transport
+const E2E_SCRIPT: [&str; 3] = ["Hello", "What is 2 + 2?", "Thanks, bye"];
+
+// Have a scripted conversation with the mock transport, save it, load it back and continue it, checking what is
+// sent and saved along the way. An end-to-end test of the send, save and load pipeline for maintainers, which needs
+// no network, API key or config file and is left out of the help.
+fn e2e() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("chatgpt-term-e2e-{}", std::process::id()));
+    let config = ChatTermConfig {
+        sessions_dir: Some(dir.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let transport = Arc::new(MockTransport::new());
+    let mut failures = Vec::new();
+    let mut check = |name: &str, passed: bool| {
+        println!("{} {}", if passed { "ok  " } else { "FAIL" }, name);
+        if !passed {
+            failures.push(name.to_string());
+        }
+    };
+
+    // Every other message is streamed
+    let client = ChatGPTClient::with_transport(config.clone(), transport.clone());
+    let mut session = client.new_session(Vec::new(), config.max_tokens);
+    for (index, message) in E2E_SCRIPT.iter().enumerate() {
+        let mut streamed = String::new();
+        let entry = if index % 2 == 1 {
+            let mut on_token = |token: &str| streamed.push_str(token);
+            session.send_message_streaming(message, Some(&mut on_token))?
+        } else {
+            session.send_message(message)?
+        };
+        check(
+            &format!("answer to message {}", index + 1),
+            entry.message == *message && entry.response == MockTransport::answer(message),
+        );
+        if index % 2 == 1 {
+            check(
+                &format!("streamed answer to message {}", index + 1),
+                streamed == entry.response,
+            );
+        }
+        check(
+            &format!("tokens of message {} are counted", index + 1),
+            entry.num_tokens_message > 0 && entry.num_tokens_response > 0,
+        );
+    }
+    let requests = transport.requests();
+    check(
+        "one request per message",
+        requests.len() == E2E_SCRIPT.len(),
+    );
+    let sent: serde_json::Value = serde_json::from_slice(
+        requests
+            .last()
+            .and_then(|request| request.body.as_deref())
+            .unwrap_or_default(),
+    )?;
+    // The initial prompt, the earlier exchanges and the new message
+    check(
+        "earlier exchanges are sent along",
+        sent["messages"].as_array().map(Vec::len) == Some(2 * E2E_SCRIPT.len()),
+    );
+
+    session.set_name("e2e");
+    let path = session.save_chatlog()?;
+    let file = SessionFile::load(&path)?;
+    check(
+        "saved session has every exchange",
+        file.entries.len() == E2E_SCRIPT.len()
+            && file.entries.iter().zip(E2E_SCRIPT).all(|(entry, message)| {
+                entry.message == message && entry.response == MockTransport::answer(message)
+            }),
+    );
+    check(
+        "saved exchanges have timestamps",
+        file.entries.iter().all(|entry| entry.timestamp.is_some()),
+    );
+
+    // A session loaded from the file continues where the saved one left off
+    let client = ChatGPTClient::with_transport(config.clone(), transport.clone());
+    let mut restored = client.new_session(Vec::new(), config.max_tokens);
+    restored.restore(file)?;
+    let entry = restored.send_message("Are you still there?")?;
+    check(
+        "restored session answers",
+        entry.response == MockTransport::answer("Are you still there?"),
+    );
+    check(
+        "restored session sends the saved exchanges",
+        transport.requests().len() == E2E_SCRIPT.len() + 1
+            && transport.requests().last().is_some_and(|request| {
+                let body = String::from_utf8_lossy(request.body.as_deref().unwrap_or_default());
+                E2E_SCRIPT.iter().all(|message| body.contains(message))
+            }),
+    );
+    restored.save_chatlog_to_path(&path)?;
+    check(
+        "continued session is saved",
+        SessionFile::load(&path)?.entries.len() == E2E_SCRIPT.len() + 1,
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    if failures.is_empty() {
+        println!("All checks passed");
+        Ok(())
+    } else {
+        Err(format!("{} checks failed: {}", failures.len(), failures.join(", ")).into())
+    }
+}
+
+// Exit code for an error, distinguishing the kinds of API errors so that scripts can branch on them
+fn exit_code(err: &(dyn std::error::Error + 'static)) -> i32 {
+    match err.downcast_ref::<ChatGptError>().map(ChatGptError::kind) {
+        Some(ApiErrorKind::Auth) => 2,
+        Some(ApiErrorKind::RateLimit | ApiErrorKind::Quota) => 3,
+        Some(ApiErrorKind::Network) => 4,
+        Some(ApiErrorKind::ContentFilter) => 5,
+        _ => 1,
+    }
+}
+
+fn main() {
+    if let Err(err) = try_main() {
+        eprintln!("Error: {}", err);
+        std::process::exit(exit_code(err.as_ref()));
+    }
+}
+
+fn try_main() -> Result<(), Box<dyn std::error::Error>> {
+    // The end-to-end test runs on its own, without the config file
+    if std::env::args().nth(1).as_deref() == Some("e2e") {
+        return e2e();
+    }
+
     // Parse command line arguments
     let args = Args::parse_args_default_or_exit();
 
     // Use confy to load config file into struct
-    let config: ChatTermConfig = confy::load("chatgpt-term", None).unwrap_or_default();
+    let mut config: ChatTermConfig = confy::load("chatgpt-term", None).unwrap_or_default();
+    tools::check_tool_names(&config)?;
 
-    // If the this is the first time or if the user wants to configure the application, run the configuration function
-    let config = if config.openai_api_key.is_empty() || args.reconfigure {
-        let config = configure()?;
-        println!("Saving config ...");
+    // A key left in the config file after turning on use_keyring is moved to the keychain
+    if config.use_keyring && !config.openai_api_key.is_empty() {
+        credentials::store_in_keyring(&config.openai_api_key)?;
+        config.openai_api_key.clear();
         confy::store("chatgpt-term", None, &config)?;
-        config
-    } else {
-        config
+        println!("Moved the API key from the config file to the keychain");
+    }
+
+    // If the this is the first time or if the user wants to configure the application, run the configuration function
+    let api_key = match credentials::api_key(&config)? {
+        Some(api_key) if !args.reconfigure => api_key,
+        // Other backends have keys of their own, or none for a local Ollama server
+        None if config.backend != BackendKind::OpenAi && !args.reconfigure => String::new(),
+        _ => {
+            let use_keyring = config.use_keyring;
+            config = configure()?;
+            config.use_keyring = use_keyring;
+            let api_key = config.openai_api_key.clone();
+            if use_keyring {
+                credentials::store_in_keyring(&api_key)?;
+                config.openai_api_key.clear();
+            }
+            println!("Saving config ...");
+            confy::store("chatgpt-term", None, &config)?;
+            api_key
+        }
     };
+    // The key is only kept in memory from here on, so the config must not be stored again as it is
+    config.openai_api_key = api_key;
 
+    let events = match (args.event_fd, &args.event_file) {
+        (Some(fd), _) => EventLog::open_fd(fd)?,
+        (None, Some(path)) => EventLog::open_file(path)?,
+        (None, None) => EventLog::default(),
+    };
+
+    if let Some(Command::Ask(ask_args)) = args.command {
+        return ask(config, ask_args, events);
+    }
+    if let Some(Command::Script(script_args)) = args.command {
+        return script(config, script_args, events);
+    }
+    if let Some(Command::Replay(replay_args)) = args.command {
+        let mut config = config;
+        if let Some(model) = replay_args.model {
+            config.openai_model = model;
+        }
+        let file = SessionFile::load(&resolve_session_file(replay_args.session)?)?;
+        let report = chatgpt_term::replay::replay(ChatGPTClient::new(config), file)?;
+        match replay_args.output {
+            Some(path) => std::fs::write(path, report)?,
+            None => print!("{}", report),
+        }
+        return Ok(());
+    }
+    if let Some(Command::Sessions(sessions_args)) = args.command {
+        let Some(SessionsCommand::Analyze(analyze_args)) = sessions_args.command else {
+            return Err("Missing command, see sessions --help".into());
+        };
+        // Sessions that can no longer be read are left out
+        let files: Vec<SessionFile> = SessionManager::from_config(&config)
+            .list()?
+            .iter()
+            .filter_map(|summary| SessionFile::load(&summary.path).ok())
+            .collect();
+        let report = chatgpt_term::analyze::analyze(&ChatGPTClient::new(config), &files)?;
+        match analyze_args.output {
+            Some(path) => std::fs::write(path, report)?,
+            None => print!("{}", report),
+        }
+        return Ok(());
+    }
+    if let Some(Command::ExportPersona(export_args)) = args.command {
+        let persona = config
+            .personas
+            .iter()
+            .find(|persona| persona.name == export_args.name)
+            .ok_or_else(|| format!("No persona named {} in the config", export_args.name))?;
+        let path = export_args
+            .output
+            .unwrap_or_else(|| format!("{}.persona.json", persona.name));
+        export_persona(persona, &path)?;
+        println!("Exported persona {} to {}", persona.name, path);
+        return Ok(());
+    }
+    if let Some(Command::ImportPersona(import_args)) = args.command {
+        // Read the file again so that a key from the environment or the keychain is not written to it
+        let mut config: ChatTermConfig = confy::load("chatgpt-term", None)?;
+        let persona = read_persona_bundle(&import_args.file)?;
+        let original_name = persona.name.clone();
+        let name = add_persona(&mut config.personas, persona, import_args.overwrite);
+        confy::store("chatgpt-term", None, &config)?;
+        if name == original_name {
+            println!("Imported persona {}", name);
+        } else {
+            println!(
+                "A persona named {} already exists, imported as {}",
+                original_name, name
+            );
+        }
+        return Ok(());
+    }
+
+    // A message given with --prompt or piped to stdin is sent like with the ask command, continuing --session. Input
+    // piped along with --prompt follows it, so that it can be asked about.
+    let piped = !args.read_only && !std::io::stdin().is_terminal();
+    if args.prompt.is_some() || piped {
+        let mut input = String::new();
+        if piped {
+            std::io::stdin().read_to_string(&mut input)?;
+        }
+        let prompt = args.prompt.unwrap_or_default();
+        let parts: Vec<&str> = [prompt.as_str(), input.trim_end()]
+            .into_iter()
+            .filter(|part| !part.trim().is_empty())
+            .collect();
+        let message = parts.join("\n\n");
+        // An empty pipe, e.g. from cron, is not worth a request
+        if message.is_empty() {
+            return Err("Nothing to send, the prompt and the piped input are empty".into());
+        }
+        let ask_args = AskArgs {
+            help: false,
+            message: vec![message],
+            session: args.session,
+            no_stream: false,
+        };
+        return ask(config, ask_args, events);
+    }
+
+    match &args.session {
+        None if args.read_only => {
+            return Err("--read-only needs a session file given with --session".into());
+        }
+        Some(path) if args.read_only && !std::path::Path::new(path).exists() => {
+            return Err(format!("Session file {} doesn't exist", path).into());
+        }
+        Some(path) if std::path::Path::new(path).is_dir() => {
+            return Err(format!("{} is a directory, not a session file", path).into());
+        }
+        _ => {}
+    }
+    // The session is saved back to the file it was given as, even if its backup was loaded
+    let session_file = args
+        .session
+        .map(|path| load_session_file(path.clone()).map(|file| (path, file)))
+        .transpose()?;
+
+    if args.no_save {
+        config.save_on_quit = false;
+    }
     // Create a new client using config
     let client = ChatGPTClient::new(config);
-    chatgpt_term::app::run(client, args.session)?;
+    chatgpt_term::app::run(client, session_file, args.read_only, events)?;
 
     Ok(())
 }
@@ -0,0 +1,272 @@
+use textwrap::core::display_width;
+
+// Code blocks are at least this wide, so that short snippets still stand out as blocks
+const MIN_CODE_WIDTH: usize = 20;
+const TAB: &str = "    ";
+
+// The language of a line opening or closing a code fence, which is empty if not given
+fn fence_language(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let rest = line
+        .strip_prefix("```")
+        .or_else(|| line.strip_prefix("~~~"))?;
+    Some(rest.trim_matches(|c: char| c == '`' || c == '~' || c.is_whitespace()))
+}
+
+/// A fenced code block in markdown text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FencedBlock {
+    // Language the fence is tagged with, empty if none
+    pub language: String,
+    pub code: String,
+    // The last line of text before the block, which often says what the code is for
+    pub intro: String,
+}
+
+/// The fenced code blocks in markdown text, in order. A block that is not closed runs to the end.
+pub fn fenced_blocks(text: &str) -> Vec<FencedBlock> {
+    let mut blocks = Vec::new();
+    let mut intro = "";
+    let mut code: Option<(&str, Vec<&str>)> = None;
+    for line in text.split('\n') {
+        match (&mut code, fence_language(line)) {
+            (Some((language, lines)), Some("")) => {
+                blocks.push(FencedBlock {
+                    language: language.to_string(),
+                    code: lines.join("\n"),
+                    intro: intro.to_string(),
+                });
+                code = None;
+                intro = "";
+            }
+            (Some((_, lines)), _) => lines.push(line),
+            (None, Some(language)) => code = Some((language, Vec::new())),
+            (None, None) if !line.trim().is_empty() => intro = line.trim(),
+            (None, None) => {}
+        }
+    }
+    blocks.extend(code.map(|(language, lines)| FencedBlock {
+        language: language.to_string(),
+        code: lines.join("\n"),
+        intro: intro.to_string(),
+    }));
+    blocks
+}
+
+/// The contents of the fenced code blocks in markdown text, in order
+pub fn code_blocks(text: &str) -> Vec<String> {
+    fenced_blocks(text)
+        .into_iter()
+        .map(|block| block.code)
+        .collect()
+}
+
+// A fenced code block, drawn as a box as wide as its longest line
+struct CodeBlock {
+    language: String,
+    lines: Vec<String>,
+}
+
+impl CodeBlock {
+    fn render(&self, closed: bool) -> Vec<String> {
+        let width = self
+            .lines
+            .iter()
+            .map(|line| display_width(line))
+            .chain([MIN_CODE_WIDTH, display_width(&self.language) + 2])
+            .max()
+            .unwrap_or_default();
+        let top = if self.language.is_empty() {
+            String::from("┌")
+        } else {
+            format!("┌─ {} ", self.language)
+        };
+        let mut lines = vec![format!(
+            "{}{}",
+            top,
+            "─".repeat((width + 2).saturating_sub(display_width(&top)))
+        )];
+        for line in self.lines.iter() {
+            let padding = " ".repeat(width - display_width(line));
+            lines.push(format!("│ {}{}", line, padding));
+        }
+        // A block that is still being streamed is left open
+        if closed {
+            lines.push(format!("└{}", "─".repeat(width + 1)));
+        }
+        lines
+    }
+}
+
+// A list item split into its indentation and marker, which becomes a bullet unless the list is numbered, and text
+fn list_item(line: &str) -> Option<(String, String, &str)> {
+    let text = line.trim_start();
+    let indent = line[..line.len() - text.len()].replace('\t', TAB);
+    let (marker, rest) = text.split_once(' ')?;
+    let numbered = marker.len() > 1
+        && marker.ends_with(['.', ')'])
+        && marker[..marker.len() - 1]
+            .bytes()
+            .all(|b| b.is_ascii_digit());
+    let marker = match marker {
+        "-" | "*" | "+" => String::from("•"),
+        _ if numbered => marker.to_string(),
+        _ => return None,
+    };
+    Some((indent, marker, rest.trim_start()))
+}
+
+// Whether a line is a thematic break such as `---` or `***`
+fn is_rule(line: &str) -> bool {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&c| chars.iter().all(|&x| x == c))
+}
+
+// Wrap text after a prefix, indenting the following lines by the width of the prefix
+fn wrap_hanging(
+    prefix: &str,
+    text: &str,
+    width: usize,
+    wrap: &dyn Fn(&str, usize) -> Vec<String>,
+) -> Vec<String> {
+    let indent = " ".repeat(display_width(prefix));
+    wrap(text, width.saturating_sub(indent.len()).max(1))
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let prefix = if index == 0 { prefix } else { &indent };
+            format!("{}{}", prefix, line)
+        })
+        .collect()
+}
+
+/// Lay out markdown text as lines of at most the given width, using `wrap` to wrap paragraphs. Code blocks are
+/// boxed and not wrapped, list items keep their indentation and wrap under their text, and the remaining markup is
+/// left in place to be styled by `line_styles`.
+pub fn render_markdown(
+    text: &str,
+    width: usize,
+    wrap: &dyn Fn(&str, usize) -> Vec<String>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut code: Option<CodeBlock> = None;
+    for line in text.split('\n') {
+        if let Some(block) = &mut code {
+            if fence_language(line).is_some_and(str::is_empty) {
+                lines.extend(block.render(true));
+                code = None;
+            } else {
+                block.lines.push(line.replace('\t', TAB));
+            }
+            continue;
+        }
+        if let Some(language) = fence_language(line) {
+            code = Some(CodeBlock {
+                language: language.to_string(),
+                lines: Vec::new(),
+            });
+        } else if is_rule(line) {
+            lines.push("─".repeat(width.min(40)));
+        } else if let Some((indent, marker, text)) = list_item(line) {
+            let prefix = format!("{}{} ", indent, marker);
+            lines.extend(wrap_hanging(&prefix, text, width, wrap));
+        } else if let Some(quote) = line.trim_start().strip_prefix('>') {
+            lines.extend(wrap_hanging("> ", quote.trim_start(), width, wrap));
+        } else {
+            lines.extend(wrap(line, width));
+        }
+    }
+    if let Some(block) = code {
+        lines.extend(block.render(false));
+    }
+    lines
+}
+
+#[cfg(feature = "tui")]
+pub use styles::line_styles;
+
+// Styles of laid out markdown in the terminal UI
+#[cfg(feature = "tui")]
+mod styles {
+    use std::ops::Range;
+
+    use tui::style::{Color, Modifier, Style};
+
+    fn heading_style() -> Style {
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    fn code_style() -> Style {
+        Style::default().bg(Color::Indexed(236))
+    }
+
+    fn border_style() -> Style {
+        Style::default().fg(Color::DarkGray)
+    }
+
+    fn inline_code_style() -> Style {
+        Style::default().fg(Color::Yellow)
+    }
+
+    // Ranges of the characters between pairs of a delimiter, including the delimiters
+    fn delimited(chars: &[char], delimiter: &str) -> Vec<Range<usize>> {
+        let delimiter: Vec<char> = delimiter.chars().collect();
+        let at = |index: usize| chars[index..].starts_with(&delimiter);
+        let mut ranges = Vec::new();
+        let mut index = 0;
+        while index < chars.len() {
+            if !at(index) {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            index += delimiter.len();
+            while index < chars.len() && !at(index) {
+                index += 1;
+            }
+            if index >= chars.len() {
+                break;
+            }
+            index += delimiter.len();
+            // Empty pairs such as `****` are not emphasis
+            if index - start > 2 * delimiter.len() {
+                ranges.push(start..index);
+            }
+        }
+        ranges
+    }
+
+    /// Styles of the parts of a line laid out by `render_markdown`, as ranges of characters
+    pub fn line_styles(line: &str) -> Vec<(Range<usize>, Style)> {
+        let chars: Vec<char> = line.chars().collect();
+        let whole = 0..chars.len();
+        if line.starts_with('┌') || line.starts_with('└') {
+            return vec![(whole, border_style())];
+        }
+        if line.starts_with("│ ") {
+            return vec![(0..1, border_style()), (1..chars.len(), code_style())];
+        }
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&hashes) && chars.get(hashes) == Some(&' ') {
+            return vec![(whole, heading_style())];
+        }
+        let code = delimited(&chars, "`");
+        let bold = delimited(&chars, "**")
+            .into_iter()
+            .filter(|range| {
+                !code
+                    .iter()
+                    .any(|code| code.start < range.end && range.start < code.end)
+            })
+            .map(|range| (range, Style::default().add_modifier(Modifier::BOLD)));
+        code.iter()
+            .map(|range| (range.clone(), inline_code_style()))
+            .chain(bold)
+            .collect()
+    }
+}
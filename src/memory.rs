@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// A fact about the user that they approved to be remembered across sessions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Memory {
+    pub fact: String,
+    pub added: DateTime<Local>,
+}
+
+/// The remembered facts, persisted next to the config file
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MemoryStore {
+    #[serde(skip)]
+    path: PathBuf,
+    memories: Vec<Memory>,
+}
+
+impl MemoryStore {
+    /// Default location of the memory store, in the same directory as the config file
+    pub fn default_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let config_path = confy::get_configuration_file_path("chatgpt-term", None)?;
+        Ok(config_path.with_file_name("memories.json"))
+    }
+
+    /// Load the memory store, starting an empty one if it doesn't exist yet
+    pub fn load(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut store: MemoryStore = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => MemoryStore::default(),
+            Err(err) => return Err(err.into()),
+        };
+        store.path = path;
+        Ok(store)
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self)?)
+    }
+
+    pub fn memories(&self) -> &[Memory] {
+        &self.memories
+    }
+
+    /// The remembered facts, oldest first
+    pub fn facts(&self) -> Vec<String> {
+        self.memories
+            .iter()
+            .map(|memory| memory.fact.clone())
+            .collect()
+    }
+
+    /// Remember a fact and save the store. A fact that is already remembered is not added again.
+    pub fn add(&mut self, fact: &str) -> std::io::Result<()> {
+        let fact = fact.trim();
+        if self.memories.iter().any(|memory| memory.fact == fact) {
+            return Ok(());
+        }
+        self.memories.push(Memory {
+            fact: fact.to_string(),
+            added: Local::now(),
+        });
+        self.save()
+    }
+
+    /// Forget the fact at an index and save the store, returning it, or None if there is no such fact
+    pub fn remove(&mut self, index: usize) -> std::io::Result<Option<Memory>> {
+        if index >= self.memories.len() {
+            return Ok(None);
+        }
+        let memory = self.memories.remove(index);
+        self.save()?;
+        Ok(Some(memory))
+    }
+
+    /// Forget every fact and save the store
+    pub fn clear(&mut self) -> std::io::Result<()> {
+        self.memories.clear();
+        self.save()
+    }
+}
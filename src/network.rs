@@ -0,0 +1,94 @@
+use reqwest::blocking::ClientBuilder;
+use reqwest::{Certificate, Proxy};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::retry::RetryConfig;
+
+// Proxy used for all requests when none is set in the config, as curl does. HTTPS_PROXY and HTTP_PROXY are
+// honored by reqwest itself.
+const ALL_PROXY_VARIABLES: [&str; 2] = ["ALL_PROXY", "all_proxy"];
+// Longest wait for the server to connect or send more of an answer when the config doesn't set one
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// How requests reach the API and the web search provider, e.g. through a corporate proxy
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NetworkConfig {
+    // Proxy for all requests, e.g. "http://proxy.example.com:3128" or "socks5h://localhost:1080", in place of
+    // those in HTTPS_PROXY, HTTP_PROXY and ALL_PROXY
+    pub proxy: Option<String>,
+    // PEM file with a certificate to trust along with the ones of the system, such as the one of a proxy that
+    // inspects TLS traffic
+    pub ca_certificate: Option<String>,
+    // Don't check certificates at all, which makes the connection open to eavesdropping
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    // Longest wait in seconds for the server to connect or send more of an answer, after which the request fails
+    // and its connection is closed, 30 if not set
+    pub timeout_secs: Option<u64>,
+}
+
+impl NetworkConfig {
+    // The proxy of the config, or else the one of ALL_PROXY
+    fn proxy_url(&self) -> Option<String> {
+        self.proxy.clone().or_else(|| {
+            ALL_PROXY_VARIABLES
+                .iter()
+                .filter_map(|variable| std::env::var(variable).ok())
+                .find(|url| !url.trim().is_empty())
+        })
+    }
+
+    /// A builder of HTTP clients that go through the proxy and trust the certificate of the config
+    pub fn client_builder(&self) -> Result<ClientBuilder, String> {
+        let mut builder = ClientBuilder::new();
+        if let Some(url) = self.proxy_url() {
+            let proxy =
+                Proxy::all(&url).map_err(|err| format!("Invalid proxy {}: {}", url, err))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_certificate {
+            let pem = std::fs::read(path)
+                .map_err(|err| format!("Error reading the certificate {}: {}", path, err))?;
+            let certificate = Certificate::from_pem(&pem)
+                .map_err(|err| format!("Invalid certificate {}: {}", path, err))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        let timeout = Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+        Ok(builder
+            .timeout(timeout)
+            .danger_accept_invalid_certs(self.accept_invalid_certs))
+    }
+}
+
+/// Settings of low-bandwidth mode, for flaky or slow connections. It is turned on and off with /lowbw.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LowBandwidthConfig {
+    // Start sessions in low-bandwidth mode
+    pub enabled: bool,
+    // Longest answer in tokens, in place of max_response_tokens when that is unset or larger
+    pub max_response_tokens: u32,
+    // Send the body of requests compressed with gzip, only for servers or gateways known to accept it, which the
+    // OpenAI API and Ollama don't say they do
+    pub compress_requests: bool,
+    // Retries in place of the ones in the [retry] table, more of them with shorter waits spread out at random
+    pub retry: RetryConfig,
+}
+
+impl Default for LowBandwidthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_response_tokens: 400,
+            compress_requests: false,
+            retry: RetryConfig {
+                max_attempts: 8,
+                initial_backoff_secs: 0.5,
+                max_backoff_secs: 20.0,
+                jitter: 0.5,
+            },
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::io::{BufRead, BufReader};
+
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::api::{api_error, metadata_headers, ChatGPTClient, ChatLogEntry, ResponseMetadata};
+use crate::backend::{ChatBackend, ChatRequest};
+use crate::errors::ChatGptError;
+use crate::transport::{HttpRequest, HttpResponse};
+
+/// Where Ollama serves local models, for the ollama backend
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct OllamaConfig {
+    pub base_url: String,
+    // Model used unless another one is chosen, as named by `ollama list`
+    pub model: String,
+    // How long the model stays loaded after a request, e.g. "30m", Ollama's default if not set
+    pub keep_alive: Option<String>,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            base_url: String::from("http://localhost:11434"),
+            model: String::from("llama3"),
+            keep_alive: None,
+        }
+    }
+}
+
+/// The chat API of a local Ollama server, which needs no API key or internet connection. Tools are not offered to
+/// the model.
+pub struct Ollama;
+
+impl Ollama {
+    // Address the endpoints are under, without a trailing slash
+    fn base_url(client: &ChatGPTClient) -> &str {
+        client.config.ollama.base_url.trim_end_matches('/')
+    }
+
+    // Errors reaching the server usually mean that Ollama isn't running
+    fn unreachable(client: &ChatGPTClient, err: ChatGptError) -> ChatGptError {
+        match err {
+            ChatGptError::Network(message) => ChatGptError::Network(format!(
+                "Can't reach Ollama at {}, start it with `ollama serve` ({})",
+                Self::base_url(client),
+                message
+            )),
+            err => err,
+        }
+    }
+
+    // The error of a response that didn't succeed, which Ollama gives as a plain string
+    fn error(response: HttpResponse) -> ChatGptError {
+        let status = response.status;
+        let message = response.json().unwrap_or(Value::Null)["error"].take();
+        api_error(status, &json!({"error": {"message": message}}), None)
+    }
+
+    // Send a request to the chat endpoint, answered line by line as it is generated when streaming
+    fn post(
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+        stream: bool,
+    ) -> Result<HttpResponse, ChatGptError> {
+        let sampling = request.sampling;
+        let mut options = json!({});
+        if let Some(temperature) = sampling.temperature {
+            options["temperature"] = json!(temperature);
+        }
+        if let Some(top_p) = sampling.top_p {
+            options["top_p"] = json!(top_p);
+        }
+        if let Some(presence_penalty) = sampling.presence_penalty {
+            options["presence_penalty"] = json!(presence_penalty);
+        }
+        if let Some(frequency_penalty) = sampling.frequency_penalty {
+            options["frequency_penalty"] = json!(frequency_penalty);
+        }
+        if let Some(max_tokens) = sampling.max_response_tokens {
+            options["num_predict"] = json!(max_tokens);
+        }
+        // Tool outputs are passed on as messages of the user
+        let messages: Vec<Value> = request
+            .messages
+            .iter()
+            .map(|message| {
+                let role = match message.role.as_str() {
+                    "system" | "assistant" => message.role.as_str(),
+                    _ => "user",
+                };
+                json!({"role": role, "content": message.content})
+            })
+            .collect();
+        let mut body = json!({
+            "model": client.config.openai_model,
+            "messages": messages,
+            "stream": stream,
+            "options": options,
+        });
+        if let Some(keep_alive) = &client.config.ollama.keep_alive {
+            body["keep_alive"] = json!(keep_alive);
+        }
+        let url = format!("{}/api/chat", Self::base_url(client));
+        let response = client
+            .post_json(url, HeaderMap::new(), &body)
+            .map_err(|err| Self::unreachable(client, err))?;
+        if !response.status.is_success() {
+            return Err(Self::error(response));
+        }
+        Ok(response)
+    }
+}
+
+// An answer in the shape of one from the chat completions API, which the client turns into a chat log entry. The
+// last object Ollama sends has the usage and why the answer ended.
+fn completion(text: &str, last: &Value) -> Value {
+    let usage = match (
+        last["prompt_eval_count"].as_i64(),
+        last["eval_count"].as_i64(),
+    ) {
+        (Some(prompt), Some(completion)) => json!({
+            "prompt_tokens": prompt,
+            "completion_tokens": completion,
+            "total_tokens": prompt + completion,
+        }),
+        _ => Value::Null,
+    };
+    json!({
+        "model": last["model"],
+        "choices": [{
+            "message": {"role": "assistant", "content": text},
+            "finish_reason": last["done_reason"],
+        }],
+        "usage": usage,
+    })
+}
+
+impl ChatBackend for Ollama {
+    fn send(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        let response = Self::post(client, request, false)?;
+        let headers = metadata_headers(&response.headers);
+        let response = response
+            .json()
+            .map_err(|err| ChatGptError::InvalidResponse(err.to_string()))?;
+        let text = response["message"]["content"].as_str().unwrap_or_default();
+        let response = completion(text, &response);
+        client.entry_from_response(request.messages, &response, headers)
+    }
+
+    fn stream(
+        &self,
+        client: &ChatGPTClient,
+        request: &ChatRequest,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<(ChatLogEntry, ResponseMetadata), ChatGptError> {
+        let response = Self::post(client, request, true)?;
+        let headers = metadata_headers(&response.headers);
+        // One JSON object per line with the next piece of the answer, until one says it is done
+        let mut text = String::new();
+        let mut last = Value::Null;
+        for line in BufReader::new(response.body).lines() {
+            if client.is_cancelled() {
+                return Err(ChatGptError::Cancelled);
+            }
+            let line = line.map_err(|err| ChatGptError::Network(err.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let chunk: Value = serde_json::from_str(&line)?;
+            if let Some(message) = chunk["error"].as_str() {
+                return Err(ChatGptError::InvalidResponse(message.to_string()));
+            }
+            if let Some(token) = chunk["message"]["content"].as_str() {
+                on_token(token);
+                text.push_str(token);
+            }
+            let done = chunk["done"].as_bool().unwrap_or(false);
+            last = chunk;
+            if done {
+                break;
+            }
+        }
+        let response = completion(&text, &last);
+        client.entry_from_response(request.messages, &response, headers)
+    }
+
+    fn list_models(&self, client: &ChatGPTClient) -> Result<Vec<String>, ChatGptError> {
+        let response = client
+            .transport
+            .send(HttpRequest {
+                method: Method::GET,
+                url: format!("{}/api/tags", Self::base_url(client)),
+                query: Vec::new(),
+                headers: HeaderMap::new(),
+                body: None,
+            })
+            .map_err(|err| Self::unreachable(client, err))?;
+        if !response.status.is_success() {
+            return Err(Self::error(response));
+        }
+        let response = response
+            .json()
+            .map_err(|err| ChatGptError::InvalidResponse(err.to_string()))?;
+        let mut models: Vec<String> = response["models"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|model| model["name"].as_str())
+            .map(String::from)
+            .collect();
+        models.sort();
+        Ok(models)
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+
+// How long the page is served before it expires
+const EXPIRES_AFTER: Duration = Duration::from_secs(15 * 60);
+// How often the server thread checks for new connections and whether to stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// A browser that doesn't finish sending its request in this time gets no page
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct Page {
+    html: String,
+    stopped: bool,
+}
+
+/// The chat log served as a web page on localhost, at an address with a random path so that other users of the
+/// machine can't guess it. The page expires after a while or when stopped.
+pub struct Presentation {
+    url: String,
+    expires: Instant,
+    expires_at: DateTime<Local>,
+    page: Arc<Mutex<Page>>,
+}
+
+// Random path of the page, from the random keys the standard library seeds its hash maps with
+fn random_token() -> String {
+    (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect()
+}
+
+// Answer one request: the page at its path, and nothing anywhere else
+fn respond(mut stream: TcpStream, path: &str, page: &Mutex<Page>) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(requested)) if requested == path => {
+            ("200 OK", page.lock().unwrap().html.clone())
+        }
+        _ => ("404 Not Found", String::from("Not found\n")),
+    };
+    let content_type = if status == "200 OK" {
+        "text/html; charset=utf-8"
+    } else {
+        "text/plain; charset=utf-8"
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+impl Presentation {
+    /// Start serving a web page on a random port of localhost in the background
+    pub fn start(html: String) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        listener.set_nonblocking(true)?;
+        let path = format!("/{}", random_token());
+        let url = format!("http://{}{}", listener.local_addr()?, path);
+        let expires = Instant::now() + EXPIRES_AFTER;
+        let page = Arc::new(Mutex::new(Page {
+            html,
+            stopped: false,
+        }));
+
+        let shared = page.clone();
+        thread::spawn(move || loop {
+            if shared.lock().unwrap().stopped || Instant::now() >= expires {
+                return;
+            }
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    // A browser that goes away halfway only loses its own page
+                    respond(stream, &path, &shared).ok();
+                }
+                // Nobody is connecting yet
+                Err(_) => thread::sleep(POLL_INTERVAL),
+            }
+        });
+        Ok(Self {
+            url,
+            expires,
+            expires_at: Local::now() + chrono::Duration::from_std(EXPIRES_AFTER).unwrap(),
+            page,
+        })
+    }
+
+    /// Address of the page
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// When the page expires
+    pub fn expires_at(&self) -> DateTime<Local> {
+        self.expires_at
+    }
+
+    /// Serve another version of the page at the same address
+    pub fn set_page(&self, html: String) {
+        self.page.lock().unwrap().html = html;
+    }
+
+    /// Stop serving the page
+    pub fn stop(&self) {
+        self.page.lock().unwrap().stopped = true;
+    }
+
+    /// Whether the page expired or was stopped
+    pub fn is_finished(&self) -> bool {
+        self.page.lock().unwrap().stopped || Instant::now() >= self.expires
+    }
+}
+
+impl Drop for Presentation {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
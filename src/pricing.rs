@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Price of a model in US dollars per 1000 tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt: f64,
+    pub completion: f64,
+}
+
+// Prices shipped with the application. These can be overridden or extended with `model_prices` in the config.
+const BUILTIN_PRICES: [(&str, ModelPrice); 6] = [
+    (
+        "gpt-3.5-turbo",
+        ModelPrice {
+            prompt: 0.0015,
+            completion: 0.002,
+        },
+    ),
+    (
+        "gpt-3.5-turbo-16k",
+        ModelPrice {
+            prompt: 0.003,
+            completion: 0.004,
+        },
+    ),
+    (
+        "gpt-4",
+        ModelPrice {
+            prompt: 0.03,
+            completion: 0.06,
+        },
+    ),
+    (
+        "gpt-4-32k",
+        ModelPrice {
+            prompt: 0.06,
+            completion: 0.12,
+        },
+    ),
+    (
+        "gpt-4-turbo",
+        ModelPrice {
+            prompt: 0.01,
+            completion: 0.03,
+        },
+    ),
+    (
+        "gpt-4o",
+        ModelPrice {
+            prompt: 0.005,
+            completion: 0.015,
+        },
+    ),
+];
+
+/// Table of model prices used to estimate the cost of requests
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    /// Create a price table from the built-in prices, with prices from the config taking precedence
+    pub fn new(overrides: &HashMap<String, ModelPrice>) -> Self {
+        let mut prices: HashMap<String, ModelPrice> = BUILTIN_PRICES
+            .iter()
+            .map(|(model, price)| (model.to_string(), *price))
+            .collect();
+        prices.extend(
+            overrides
+                .iter()
+                .map(|(model, price)| (model.clone(), *price)),
+        );
+        Self { prices }
+    }
+
+    /// Price of a model. Dated model snapshots such as `gpt-4-0613` use the price of the longest matching model name.
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        if let Some(price) = self.prices.get(model) {
+            return Some(*price);
+        }
+        self.prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(&format!("{}-", name)))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Estimated cost in US dollars of a request to a model, if its price is known
+    pub fn cost(&self, model: &str, prompt_tokens: u32, completion_tokens: u32) -> Option<f64> {
+        let price = self.price(model)?;
+        Some(
+            (price.prompt * prompt_tokens as f64 + price.completion * completion_tokens as f64)
+                / 1000.0,
+        )
+    }
+}
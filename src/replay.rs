@@ -0,0 +1,63 @@
+use std::fmt::Write;
+
+use crate::api::{ChatGPTClient, SessionFile};
+use crate::diff::{diff_lines, DiffLine};
+
+/// Send the user messages of a saved session again, in order, and report how the new answers differ from the
+/// original ones
+pub fn replay(
+    client: ChatGPTClient,
+    file: SessionFile,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let model = client.config.openai_model.clone();
+    let mut session = client.new_session(Vec::new(), 2000);
+    // Use the initial prompt the session was recorded with, if it is known
+    if let Some(sent) = file.entries.first().and_then(|entry| entry.sent.as_ref()) {
+        session.set_initial_prompt(sent.initial_prompt.trim_end());
+    }
+
+    let mut report = String::new();
+    let mut num_changed = 0;
+    for (index, original) in file.entries.iter().enumerate() {
+        let replayed = session.send_message(&original.message)?;
+        writeln!(
+            report,
+            "## Exchange {} of {}",
+            index + 1,
+            file.entries.len()
+        )?;
+        writeln!(report)?;
+        for line in original.message.lines() {
+            writeln!(report, "> {}", line)?;
+        }
+        writeln!(report)?;
+        if replayed.response == original.response {
+            writeln!(report, "Answer unchanged")?;
+        } else {
+            num_changed += 1;
+            writeln!(
+                report,
+                "Answer changed ({} -> {} tokens):",
+                original.num_tokens_response, replayed.num_tokens_response
+            )?;
+            writeln!(report, "```diff")?;
+            for line in diff_lines(&original.response, &replayed.response) {
+                match line {
+                    DiffLine::Same(line) => writeln!(report, "  {}", line)?,
+                    DiffLine::Removed(line) => writeln!(report, "- {}", line)?,
+                    DiffLine::Added(line) => writeln!(report, "+ {}", line)?,
+                }
+            }
+            writeln!(report, "```")?;
+        }
+        writeln!(report)?;
+    }
+    writeln!(
+        report,
+        "{} of {} answers changed when replayed with {}",
+        num_changed,
+        file.entries.len(),
+        model
+    )?;
+    Ok(report)
+}
@@ -0,0 +1,78 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+/// How requests failing with rate limits, server errors or network errors are tried again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    // Attempts in all, including the first one. 1 never tries again.
+    pub max_attempts: u32,
+    // Wait before the first retry, doubled for each one after it
+    pub initial_backoff_secs: f64,
+    // Longest wait between attempts. Servers asking to wait longer get their error reported instead.
+    pub max_backoff_secs: f64,
+    // Part of each wait, from 0 to 1, added or taken away at random so that clients failing at the same time don't
+    // all try again at once
+    pub jitter: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_secs: 1.0,
+            max_backoff_secs: 30.0,
+            jitter: 0.0,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// How long to wait after the given attempt failed, counting from 1, unless the server said how long. None if
+    /// that is longer than the longest wait.
+    pub fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        // A longest wait too long to be a duration is no limit at all
+        let max =
+            Duration::try_from_secs_f64(self.max_backoff_secs.max(0.0)).unwrap_or(Duration::MAX);
+        match retry_after {
+            Some(wait) => (wait <= max).then_some(wait),
+            None => {
+                let wait = self.initial_backoff_secs.max(0.0) * 2f64.powi(attempt as i32 - 1);
+                let jitter = self.jitter.clamp(0.0, 1.0) * (2.0 * random_fraction() - 1.0);
+                // Capped while still in seconds, as the wait after many attempts is too long to be a duration
+                let wait = (wait * (1.0 + jitter)).min(max.as_secs_f64());
+                Some(Duration::try_from_secs_f64(wait).unwrap_or(max))
+            }
+        }
+    }
+}
+
+// A number from 0 to 1, from the random keys the standard library seeds its hash maps with
+fn random_fraction() -> f64 {
+    RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64
+}
+
+/// A failed request that is about to be tried again
+#[derive(Debug, Clone)]
+pub struct Retry {
+    // The attempt that failed, counting from 1
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub wait: Duration,
+    pub reason: String,
+}
+
+/// How long the server asked to wait before trying again, from `retry-after-ms` or `retry-after` in seconds
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header =
+        |name: &str| -> Option<f64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+    header("retry-after-ms")
+        .map(|ms| ms / 1000.0)
+        .or_else(|| header("retry-after"))
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
@@ -0,0 +1,99 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// Names of the parameters that can be changed with /set
+pub const PARAMETERS: &[&str] = &[
+    "temperature",
+    "top_p",
+    "presence_penalty",
+    "frequency_penalty",
+    "max_response_tokens",
+];
+
+/// How the model picks the tokens of its answers. Parameters that are not set are left to the API's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingParams {
+    // Randomness of the answers, from 0 to 2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    // Only tokens making up this share of the probability are considered, from 0 to 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    // Penalties for tokens that already appeared at all, or by how often they appeared, from -2 to 2
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    // Longest answer, in tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_tokens: Option<u32>,
+}
+
+impl SamplingParams {
+    /// Set a parameter from its name and a value typed by the user, or unset it if the value is "default"
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let unset = value == "default";
+        let number = |min: f32, max: f32| -> Result<Option<f32>, String> {
+            if unset {
+                return Ok(None);
+            }
+            match value.parse::<f32>() {
+                Ok(number) if (min..=max).contains(&number) => Ok(Some(number)),
+                _ => Err(format!("{} must be a number from {} to {}", name, min, max)),
+            }
+        };
+        match name {
+            "temperature" => self.temperature = number(0.0, 2.0)?,
+            "top_p" => self.top_p = number(0.0, 1.0)?,
+            "presence_penalty" => self.presence_penalty = number(-2.0, 2.0)?,
+            "frequency_penalty" => self.frequency_penalty = number(-2.0, 2.0)?,
+            "max_response_tokens" => {
+                self.max_response_tokens = match value.parse::<u32>() {
+                    _ if unset => None,
+                    Ok(tokens) if tokens > 0 => Some(tokens),
+                    _ => return Err(format!("{} must be a positive whole number", name)),
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown parameter {}, use one of {}",
+                    name,
+                    PARAMETERS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The parameters that are set, e.g. `temperature 0.2, top_p 0.9`
+impl Display for SamplingParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = [
+            (
+                "temperature",
+                self.temperature.map(|value| value.to_string()),
+            ),
+            ("top_p", self.top_p.map(|value| value.to_string())),
+            (
+                "presence_penalty",
+                self.presence_penalty.map(|value| value.to_string()),
+            ),
+            (
+                "frequency_penalty",
+                self.frequency_penalty.map(|value| value.to_string()),
+            ),
+            (
+                "max_response_tokens",
+                self.max_response_tokens.map(|value| value.to_string()),
+            ),
+        ];
+        let set: Vec<String> = values
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{} {}", name, value?)))
+            .collect();
+        write!(f, "{}", set.join(", "))
+    }
+}
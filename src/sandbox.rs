@@ -0,0 +1,197 @@
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+// How often a running tool is checked on for having exited or run out of time
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Restrictions applied to the commands run as tools
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxPolicy {
+    // Run tools in a new empty directory, with only PATH kept from the environment and HOME and TMPDIR pointing to
+    // the directory. The directory is removed afterwards.
+    pub temp_dir: bool,
+    // Without network access, tools are run in a new network namespace with `unshare`, which needs Linux
+    pub allow_network: bool,
+    // Tools still running after this many seconds are killed
+    pub timeout_secs: u64,
+    // Output beyond this many bytes is dropped before it is sent to the model
+    pub max_output_bytes: usize,
+}
+
+impl Default for SandboxPolicy {
+    fn default() -> Self {
+        Self {
+            temp_dir: true,
+            allow_network: false,
+            timeout_secs: 30,
+            max_output_bytes: 16 * 1024,
+        }
+    }
+}
+
+// Fresh directory for a single run of a tool
+fn create_temp_dir() -> io::Result<PathBuf> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!(
+        "chatgpt-term-tool-{}-{}",
+        std::process::id(),
+        nanos
+    ));
+    std::fs::create_dir(&dir)?;
+    Ok(dir)
+}
+
+// Read up to `max` bytes from a pipe in the background. The rest is read and dropped so that the command doesn't
+// block on a full pipe. Also returns whether anything was dropped.
+fn read_capped(
+    mut pipe: impl Read + Send + 'static,
+    max: usize,
+) -> thread::JoinHandle<(Vec<u8>, bool)> {
+    thread::spawn(move || {
+        let mut kept = Vec::new();
+        (&mut pipe).take(max as u64).read_to_end(&mut kept).ok();
+        let dropped = io::copy(&mut pipe, &mut io::sink()).unwrap_or(0);
+        (kept, dropped > 0)
+    })
+}
+
+// Kill the processes started by a command, which could otherwise keep its output open after it exits
+fn kill_group(child: &Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as i32), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = child;
+}
+
+// Wait for a command to exit, killing it once it runs out of time. None if it was killed.
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> io::Result<Option<std::process::ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            kill_group(child);
+            child.kill().ok();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Build the command to run with the restrictions of the policy, split on whitespace like other commands
+fn build_command(
+    command: &str,
+    policy: &SandboxPolicy,
+    dir: Option<&PathBuf>,
+) -> Result<Command, Box<dyn std::error::Error>> {
+    let mut args = command.split_whitespace();
+    let program = args.next().ok_or("Empty tool command")?;
+    let mut cmd = if policy.allow_network {
+        Command::new(program)
+    } else {
+        // Map the user to root in a new user namespace, which allows creating a network namespace without privileges
+        let mut cmd = Command::new("unshare");
+        cmd.args(["--net", "--map-root-user", "--", program]);
+        cmd
+    };
+    cmd.args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = dir {
+        cmd.current_dir(dir)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", dir)
+            .env("TMPDIR", dir);
+    }
+    // A group of its own lets the command be killed along with everything it started
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+    Ok(cmd)
+}
+
+// Run the command in a directory if given, returning what it printed and how it ended
+fn run_in(
+    command: &str,
+    input: &str,
+    policy: &SandboxPolicy,
+    dir: Option<&PathBuf>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut child = build_command(command, policy, dir)?
+        .spawn()
+        .map_err(|err| {
+            if policy.allow_network {
+                err.to_string()
+            } else {
+                format!(
+                    "{}. Tools are run without network access using unshare, set allow_network in [tool_sandbox] \
+                    if it is not available",
+                    err
+                )
+            }
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let stdout = read_capped(child.stdout.take().unwrap(), policy.max_output_bytes);
+    let stderr = read_capped(child.stderr.take().unwrap(), policy.max_output_bytes);
+    let status = wait_with_timeout(&mut child, Duration::from_secs(policy.timeout_secs))?;
+    kill_group(&child);
+    // A command may exit without reading all of its input, which is not an error
+    writer.join().ok();
+    let (mut output, mut truncated) = stdout.join().unwrap_or_default();
+    let (errors, errors_truncated) = stderr.join().unwrap_or_default();
+    output.extend(errors);
+    truncated |= errors_truncated || output.len() > policy.max_output_bytes;
+    output.truncate(policy.max_output_bytes);
+
+    let mut text = String::from_utf8_lossy(&output).into_owned();
+    if truncated {
+        text.push_str(&format!(
+            "\n(output truncated to {} bytes)",
+            policy.max_output_bytes
+        ));
+    }
+    match status {
+        None => text.push_str(&format!(
+            "\n(killed after running for {} seconds)",
+            policy.timeout_secs
+        )),
+        Some(status) if !status.success() => text.push_str(&format!("\n(exited with {})", status)),
+        Some(_) => {}
+    }
+    Ok(text)
+}
+
+/// Run a command with the input on stdin under the restrictions of the policy, returning what it printed on stdout
+/// and stderr. Running out of time or output is reported at the end of the output.
+pub fn run_sandboxed(
+    command: &str,
+    input: &str,
+    policy: &SandboxPolicy,
+) -> Result<String, Box<dyn std::error::Error>> {
+    if !policy.temp_dir {
+        return run_in(command, input, policy, None);
+    }
+    let dir = create_temp_dir()?;
+    let result = run_in(command, input, policy, Some(&dir));
+    std::fs::remove_dir_all(&dir).ok();
+    result
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::network::NetworkConfig;
+use crate::tools::{Tool, ToolCall};
+
+/// Name of the built-in tool the model calls to search the web
+pub const WEB_SEARCH_TOOL: &str = "web_search";
+
+// Searches taking longer than this are given up on
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+const BRAVE_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+const BING_URL: &str = "https://api.bing.microsoft.com/v7.0/search";
+
+fn default_num_results() -> usize {
+    5
+}
+
+/// Search engines the web search tool can use
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchProvider {
+    // A SearxNG instance with the JSON format enabled, at the configured url
+    Searxng,
+    Brave,
+    Bing,
+}
+
+/// Settings of the built-in web search tool, which is only offered to the model when they are in the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSearchConfig {
+    pub provider: SearchProvider,
+    // Address of the SearxNG instance, or of the API of the other providers if it is not the default one
+    pub url: Option<String>,
+    // Key of the Brave or Bing API
+    pub api_key: Option<String>,
+    #[serde(default = "default_num_results")]
+    pub num_results: usize,
+    // Search without asking first. Searching sends the query chosen by the model to the provider.
+    #[serde(default)]
+    pub auto_approve: bool,
+}
+
+/// A search result as given to the model
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+impl WebSearchConfig {
+    /// The tool offered to the model
+    pub fn tool(&self) -> Tool {
+        Tool {
+            name: WEB_SEARCH_TOOL.to_string(),
+            description: String::from(
+                "Search the web. Returns numbered results with their title, URL and a snippet. Summarize what the \
+                results say rather than quoting them, cite the results you use by their number, e.g. [1], and list \
+                their URLs at the end of the answer.",
+            ),
+            command: String::new(),
+            auto_approve: self.auto_approve,
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "What to search for"}
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    fn api_key(&self) -> Result<&str, Box<dyn std::error::Error>> {
+        self.api_key
+            .as_deref()
+            .ok_or_else(|| format!("set api_key in [web_search] to use {:?}", self.provider).into())
+    }
+
+    /// Search for the query, returning the results along with the raw response of the provider
+    pub fn search(
+        &self,
+        query: &str,
+        network: &NetworkConfig,
+    ) -> Result<(Vec<SearchResult>, Value), Box<dyn std::error::Error>> {
+        let client = network.client_builder()?.timeout(SEARCH_TIMEOUT).build()?;
+        let count = self.num_results.to_string();
+        let request = match self.provider {
+            SearchProvider::Searxng => {
+                let url = self
+                    .url
+                    .as_deref()
+                    .ok_or("set url in [web_search] to the address of the SearxNG instance")?;
+                client
+                    .get(format!("{}/search", url.trim_end_matches('/')))
+                    .query(&[("q", query), ("format", "json")])
+            }
+            SearchProvider::Brave => client
+                .get(self.url.as_deref().unwrap_or(BRAVE_URL))
+                .query(&[("q", query), ("count", &count)])
+                .header("X-Subscription-Token", self.api_key()?),
+            SearchProvider::Bing => client
+                .get(self.url.as_deref().unwrap_or(BING_URL))
+                .query(&[("q", query), ("count", &count)])
+                .header("Ocp-Apim-Subscription-Key", self.api_key()?),
+        };
+        let response: Value = request.send()?.error_for_status()?.json()?;
+        // Where each provider puts the results, and what it calls the fields of a result
+        let (results, title, snippet) = match self.provider {
+            SearchProvider::Searxng => (&response["results"], "title", "content"),
+            SearchProvider::Brave => (&response["web"]["results"], "title", "description"),
+            SearchProvider::Bing => (&response["webPages"]["value"], "name", "snippet"),
+        };
+        let field =
+            |result: &Value, name: &str| result[name].as_str().unwrap_or_default().to_string();
+        let results = results
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .take(self.num_results)
+            .map(|result| SearchResult {
+                title: field(result, title),
+                url: field(result, "url"),
+                snippet: field(result, snippet),
+            })
+            .collect();
+        Ok((results, response))
+    }
+}
+
+/// Numbered list of results to send to the model, so that it can cite them by number
+pub fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results found for \"{}\"", query);
+    }
+    results
+        .iter()
+        .enumerate()
+        .map(|(index, result)| {
+            format!(
+                "[{}] {}\n{}\n{}",
+                index + 1,
+                result.title,
+                result.url,
+                result.snippet
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// The results in the output of a search, as written by format_results
+fn parse_results(output: &str) -> Vec<SearchResult> {
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    for line in output.lines() {
+        let next = format!("[{}] ", blocks.len() + 1);
+        if line.starts_with(&next) {
+            blocks.push(vec![&line[next.len()..]]);
+        } else if let Some(block) = blocks.last_mut() {
+            block.push(line);
+        }
+    }
+    blocks
+        .into_iter()
+        .map(|block| SearchResult {
+            title: block[0].to_string(),
+            url: block.get(1).unwrap_or(&"").to_string(),
+            snippet: block
+                .get(2..)
+                .unwrap_or_default()
+                .join("\n")
+                .trim_end()
+                .to_string(),
+        })
+        .collect()
+}
+
+// Numbers cited in a text as [1] or [1, 2]. Other text in brackets, such as the text of links, is left out.
+fn cited_numbers(text: &str) -> BTreeSet<usize> {
+    let mut numbers = BTreeSet::new();
+    for part in text.split('[').skip(1) {
+        let Some((inside, _)) = part.split_once(']') else {
+            continue;
+        };
+        let cited: Option<Vec<usize>> = inside
+            .split(',')
+            .map(|number| number.trim().parse().ok())
+            .collect();
+        numbers.extend(cited.unwrap_or_default());
+    }
+    numbers
+}
+
+/// The search results an answer cites by number, in order, as footnotes. Each search numbers its results from 1, so
+/// a number is taken to cite the result of the last search that had that many.
+pub fn cited_results(answer: &str, tool_calls: &[ToolCall]) -> Vec<(usize, SearchResult)> {
+    let searches: Vec<Vec<SearchResult>> = tool_calls
+        .iter()
+        .filter(|call| call.name == WEB_SEARCH_TOOL)
+        .map(|call| parse_results(&call.output))
+        .collect();
+    cited_numbers(answer)
+        .into_iter()
+        .filter_map(|number| {
+            let result = searches
+                .iter()
+                .rev()
+                .find_map(|results| results.get(number.checked_sub(1)?))?;
+            Some((number, result.clone()))
+        })
+        .collect()
+}
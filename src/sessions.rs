@@ -0,0 +1,248 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::api::{
+    backup_path, checksum_path, verify_chatlog, ChatGPTSession, ChatlogIntegrity, SessionFile,
+};
+use crate::ChatTermConfig;
+
+/// Summary of a saved session file, used to list sessions
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub path: String,
+    pub modified: SystemTime,
+    pub favorite: bool,
+    pub num_entries: usize,
+    pub first_message: Option<String>,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub persona: Option<String>,
+}
+
+impl Display for SessionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let modified: chrono::DateTime<chrono::Local> = self.modified.into();
+        write!(
+            f,
+            "{} {}  {} ({} messages)",
+            if self.favorite { "*" } else { " " },
+            modified.format("%Y-%m-%d %H:%M"),
+            // The sessions are all in the same directory, so only their names tell them apart
+            Path::new(&self.path)
+                .file_name()
+                .map_or(self.path.as_str().into(), |name| name.to_string_lossy()),
+            self.num_entries
+        )?;
+        if let Some(title) = &self.title {
+            write!(f, ": {}", title)?;
+        } else if let Some(message) = &self.first_message {
+            write!(f, ": {}", message)?;
+        }
+        for tag in self.tags.iter() {
+            write!(f, " #{}", tag)?;
+        }
+        Ok(())
+    }
+}
+
+// Whether a file may be a session, judging by its name. Sessions saved under a name of their own are listed too,
+// so whether it really is one is only known once it is read.
+fn is_session_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+// Whether a file in the directory older versions saved sessions to is one of them. Only the names those versions
+// gave sessions are looked at, since the directory holds other files too.
+fn is_legacy_session_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("chatlog_") && name.ends_with(".json"))
+}
+
+// Whether two paths point to the same existing file
+fn same_file(a: &str, b: &str) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// Remove a file, which is fine if it doesn't exist
+fn remove_if_exists(path: &str) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// Overwrite a file with zeros and flush it to the disk before removing it, so that its contents can't be read back
+// from the disk after it is deleted. Fine if it doesn't exist.
+fn wipe_if_exists(path: &str) -> io::Result<()> {
+    let mut file = match OpenOptions::new().write(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    let zeros = [0u8; 8192];
+    let mut remaining = file.metadata()?.len();
+    while remaining > 0 {
+        let len = remaining.min(zeros.len() as u64) as usize;
+        file.write_all(&zeros[..len])?;
+        remaining -= len as u64;
+    }
+    file.sync_all()?;
+    drop(file);
+    remove_if_exists(path)
+}
+
+/// Finds, opens and deletes the sessions saved in a directory
+pub struct SessionManager {
+    dir: PathBuf,
+    // Directory older versions saved sessions to, whose sessions are listed along with the others
+    legacy_dir: Option<PathBuf>,
+}
+
+impl SessionManager {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            legacy_dir: None,
+        }
+    }
+
+    /// The sessions in the directory of the config. Unless the config sets one, those that older versions saved to
+    /// the current directory are listed too, so that they don't go missing after upgrading.
+    pub fn from_config(config: &ChatTermConfig) -> Self {
+        let dir = config.sessions_dir();
+        let separate = config.sessions_dir.is_none() && !same_file(".", &dir.to_string_lossy());
+        Self {
+            dir,
+            legacy_dir: separate.then(|| PathBuf::from(".")),
+        }
+    }
+
+    /// Saved sessions, favorites first and then most recently modified first
+    pub fn list(&self) -> io::Result<Vec<SessionSummary>> {
+        let mut paths = Vec::new();
+        let dirs = [(&self.dir, false)]
+            .into_iter()
+            .chain(self.legacy_dir.iter().map(|dir| (dir, true)));
+        for (dir, legacy) in dirs {
+            // Nothing was saved yet if the directory doesn't exist
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for entry in entries {
+                let path = entry?.path();
+                if is_session_file(&path) && (!legacy || is_legacy_session_file(&path)) {
+                    paths.push(path);
+                }
+            }
+        }
+        let mut sessions = Vec::new();
+        for path in paths {
+            let path = path.to_string_lossy().to_string();
+            // Skip files that are not sessions or can no longer be read as one
+            let file = match SessionFile::load(&path) {
+                Ok(file) => file,
+                Err(_) => continue,
+            };
+            sessions.push(SessionSummary {
+                modified: std::fs::metadata(&path)?.modified()?,
+                favorite: file.metadata.favorite,
+                num_entries: file.entries.len(),
+                first_message: file.entries.first().map(|entry| entry.message.clone()),
+                title: file.metadata.title,
+                tags: file.metadata.tags,
+                persona: file.metadata.persona,
+                path,
+            });
+        }
+        sessions.sort_by_key(|session| (!session.favorite, std::cmp::Reverse(session.modified)));
+        Ok(sessions)
+    }
+
+    /// Mark or unmark a saved session as a favorite. It keeps its place among the recently modified sessions.
+    pub fn set_favorite(&self, path: &str, favorite: bool) -> Result<(), Box<dyn Error>> {
+        let modified = std::fs::metadata(path)?.modified()?;
+        let mut file = SessionFile::load(path)?;
+        file.metadata.favorite = favorite;
+        file.save(path)?;
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_modified(modified)?;
+        Ok(())
+    }
+
+    /// Read a session file, falling back to its most recent intact backup if it is corrupted. Also returns whether
+    /// the backup was used.
+    pub fn load(&self, path: &str) -> Result<(SessionFile, bool), Box<dyn Error>> {
+        let backup = backup_path(path);
+        if verify_chatlog(path)? == ChatlogIntegrity::Corrupted
+            && Path::new(&backup).exists()
+            && verify_chatlog(&backup)? == ChatlogIntegrity::Intact
+        {
+            return Ok((SessionFile::load(&backup)?, true));
+        }
+        Ok((SessionFile::load(path)?, false))
+    }
+
+    // Save the current session if it has any exchanges, so that moving on to another one doesn't lose it
+    fn save_current(&self, session: &ChatGPTSession) -> io::Result<()> {
+        if !session.get_chatlog().is_empty() {
+            session.save_chatlog()?;
+        }
+        Ok(())
+    }
+
+    /// Save the current session and continue with a saved one, which is then saved back to the same file. Returns
+    /// whether its backup was loaded instead. If the profile of the saved session can't be used, it is switched to
+    /// all the same with the main settings, and the error says so.
+    pub fn switch(&self, session: &mut ChatGPTSession, path: &str) -> Result<bool, Box<dyn Error>> {
+        self.save_current(session)?;
+        let (file, from_backup) = self.load(path)?;
+        let restored = session.restore(file);
+        session.set_name(path);
+        restored?;
+        Ok(from_backup)
+    }
+
+    /// Save the current session and start a new one
+    pub fn create(&self, session: &mut ChatGPTSession) -> io::Result<()> {
+        self.save_current(session)?;
+        session.reset();
+        Ok(())
+    }
+
+    /// Delete a saved session along with its checksum and backup, overwriting them first if `wipe` is set. The session
+    /// currently open can't be deleted.
+    pub fn delete(
+        &self,
+        session: &ChatGPTSession,
+        path: &str,
+        wipe: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        if same_file(&session.file_name(), path) {
+            return Err(
+                "Can't delete the session that is open, switch to another one first".into(),
+            );
+        }
+        let backup = backup_path(path);
+        for file in [path, &checksum_path(path), &backup, &checksum_path(&backup)] {
+            if wipe {
+                wipe_if_exists(file)?;
+            } else {
+                remove_if_exists(file)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use crate::fence::detect_language;
+use crate::markdown::FencedBlock;
+
+// File extensions for the languages code fences are tagged with
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rust", "rs"),
+    ("python", "py"),
+    ("javascript", "js"),
+    ("typescript", "ts"),
+    ("java", "java"),
+    ("c", "c"),
+    ("cpp", "cpp"),
+    ("c++", "cpp"),
+    ("go", "go"),
+    ("ruby", "rb"),
+    ("bash", "sh"),
+    ("shell", "sh"),
+    ("zsh", "sh"),
+    ("sql", "sql"),
+    ("html", "html"),
+    ("css", "css"),
+    ("json", "json"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("toml", "toml"),
+    ("markdown", "md"),
+];
+
+// Characters around a file name in text that are not part of it, e.g. in `main.rs` or (see src/lib.rs)
+const NAME_DELIMITERS: &[char] = &[
+    '`', '\'', '"', '(', ')', '[', ']', '{', '}', '<', '>', ',', ';', ':', '*',
+];
+
+// Ways of starting and ending a comment on the first line of a block, as in `// main.rs` or `<!-- index.html -->`
+const COMMENT_PREFIXES: &[&str] = &["<!--", "//", "/*", "#", "--", ";"];
+const COMMENT_SUFFIXES: &[&str] = &["-->", "*/"];
+
+// The extension of files in a language, which can also be given as the extension itself, e.g. `rs`
+fn extension(language: &str) -> Option<&'static str> {
+    let language = language.to_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(name, extension)| *name == language || *extension == language)
+        .map(|(_, extension)| *extension)
+}
+
+// The word as a file name if it looks like one, e.g. `main.rs` or `src/lib.rs`. Extensions of one letter have to be
+// known ones, so that abbreviations such as e.g. are left out.
+fn file_name(word: &str) -> Option<&str> {
+    let word = word
+        .trim_end_matches(['.', '!', '?'])
+        .trim_matches(NAME_DELIMITERS);
+    let (name, ext) = word.rsplit_once('.')?;
+    let valid = !name.is_empty()
+        && !name.ends_with('/')
+        && !word.contains("://")
+        && ext.chars().all(|c| c.is_ascii_alphanumeric())
+        && !ext.chars().all(|c| c.is_ascii_digit())
+        && (ext.len() > 1 || EXTENSIONS.iter().any(|(_, known)| *known == ext));
+    valid.then_some(word)
+}
+
+// A file named in a comment on the first line of the code, e.g. `# file: app.py`
+fn commented_name(code: &str) -> Option<&str> {
+    let line = code.lines().next()?.trim();
+    let prefix = COMMENT_PREFIXES
+        .iter()
+        .find(|prefix| line.starts_with(*prefix))?;
+    let mut comment = &line[prefix.len()..];
+    for suffix in COMMENT_SUFFIXES {
+        comment = comment.strip_suffix(suffix).unwrap_or(comment);
+    }
+    let comment = comment.trim();
+    let comment = ["file:", "filename:", "File:", "Filename:"]
+        .iter()
+        .find_map(|label| comment.strip_prefix(label))
+        .unwrap_or(comment)
+        .trim();
+    if comment.contains(char::is_whitespace) {
+        return None;
+    }
+    file_name(comment)
+}
+
+// A file named in the text before the code. Names that are quoted or have a directory are more likely to be files
+// than words such as Node.js, so the first of those is preferred.
+fn mentioned_name(intro: &str) -> Option<&str> {
+    let names: Vec<(bool, &str)> = intro
+        .split_whitespace()
+        .filter_map(|word| {
+            let name = file_name(word)?;
+            Some((word.contains('`') || name.contains('/'), name))
+        })
+        .collect();
+    names
+        .iter()
+        .find(|(likely, _)| *likely)
+        .or_else(|| names.first())
+        .map(|(_, name)| *name)
+}
+
+/// A path to save a code block to: a file named in a comment at its top or in the text before it, or else a name
+/// with the extension of its language, e.g. `snippet.py`
+pub fn suggest_path(block: &FencedBlock) -> String {
+    if let Some(name) = commented_name(&block.code).or_else(|| mentioned_name(&block.intro)) {
+        return name.to_string();
+    }
+    let language = if block.language.is_empty() {
+        detect_language(&block.code).unwrap_or_default()
+    } else {
+        block.language.split_whitespace().next().unwrap_or_default()
+    };
+    format!("snippet.{}", extension(language).unwrap_or("txt"))
+}
+
+/// Write a code block to a file, creating its directory if needed
+pub fn save_code(path: &str, code: &str) -> std::io::Result<()> {
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+    let mut text = code.to_string();
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    std::fs::write(path, text)
+}
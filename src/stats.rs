@@ -0,0 +1,40 @@
+use crate::locale::Locale;
+
+// Average silent reading speed of adults, in words per minute
+const READING_WORDS_PER_MINUTE: usize = 230;
+
+/// Length of a text and the estimated time it takes to read it
+#[derive(Debug, Clone, Copy)]
+pub struct TextStats {
+    pub words: usize,
+    pub characters: usize,
+}
+
+impl TextStats {
+    pub fn of(text: &str) -> Self {
+        Self {
+            words: text.split_whitespace().count(),
+            characters: text.chars().count(),
+        }
+    }
+
+    /// Estimated reading time in whole minutes, rounded up
+    pub fn reading_minutes(&self) -> usize {
+        self.words.div_ceil(READING_WORDS_PER_MINUTE)
+    }
+
+    /// The counts and reading time with numbers written for the locale, e.g. `1,204 words, 7,310 characters, about
+    /// 6 min to read`
+    pub fn describe(&self, locale: &Locale) -> String {
+        let mut description = format!(
+            "{} words, {} characters",
+            locale.number(self.words as u64),
+            locale.number(self.characters as u64)
+        );
+        // Reading time is only worth mentioning for long texts
+        if self.words > READING_WORDS_PER_MINUTE {
+            description.push_str(&format!(", about {} min to read", self.reading_minutes()));
+        }
+        description
+    }
+}
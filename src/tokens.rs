@@ -0,0 +1,57 @@
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::{
+    cl100k_base_singleton, o200k_base_singleton, p50k_base_singleton, p50k_edit_singleton,
+    r50k_base_singleton,
+};
+
+// Tokens taken by the role and separators of each message in a chat request, as described in OpenAI's guide to
+// counting tokens
+const TOKENS_PER_MESSAGE: u32 = 4;
+
+// Context windows of models, matched by the start of their name with the more specific names first. The windows of
+// other models, including those of the Anthropic and Ollama backends, aren't known, so their limit is trusted.
+const CONTEXT_WINDOWS: &[(&str, u32)] = &[
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-0125", 128_000),
+    ("gpt-4-1106", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4-0613", 8192),
+    ("gpt-4-0314", 8192),
+    ("gpt-3.5-turbo", 16_385),
+];
+
+/// Tokens every answer is primed with, on top of the messages of the request
+pub const TOKENS_PER_REQUEST: u32 = 3;
+
+/// Number of tokens in a text as counted by the tokenizer of the model. Models the tokenizer library doesn't know
+/// are assumed to use the tokenizer of the recent chat models.
+pub fn count_tokens(model: &str, text: &str) -> u32 {
+    // The tokenizers are loaded once and shared, since loading one takes a while
+    let bpe = match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => o200k_base_singleton(),
+        Some(Tokenizer::P50kBase) => p50k_base_singleton(),
+        Some(Tokenizer::P50kEdit) => p50k_edit_singleton(),
+        Some(Tokenizer::R50kBase | Tokenizer::Gpt2) => r50k_base_singleton(),
+        Some(Tokenizer::Cl100kBase) | None => cl100k_base_singleton(),
+    };
+    let bpe = bpe.lock();
+    bpe.encode_ordinary(text).len() as u32
+}
+
+/// Number of tokens a message takes in the context of a chat request
+pub fn count_message_tokens(model: &str, content: &str) -> u32 {
+    TOKENS_PER_MESSAGE + count_tokens(model, content)
+}
+
+/// Number of tokens the model can take in a request and its answer together, if known
+pub fn context_size(model: &str) -> Option<u32> {
+    if model == "gpt-4" {
+        return Some(8192);
+    }
+    CONTEXT_WINDOWS
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|&(_, size)| size)
+}
@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::calculator::{evaluate, format_number, CALCULATOR_TOOL};
+use crate::files::{LIST_DIR_TOOL, READ_FILE_TOOL};
+use crate::sandbox::run_sandboxed;
+use crate::search::{format_results, WEB_SEARCH_TOOL};
+use crate::ChatTermConfig;
+
+/// Most tool calls the model can make to answer one message, unless set in the config
+pub const DEFAULT_MAX_TOOL_ITERATIONS: u32 = 5;
+
+fn default_parameters() -> Value {
+    json!({"type": "object", "properties": {}})
+}
+
+/// A command the model can ask to run. It gets the arguments chosen by the model as JSON on stdin, and what it
+/// prints is sent back to the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    pub command: String,
+    // Run the tool without asking first, for tools that can't do any harm such as reading notes
+    #[serde(default)]
+    pub auto_approve: bool,
+    // JSON schema of the arguments
+    #[serde(default = "default_parameters")]
+    pub parameters: Value,
+}
+
+impl Tool {
+    /// Description of the tool as expected in the `functions` of a request
+    pub fn function_spec(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "parameters": self.parameters,
+        })
+    }
+}
+
+/// A tool invocation requested by the model while answering a message, kept in the session file for auditing
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ToolCall {
+    pub name: String,
+    // Arguments as JSON, exactly as written by the model
+    pub arguments: String,
+    // What the model said it was about to do, if anything, when asking for the tool
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub plan: String,
+    pub output: String,
+    // Response of the search provider for web searches, as received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_results: Option<Value>,
+    // Usage of the request in which the model asked for the tool
+    pub num_tokens_message: u32,
+    pub num_tokens_response: u32,
+}
+
+/// Output sent to the model in place of that of a tool the user declined to run
+pub const DECLINED_OUTPUT: &str = "The user declined to run this tool.";
+
+/// Tools offered to the model: the commands in the config, and the built-in tools that are set up
+pub fn available_tools(config: &ChatTermConfig) -> Vec<Tool> {
+    let mut tools = config.tools.clone();
+    tools.extend(config.web_search.as_ref().map(|search| search.tool()));
+    tools.extend(
+        config
+            .calculator
+            .as_ref()
+            .map(|calculator| calculator.tool()),
+    );
+    tools.extend(config.files.iter().flat_map(|files| files.tools()));
+    tools
+}
+
+/// Fail if a command in the config has the name of a built-in tool that is set up, since the model couldn't tell
+/// them apart and only one of them would ever run
+pub fn check_tool_names(config: &ChatTermConfig) -> Result<(), String> {
+    let mut built_in: Vec<&str> = Vec::new();
+    if config.web_search.is_some() {
+        built_in.push(WEB_SEARCH_TOOL);
+    }
+    if config.calculator.is_some() {
+        built_in.push(CALCULATOR_TOOL);
+    }
+    if config.files.is_some() {
+        built_in.extend([READ_FILE_TOOL, LIST_DIR_TOOL]);
+    }
+    match config
+        .tools
+        .iter()
+        .find(|tool| built_in.contains(&tool.name.as_str()))
+    {
+        Some(tool) => Err(format!(
+            "The tool {} in the config has the name of a built-in tool, rename it",
+            tool.name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether the user has to approve running a tool the model asked for. Unknown tools fail without running anything.
+pub fn needs_approval(tools: &[Tool], name: &str) -> bool {
+    tools
+        .iter()
+        .any(|tool| tool.name == name && !tool.auto_approve)
+}
+
+// Search the web for the query in the arguments, keeping the response of the provider in the call
+fn run_web_search(config: &ChatTermConfig, call: &mut ToolCall) -> String {
+    let Some(search) = &config.web_search else {
+        return String::from("Error: web search is not set up");
+    };
+    let arguments: Value = serde_json::from_str(&call.arguments).unwrap_or_default();
+    let Some(query) = arguments["query"].as_str() else {
+        return String::from("Error: web_search needs a query");
+    };
+    match search.search(query, &config.network) {
+        Ok((results, raw)) => {
+            call.raw_results = Some(raw);
+            format_results(query, &results)
+        }
+        Err(err) => format!("Error: failed to search the web: {}", err),
+    }
+}
+
+// Compute the expression in the arguments, or run the Python code in the sandbox if the calculator is set up to
+fn run_calculator(config: &ChatTermConfig, call: &ToolCall) -> String {
+    let python = config
+        .calculator
+        .as_ref()
+        .is_some_and(|calculator| calculator.python);
+    let arguments: Value = serde_json::from_str(&call.arguments).unwrap_or_default();
+    if python {
+        let Some(code) = arguments["code"].as_str() else {
+            return String::from("Error: calculate needs code to run");
+        };
+        return match run_sandboxed("python3 -", code, &config.tool_sandbox) {
+            Ok(output) => output,
+            Err(err) => format!("Error: failed to run python3: {}", err),
+        };
+    }
+    let Some(expression) = arguments["expression"].as_str() else {
+        return String::from("Error: calculate needs an expression");
+    };
+    match evaluate(expression) {
+        Ok(value) => format!("{} = {}", expression.trim(), format_number(value)),
+        Err(err) => format!("Error: {}", err),
+    }
+}
+
+// List a directory or read a file at the path in the arguments, within the configured directories
+fn run_files(config: &ChatTermConfig, call: &ToolCall) -> String {
+    let Some(files) = &config.files else {
+        return String::from("Error: reading files is not set up");
+    };
+    let arguments: Value = serde_json::from_str(&call.arguments).unwrap_or_default();
+    let Some(path) = arguments["path"].as_str() else {
+        return format!("Error: {} needs a path", call.name);
+    };
+    let result = if call.name == LIST_DIR_TOOL {
+        files.list_dir(path)
+    } else {
+        files.read_file(path)
+    };
+    result.unwrap_or_else(|err| format!("Error: {}", err))
+}
+
+/// Run the tool the model asked for and fill in its output. Commands get the arguments on stdin and run under the
+/// restrictions of the sandbox policy. Failures are reported in the output, so that the model can react to them.
+pub fn run_tool(config: &ChatTermConfig, call: &mut ToolCall) {
+    if call.name == WEB_SEARCH_TOOL && config.web_search.is_some() {
+        call.output = run_web_search(config, call);
+        return;
+    }
+    if call.name == CALCULATOR_TOOL && config.calculator.is_some() {
+        call.output = run_calculator(config, call);
+        return;
+    }
+    if [READ_FILE_TOOL, LIST_DIR_TOOL].contains(&call.name.as_str()) && config.files.is_some() {
+        call.output = run_files(config, call);
+        return;
+    }
+    let Some(tool) = config.tools.iter().find(|tool| tool.name == call.name) else {
+        call.output = format!("Error: there is no tool called {}", call.name);
+        return;
+    };
+    call.output = match run_sandboxed(&tool.command, &call.arguments, &config.tool_sandbox) {
+        Ok(output) => output,
+        Err(err) => format!("Error: failed to run {}: {}", call.name, err),
+    };
+}
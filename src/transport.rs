@@ -0,0 +1,186 @@
+use std::io::Read;
+use std::sync::Mutex;
+
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+
+use crate::errors::ChatGptError;
+use crate::network::NetworkConfig;
+
+/// A request to the API, made by [`ChatGPTClient`](crate::api::ChatGPTClient) and sent by a [`Transport`]
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+    pub url: String,
+    pub query: Vec<(String, String)>,
+    pub headers: HeaderMap,
+    // JSON body of POST requests, compressed when the Content-Encoding header says so
+    pub body: Option<Vec<u8>>,
+}
+
+/// The answer to an [`HttpRequest`]. The body is read as it arrives, so that streamed answers can be shown as they
+/// are generated.
+pub struct HttpResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Box<dyn Read + Send>,
+}
+
+impl HttpResponse {
+    /// Read the whole body as JSON
+    pub fn json(self) -> Result<Value, serde_json::Error> {
+        serde_json::from_reader(self.body)
+    }
+}
+
+/// How requests reach the API. The default one sends them over HTTP with reqwest; others can answer them in
+/// process, e.g. to test an application without a network connection.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use chatgpt_term::api::ChatGPTClient;
+/// use chatgpt_term::errors::ChatGptError;
+/// use chatgpt_term::transport::{HttpRequest, HttpResponse, Transport};
+/// use chatgpt_term::ChatTermConfig;
+///
+/// // Answers every message with the same text
+/// struct Canned;
+///
+/// impl Transport for Canned {
+///     fn send(&self, _request: HttpRequest) -> Result<HttpResponse, ChatGptError> {
+///         let body = r#"{"choices": [{"message": {"role": "assistant", "content": "Hi!"}}],
+///             "usage": {"prompt_tokens": 10, "completion_tokens": 2}}"#;
+///         Ok(HttpResponse {
+///             status: reqwest::StatusCode::OK,
+///             headers: Default::default(),
+///             body: Box::new(body.as_bytes()),
+///         })
+///     }
+/// }
+///
+/// let client = ChatGPTClient::with_transport(ChatTermConfig::default(), Arc::new(Canned));
+/// let mut session = client.new_session(Vec::new(), 2000);
+/// assert_eq!(session.send_message("Hello").unwrap().response, "Hi!");
+/// ```
+pub trait Transport: Send + Sync {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, ChatGptError>;
+}
+
+/// Sends requests over HTTP with a blocking reqwest client, going through the proxy of the network settings
+pub struct ReqwestTransport {
+    // Or why it couldn't be set up from the network settings
+    client: Result<Client, String>,
+}
+
+impl ReqwestTransport {
+    pub fn new(network: &NetworkConfig) -> Self {
+        let client = network
+            .client_builder()
+            .and_then(|builder| builder.build().map_err(|err| err.to_string()));
+        Self { client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, ChatGptError> {
+        let client = self.client.as_ref().map_err(|err| {
+            ChatGptError::Config(format!("Error in the network settings: {}", err))
+        })?;
+        let mut builder = client
+            .request(request.method, request.url)
+            .query(&request.query)
+            .headers(request.headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        let response = builder
+            .send()
+            .map_err(|err| ChatGptError::Network(err.to_string()))?;
+        Ok(HttpResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: Box::new(response),
+        })
+    }
+}
+
+/// Answers requests to the chat completions API in process by repeating the last message back, streamed word by
+/// word when asked to, for testing without a network or an API key. The requests it was sent are kept to be
+/// checked.
+#[derive(Default)]
+pub struct MockTransport {
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The answer given to a message
+    pub fn answer(message: &str) -> String {
+        format!("You said: {}", message)
+    }
+
+    /// The requests sent so far, oldest first
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    // A response with a body of the given type
+    fn response(content_type: &'static str, body: String) -> HttpResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        HttpResponse {
+            status: StatusCode::OK,
+            headers,
+            body: Box::new(std::io::Cursor::new(body.into_bytes())),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, ChatGptError> {
+        self.requests.lock().unwrap().push(request.clone());
+        if request.method == Method::GET {
+            let models = json!({"data": [{"id": "mock-model"}]});
+            return Ok(Self::response("application/json", models.to_string()));
+        }
+        let body: Value = serde_json::from_slice(request.body.as_deref().unwrap_or_default())?;
+        let message = body["messages"]
+            .as_array()
+            .and_then(|messages| messages.last())
+            .and_then(|message| message["content"].as_str())
+            .unwrap_or_default();
+        let answer = Self::answer(message);
+        if body["stream"].as_bool() == Some(true) {
+            let mut events = String::new();
+            for (index, word) in answer.split(' ').enumerate() {
+                let token = if index == 0 {
+                    word.to_string()
+                } else {
+                    format!(" {}", word)
+                };
+                let chunk = json!({"choices": [{"delta": {"content": token}}]});
+                events.push_str(&format!("data: {}\n\n", chunk));
+            }
+            let last = json!({
+                "model": body["model"],
+                "choices": [{"delta": {}, "finish_reason": "stop"}],
+            });
+            events.push_str(&format!("data: {}\n\ndata: [DONE]\n\n", last));
+            return Ok(Self::response("text/event-stream", events));
+        }
+        let completion = json!({
+            "model": body["model"],
+            "choices": [{
+                "message": {"role": "assistant", "content": answer},
+                "finish_reason": "stop",
+            }],
+        });
+        Ok(Self::response("application/json", completion.to_string()))
+    }
+}
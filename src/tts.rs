@@ -0,0 +1,152 @@
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often the playback thread checks on the text-to-speech process
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Split text into sentences so that playback can be paused and tracked sentence by sentence
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if c != '\n' {
+            current.push(c);
+        }
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            if !current.trim().is_empty() {
+                sentences.push(current.trim().to_string());
+            }
+            current.clear();
+        }
+    }
+    if !current.trim().is_empty() {
+        sentences.push(current.trim().to_string());
+    }
+    sentences
+}
+
+struct Playback {
+    sentences: Vec<String>,
+    current: usize,
+    paused: bool,
+    stopped: bool,
+    // Text-to-speech process for the current sentence
+    child: Option<Child>,
+}
+
+impl Playback {
+    fn kill_child(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            child.kill().ok();
+        }
+    }
+}
+
+/// Reads text aloud one sentence at a time using an external text-to-speech command (e.g. `espeak` or `say`)
+pub struct ReadAloud {
+    playback: Arc<Mutex<Playback>>,
+}
+
+impl ReadAloud {
+    /// Start reading text aloud in the background. The sentence being read is appended as the last argument
+    /// of the command.
+    pub fn start(command: &str, text: &str) -> Self {
+        let playback = Arc::new(Mutex::new(Playback {
+            sentences: split_sentences(text),
+            current: 0,
+            paused: false,
+            stopped: false,
+            child: None,
+        }));
+        let mut args: Vec<String> = command.split_whitespace().map(String::from).collect();
+        let program = if args.is_empty() {
+            String::new()
+        } else {
+            args.remove(0)
+        };
+
+        let shared = playback.clone();
+        thread::spawn(move || loop {
+            {
+                let mut playback = shared.lock().unwrap();
+                if playback.stopped || playback.current >= playback.sentences.len() {
+                    // Reap the process killed when stopping
+                    if let Some(mut child) = playback.child.take() {
+                        child.wait().ok();
+                    }
+                    return;
+                }
+                if !playback.paused && playback.child.is_none() {
+                    let child = Command::new(&program)
+                        .args(&args)
+                        .arg(&playback.sentences[playback.current])
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn();
+                    match child {
+                        Ok(child) => playback.child = Some(child),
+                        Err(_) => {
+                            playback.stopped = true;
+                            return;
+                        }
+                    }
+                }
+                let finished = match playback.child.as_mut() {
+                    Some(child) => !matches!(child.try_wait(), Ok(None)),
+                    None => false,
+                };
+                if finished {
+                    playback.child = None;
+                    // A sentence interrupted by pausing is read again from the start when resuming
+                    if !playback.paused {
+                        playback.current += 1;
+                    }
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        });
+        Self { playback }
+    }
+
+    /// Pause playback, or resume it if it is paused
+    pub fn toggle_pause(&self) {
+        let mut playback = self.playback.lock().unwrap();
+        playback.paused = !playback.paused;
+        if playback.paused {
+            playback.kill_child();
+        }
+    }
+
+    /// Stop playback
+    pub fn stop(&self) {
+        let mut playback = self.playback.lock().unwrap();
+        playback.stopped = true;
+        playback.kill_child();
+    }
+
+    /// Whether playback is paused
+    pub fn is_paused(&self) -> bool {
+        self.playback.lock().unwrap().paused
+    }
+
+    /// Whether playback has finished or was stopped
+    pub fn is_finished(&self) -> bool {
+        let playback = self.playback.lock().unwrap();
+        playback.stopped || playback.current >= playback.sentences.len()
+    }
+
+    /// Number of the sentence being read and the total number of sentences
+    pub fn progress(&self) -> (usize, usize) {
+        let playback = self.playback.lock().unwrap();
+        (playback.current + 1, playback.sentences.len())
+    }
+}
+
+impl Drop for ReadAloud {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
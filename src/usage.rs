@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{Datelike, Duration, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// Tokens used and their estimated cost over some period
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub requests: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // Estimated cost in US dollars, counting only requests to models with a known price
+    pub cost: f64,
+}
+
+impl Usage {
+    /// Total of the prompt and completion tokens
+    pub fn tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    fn add(&mut self, other: &Usage) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// Usage across all sessions, persisted per day next to the config file
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct UsageStore {
+    #[serde(skip)]
+    path: PathBuf,
+    // Usage keyed by date in YYYY-MM-DD format
+    days: BTreeMap<NaiveDate, Usage>,
+}
+
+impl UsageStore {
+    /// Default location of the usage store, in the same directory as the config file
+    pub fn default_path() -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let config_path = confy::get_configuration_file_path("chatgpt-term", None)?;
+        Ok(config_path.with_file_name("usage.json"))
+    }
+
+    /// Load the usage store, starting an empty one if it doesn't exist yet
+    pub fn load(path: PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut store: UsageStore = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => UsageStore::default(),
+            Err(err) => return Err(err.into()),
+        };
+        store.path = path;
+        Ok(store)
+    }
+
+    /// Add a request to today's usage and save the store
+    pub fn record(
+        &mut self,
+        prompt_tokens: u32,
+        completion_tokens: u32,
+        cost: Option<f64>,
+    ) -> std::io::Result<()> {
+        self.days
+            .entry(Local::now().date_naive())
+            .or_default()
+            .add(&Usage {
+                requests: 1,
+                prompt_tokens: prompt_tokens as u64,
+                completion_tokens: completion_tokens as u64,
+                cost: cost.unwrap_or(0.0),
+            });
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self)?)
+    }
+
+    // Total usage on and after the given date
+    fn since(&self, date: NaiveDate) -> Usage {
+        let mut total = Usage::default();
+        for usage in self.days.range(date..).map(|(_, usage)| usage) {
+            total.add(usage);
+        }
+        total
+    }
+
+    /// Usage today
+    pub fn today(&self) -> Usage {
+        self.since(Local::now().date_naive())
+    }
+
+    /// Usage in the current month
+    pub fn this_month(&self) -> Usage {
+        self.since(Local::now().date_naive().with_day(1).unwrap())
+    }
+
+    /// Usage since the store was started
+    pub fn all_time(&self) -> Usage {
+        self.since(NaiveDate::MIN)
+    }
+
+    /// Usage in the current week, starting on Monday
+    pub fn this_week(&self) -> Usage {
+        let today = Local::now().date_naive();
+        self.since(today - Duration::days(today.weekday().num_days_from_monday() as i64))
+    }
+}
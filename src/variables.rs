@@ -0,0 +1,67 @@
+use chrono::Local;
+
+use crate::ChatTermConfig;
+
+// Name of the user for {{user_name}}: the one in the config, or else the login name
+fn user_name(config: &ChatTermConfig) -> Option<String> {
+    config
+        .user_name
+        .clone()
+        .or_else(|| std::env::var("USER").ok())
+        .or_else(|| std::env::var("USERNAME").ok())
+        .filter(|name| !name.trim().is_empty())
+}
+
+// Value of a variable, or None if there is no variable with this name. Environment variables are only looked up
+// with `with_env`.
+fn resolve(name: &str, config: &ChatTermConfig, with_env: bool) -> Option<String> {
+    if let Some(value) = config.prompt_variables.get(name) {
+        return Some(value.clone());
+    }
+    if let Some(variable) = name.strip_prefix("env:") {
+        return with_env.then(|| std::env::var(variable).unwrap_or_default());
+    }
+    let now = Local::now();
+    match name {
+        "today" => Some(now.format("%Y-%m-%d").to_string()),
+        "weekday" => Some(now.format("%A").to_string()),
+        "time" => Some(now.format("%H:%M").to_string()),
+        "os" => Some(std::env::consts::OS.to_string()),
+        "user_name" => user_name(config),
+        _ => None,
+    }
+}
+
+/// Replace the `{{name}}` variables in a prompt with their values: the built-in ones such as `{{today}}`, `{{os}}`
+/// and `{{user_name}}`, and those set in `prompt_variables` in the config. Unknown variables are left as they are,
+/// and so are environment variables, since the prompt may come from an imported persona that would otherwise send
+/// secrets such as `{{env:OPENAI_API_KEY}}` to the model.
+pub fn expand_variables(prompt: &str, config: &ChatTermConfig) -> String {
+    expand(prompt, config, false)
+}
+
+/// Replace the variables in a value written in the config file itself, such as the initial prompt or an extra
+/// header, like [`expand_variables`] does, and environment variables as `{{env:NAME}}` too
+pub fn expand_config_variables(value: &str, config: &ChatTermConfig) -> String {
+    expand(value, config, true)
+}
+
+fn expand(prompt: &str, config: &ChatTermConfig, with_env: bool) -> String {
+    let mut expanded = String::new();
+    let mut rest = prompt;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + length];
+        let end = start + 2 + length + 2;
+        expanded.push_str(&rest[..start]);
+        match resolve(name.trim(), config, with_env) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
@@ -0,0 +1,59 @@
+use crate::tokens::count_tokens;
+
+// Messages shorter than this are left alone, normalizing only pays off for large pastes
+const MIN_CHARS: usize = 1000;
+
+/// A message with redundant whitespace collapsed, and how much smaller it got
+pub struct Normalized {
+    pub text: String,
+    pub saved_chars: usize,
+    // Counted with the tokenizer of the model the message is sent to
+    pub saved_tokens: u32,
+}
+
+// Collapse runs of spaces and tabs after the indentation of a line, which is kept since it can carry meaning
+fn collapse_spaces(line: &str) -> String {
+    let content = line.trim_start();
+    let mut collapsed = line[..line.len() - content.len()].to_string();
+    let mut last_was_space = false;
+    for c in content.chars() {
+        let is_space = c == ' ' || c == '\t';
+        if !(is_space && last_was_space) {
+            collapsed.push(if is_space { ' ' } else { c });
+        }
+        last_was_space = is_space;
+    }
+    collapsed
+}
+
+/// Remove trailing whitespace, collapse runs of blank lines into one and runs of spaces within lines into one, except
+/// inside fenced code blocks. None if the message is too short to bother or nothing changed.
+pub fn normalize_whitespace(text: &str, model: &str) -> Option<Normalized> {
+    if text.chars().count() < MIN_CHARS {
+        return None;
+    }
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.trim_start().starts_with("```") {
+            in_fence = !in_fence;
+        } else if !in_fence && line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(if in_fence {
+            line.to_string()
+        } else {
+            collapse_spaces(line)
+        });
+    }
+    let normalized = lines.join("\n").trim_end().to_string();
+    if normalized == text {
+        return None;
+    }
+    Some(Normalized {
+        saved_chars: text.chars().count() - normalized.chars().count(),
+        saved_tokens: count_tokens(model, text).saturating_sub(count_tokens(model, &normalized)),
+        text: normalized,
+    })
+}